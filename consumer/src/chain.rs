@@ -0,0 +1,220 @@
+use sha2::{Digest, Sha256};
+use tokio_postgres::Client;
+use tracing::info;
+
+pub type Hash = [u8; 32];
+
+/// Session advisory lock held by the consumer extending the chain ("TRADECHN").
+const WRITER_LOCK: i64 = i64::from_le_bytes(*b"TRADECHN");
+
+/// `chain_version` of newly chained rows. Version 1 (stored as NULL by older
+/// consumers) predates `gap_before`; version 2 covers it too.
+pub const RECORD_VERSION: i16 = 2;
+
+/// Canonical bytes of a stored trade row: its column values as a JSON array in a
+/// fixed order, so the verifier can rebuild them from the table alone. The row's
+/// `chain_version` picks the columns, so rows chained before `gap_before` existed
+/// still verify. `None` for a version this build doesn't know.
+#[allow(clippy::too_many_arguments)]
+pub fn record_bytes(
+    version: i16, ts_ms: i64, symbol: &str, price_u: i64, qty_u: i64, side: &str, vwap_u: Option<i64>,
+    bid_at: Option<i64>, ask_at: Option<i64>, trade_id: Option<i64>, side_inferred: bool, raw_json: Option<&str>, gap_before: bool,
+) -> Option<Vec<u8>> {
+    let record = match version {
+        1 => serde_json::json!([ts_ms, symbol, price_u, qty_u, side, vwap_u, bid_at, ask_at, trade_id, side_inferred, raw_json]),
+        2 => serde_json::json!([ts_ms, symbol, price_u, qty_u, side, vwap_u, bid_at, ask_at, trade_id, side_inferred, raw_json, gap_before]),
+        _ => return None,
+    };
+    Some(record.to_string().into_bytes())
+}
+
+/// `sha256(prev_hash || record)`.
+pub fn link_hash(prev: &Hash, record: &[u8]) -> Hash {
+    let mut h = Sha256::new();
+    h.update(prev);
+    h.update(record);
+    h.finalize().into()
+}
+
+pub fn hex(hash: &[u8]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Tail of the trade hash chain. Each stored trade gets the next `chain_seq`, the
+/// previous trade's hash as `chain_prev` and `chain_hash = sha256(chain_prev || row)`.
+/// The first trade ever chained links to 32 zero bytes. Only one consumer may extend
+/// the chain: `load` takes a session advisory lock, so a second chaining consumer
+/// waits at startup until the first one's connection closes. The unique index on
+/// `chain_seq` rejects a forked chain should two writers ever overlap.
+pub struct HashChain {
+    seq: i64,
+    prev: Hash,
+}
+
+impl HashChain {
+    /// Takes the writer lock, then resumes from the newest chained row, so restarts
+    /// extend the same chain.
+    pub async fn load(pg: &Client) -> Result<Self, tokio_postgres::Error> {
+        let locked: bool = pg.query_one("SELECT pg_try_advisory_lock($1)", &[&WRITER_LOCK]).await?.get(0);
+        if !locked {
+            info!("another consumer is extending the trade hash chain, waiting for its lock");
+            pg.execute("SELECT pg_advisory_lock($1)", &[&WRITER_LOCK]).await?;
+        }
+        let row = pg.query_opt("SELECT chain_seq, chain_hash FROM trades WHERE chain_seq IS NOT NULL ORDER BY chain_seq DESC LIMIT 1", &[]).await?;
+        Ok(match row {
+            Some(r) => {
+                let hash: Vec<u8> = r.get(1);
+                Self { seq: r.get(0), prev: hash.try_into().unwrap_or([0; 32]) }
+            }
+            None => Self { seq: 0, prev: [0; 32] },
+        })
+    }
+
+    /// The `(chain_seq, chain_prev, chain_hash)` the next record would get. Call
+    /// `advance` only once the record is stored, so a failed insert leaves no gap.
+    pub fn next(&self, record: &[u8]) -> (i64, Hash, Hash) {
+        (self.seq + 1, self.prev, link_hash(&self.prev, record))
+    }
+
+    pub fn advance(&mut self, seq: i64, hash: Hash) {
+        self.seq = seq;
+        self.prev = hash;
+    }
+}
+
+/// Outcome of walking the chain.
+pub struct Verification {
+    pub checked: u64,
+    /// Hash of the last verified row; record it elsewhere to also detect truncation.
+    pub head: Option<Vec<u8>>,
+    /// First broken row and why, if any. Rows after it are not trusted.
+    pub broken: Option<(i64, &'static str)>,
+}
+
+/// A chained trade row as `verify` reads it back.
+pub struct ChainedRow {
+    pub seq: i64,
+    pub prev: Vec<u8>,
+    pub hash: Vec<u8>,
+    /// `record_bytes` of the row's columns at its `chain_version`; `None` for a version
+    /// this build doesn't know.
+    pub record: Option<Vec<u8>>,
+}
+
+/// Walks chained trades in `chain_seq` order, recomputing each hash from the row's
+/// columns and checking it links to the row before. Retention deletes the oldest rows
+/// (a prefix of the chain), so the walk starts at the oldest one left; its own hash is still checked against
+/// its stored `chain_prev`. A row edited, deleted or inserted mid-chain breaks it.
+pub async fn verify(pg: &Client) -> Result<Verification, tokio_postgres::Error> {
+    let rows = pg.query(
+        "SELECT chain_seq, chain_prev, chain_hash, ts_ms, symbol, price_u, qty_u, side, vwap_u, bid_at, ask_at, trade_id, side_inferred, raw_json, \
+         COALESCE(chain_version, 1::smallint), gap_before \
+         FROM trades WHERE chain_seq IS NOT NULL ORDER BY chain_seq", &[]).await?;
+    Ok(verify_rows(rows.iter().map(|r| ChainedRow {
+        seq: r.get(0),
+        prev: r.get(1),
+        hash: r.get(2),
+        record: record_bytes(r.get(14), r.get(3), r.get(4), r.get(5), r.get(6), r.get(7), r.get(8), r.get(9), r.get(10), r.get(11), r.get(12), r.get(13), r.get(15)),
+    })))
+}
+
+/// The checks behind `verify`, over rows already in `chain_seq` order.
+pub fn verify_rows(rows: impl IntoIterator<Item = ChainedRow>) -> Verification {
+    let mut checked = 0;
+    let mut last: Option<(i64, Vec<u8>)> = None;
+    for r in rows {
+        if let Some((last_seq, last_hash)) = &last {
+            if r.seq != last_seq + 1 {
+                return Verification { checked, head: last.map(|l| l.1), broken: Some((r.seq, "sequence gap")) };
+            }
+            if r.prev != *last_hash {
+                return Verification { checked, head: last.map(|l| l.1), broken: Some((r.seq, "chain_prev does not match previous chain_hash")) };
+            }
+        }
+        let Some(record) = r.record else {
+            return Verification { checked, head: last.map(|l| l.1), broken: Some((r.seq, "unknown chain_version")) };
+        };
+        let Ok(prev) = Hash::try_from(r.prev.as_slice()) else {
+            return Verification { checked, head: last.map(|l| l.1), broken: Some((r.seq, "malformed chain_prev")) };
+        };
+        if link_hash(&prev, &record).as_slice() != r.hash.as_slice() {
+            return Verification { checked, head: last.map(|l| l.1), broken: Some((r.seq, "row does not match chain_hash")) };
+        }
+        checked += 1;
+        last = Some((r.seq, r.hash));
+    }
+    Verification { checked, head: last.map(|l| l.1), broken: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(version: i16, gap_before: bool) -> Option<Vec<u8>> {
+        record_bytes(version, 1_700_000_000_000, "SOLUSD", 150_000_000, 2_000_000, "buy", None, Some(149_990_000), None, Some(42), false, None, gap_before)
+    }
+
+    #[test]
+    fn version_1_record_is_unchanged() {
+        let v1 = record(1, true).unwrap();
+        assert_eq!(String::from_utf8(v1).unwrap(), r#"[1700000000000,"SOLUSD",150000000,2000000,"buy",null,149990000,null,42,false,null]"#);
+        assert_eq!(record(1, false), record(1, true), "version 1 predates gap_before");
+    }
+
+    #[test]
+    fn version_2_record_covers_gap_before() {
+        let (gapped, clean) = (record(2, true).unwrap(), record(2, false).unwrap());
+        assert_ne!(gapped, clean);
+        assert_ne!(link_hash(&[0; 32], &gapped), link_hash(&[0; 32], &clean));
+        // Relabelling a row as version 1 changes its bytes, so it no longer matches its hash
+        assert_ne!(record(1, true).unwrap(), gapped);
+        assert_eq!(record(3, false), None);
+    }
+
+    /// Five trades chained the way the consumer stores them.
+    fn chained() -> Vec<ChainedRow> {
+        let mut chain = HashChain { seq: 0, prev: [0; 32] };
+        (0..5).map(|i| {
+            let record = record_bytes(RECORD_VERSION, 1_700_000_000_000 + i, "SOLUSD", 150_000_000 + i, 1_000_000, "buy", None, None, None, Some(i), false, None, false).unwrap();
+            let (seq, prev, hash) = chain.next(&record);
+            chain.advance(seq, hash);
+            ChainedRow { seq, prev: prev.to_vec(), hash: hash.to_vec(), record: Some(record) }
+        }).collect()
+    }
+
+    #[test]
+    fn intact_chain_verifies() {
+        let rows = chained();
+        let head = rows.last().unwrap().hash.clone();
+        let v = verify_rows(rows);
+        assert_eq!((v.checked, v.broken), (5, None));
+        assert_eq!(v.head, Some(head));
+        // Retention trims a prefix; the rest still verifies
+        let v = verify_rows(chained().into_iter().skip(2));
+        assert_eq!((v.checked, v.broken), (3, None));
+    }
+
+    #[test]
+    fn tampering_breaks_the_chain_at_that_row() {
+        // An edited price in row 3: its columns no longer hash to its chain_hash
+        let mut rows = chained();
+        rows[2].record = record_bytes(RECORD_VERSION, 1_700_000_000_002, "SOLUSD", 999_000_000, 1_000_000, "buy", None, None, None, Some(2), false, None, false);
+        let head_before = rows[1].hash.clone();
+        let v = verify_rows(rows);
+        assert_eq!((v.checked, v.broken), (2, Some((3, "row does not match chain_hash"))));
+        assert_eq!(v.head, Some(head_before));
+
+        // Re-hashing the edited row to cover it up breaks the link from row 4
+        let mut rows = chained();
+        let record = record_bytes(RECORD_VERSION, 1_700_000_000_002, "SOLUSD", 999_000_000, 1_000_000, "buy", None, None, None, Some(2), false, None, false).unwrap();
+        let prev: Hash = rows[2].prev.clone().try_into().unwrap();
+        rows[2].hash = link_hash(&prev, &record).to_vec();
+        rows[2].record = Some(record);
+        assert_eq!(verify_rows(rows).broken, Some((4, "chain_prev does not match previous chain_hash")));
+
+        // A deleted middle row leaves a sequence gap
+        let mut rows = chained();
+        rows.remove(2);
+        assert_eq!(verify_rows(rows).broken, Some((4, "sequence gap")));
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Comment line sent on idle SSE streams so proxies and clients don't time them out.
+const SSE_HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// Stored trades as served over HTTP: the most recent per symbol, by `ts_ms`, plus a
+/// live feed of every trade for SSE subscribers.
+#[derive(Clone)]
+pub struct LastTrades {
+    latest: Arc<RwLock<HashMap<String, serde_json::Value>>>,
+    live: broadcast::Sender<serde_json::Value>,
+}
+
+impl Default for LastTrades {
+    fn default() -> Self {
+        Self { latest: Arc::default(), live: broadcast::channel(1024).0 }
+    }
+}
+
+impl LastTrades {
+    /// Records a stored trade and pushes it to stream subscribers. The latest-trade
+    /// entry is kept unless the symbol already has a later one (out-of-order delivery
+    /// or a replayed backlog must not move the cache backwards).
+    pub fn update(&self, symbol: &str, ts_ms: i64, trade: serde_json::Value) {
+        let _ = self.live.send(trade.clone());
+        let mut map = self.latest.write().unwrap();
+        let newer = map.get(symbol).and_then(|t| t.get("ts_ms")).and_then(|t| t.as_i64()).is_none_or(|prev| ts_ms >= prev);
+        if newer {
+            map.insert(symbol.to_string(), trade);
+        }
+    }
+
+    fn get(&self, symbol: &str) -> Option<serde_json::Value> {
+        self.latest.read().unwrap().get(symbol).cloned()
+    }
+}
+
+/// Serves on `addr` until the process exits:
+/// - `GET /v1/last/{symbol}`: 200 with the trade JSON, 404 if none is stored yet
+/// - `GET /v1/trades/stream[?symbol=S]`: Server-Sent Events, one `data:` line per
+///   stored trade (optionally one symbol's), with a comment heartbeat when idle
+///
+/// Other paths are 404. This is a status endpoint, not a general HTTP server.
+pub async fn serve(addr: &str, cache: LastTrades) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("🌐 Serving trades on http://{} (/v1/last/{{symbol}}, /v1/trades/stream)", listener.local_addr()?);
+    accept(listener, cache).await
+}
+
+async fn accept(listener: TcpListener, cache: LastTrades) -> std::io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &cache).await {
+                warn!(?e, "trade HTTP request failed");
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, cache: &LastTrades) -> std::io::Result<()> {
+    let mut buf = vec![0u8; 4096];
+    let mut len = 0;
+    while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") && len < buf.len() {
+        let n = stream.read(&mut buf[len..]).await?;
+        if n == 0 { break; }
+        len += n;
+    }
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    let (method, target) = (parts.next(), parts.next());
+    let (path, query) = target.map_or(("", ""), |t| t.split_once('?').unwrap_or((t, "")));
+    let (status, body) = match (method, path) {
+        (Some("GET"), "/v1/trades/stream") => {
+            let symbol = query.split('&').find_map(|kv| kv.strip_prefix("symbol=")).map(str::to_uppercase);
+            return stream_trades(stream, cache, symbol).await;
+        }
+        (Some("GET"), path) => match path.strip_prefix("/v1/last/").map(|s| s.to_uppercase()) {
+            Some(symbol) => match cache.get(&symbol) {
+                Some(trade) => ("200 OK", trade.to_string()),
+                None => ("404 Not Found", serde_json::json!({"error": "no trades for symbol", "symbol": symbol}).to_string()),
+            },
+            None => ("404 Not Found", serde_json::json!({"error": "not found"}).to_string()),
+        },
+        (Some(_), _) if target.is_some() => ("405 Method Not Allowed", serde_json::json!({"error": "method not allowed"}).to_string()),
+        _ => ("400 Bad Request", serde_json::json!({"error": "bad request"}).to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Streams trades stored from now on until the client disconnects. A client too slow
+/// to keep up skips the trades it missed and is told how many with a comment line.
+async fn stream_trades(mut stream: TcpStream, cache: &LastTrades, symbol: Option<String>) -> std::io::Result<()> {
+    let mut rx = cache.live.subscribe();
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n").await?;
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + SSE_HEARTBEAT, SSE_HEARTBEAT);
+    let mut probe = [0u8; 64];
+    loop {
+        let event = tokio::select! {
+            trade = rx.recv() => match trade {
+                Ok(trade) => {
+                    let wanted = symbol.as_deref().is_none_or(|s| trade.get("symbol").and_then(|x| x.as_str()) == Some(s));
+                    if !wanted { continue; }
+                    format!("data: {}\n\n", trade)
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => format!(": skipped {} trades\n\n", n),
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            _ = heartbeat.tick() => ": heartbeat\n\n".to_string(),
+            // Clients never send after the request, so a read returning means they hung up
+            read = stream.read(&mut probe) => match read {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => continue,
+            },
+        };
+        if stream.write_all(event.as_bytes()).await.is_err() {
+            return Ok(()); // client went away
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    async fn start(cache: LastTrades) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept(listener, cache));
+        addr
+    }
+
+    /// Sends a GET and returns the status line and body.
+    async fn get(addr: SocketAddr, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(format!("GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    fn trade(symbol: &str, ts_ms: i64, price: &str) -> serde_json::Value {
+        serde_json::json!({"symbol": symbol, "ts_ms": ts_ms, "price": price})
+    }
+
+    #[tokio::test]
+    async fn last_trade_is_the_latest_per_symbol() {
+        let cache = LastTrades::default();
+        cache.update("SOLUSD", 1_000, trade("SOLUSD", 1_000, "145.10"));
+        cache.update("SOLUSD", 3_000, trade("SOLUSD", 3_000, "145.30"));
+        cache.update("SOLUSD", 2_000, trade("SOLUSD", 2_000, "145.20")); // late delivery
+        cache.update("BTCUSD", 1_500, trade("BTCUSD", 1_500, "64000.00"));
+        let addr = start(cache).await;
+
+        let (status, body) = get(addr, "/v1/last/SOLUSD").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(), trade("SOLUSD", 3_000, "145.30"));
+
+        let (status, body) = get(addr, "/v1/last/btcusd").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(), trade("BTCUSD", 1_500, "64000.00"));
+    }
+
+    #[tokio::test]
+    async fn unknown_symbol_is_not_found() {
+        let cache = LastTrades::default();
+        cache.update("SOLUSD", 1_000, trade("SOLUSD", 1_000, "145.10"));
+        let addr = start(cache).await;
+
+        let (status, body) = get(addr, "/v1/last/ETHUSD").await;
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({"error": "no trades for symbol", "symbol": "ETHUSD"}));
+    }
+
+    #[tokio::test]
+    async fn stream_delivers_trades_stored_after_connecting() {
+        let cache = LastTrades::default();
+        let addr = start(cache.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /v1/trades/stream?symbol=solusd HTTP/1.1\r\nHost: test\r\n\r\n").await.unwrap();
+
+        // The subscription exists once the response head is written
+        let mut received = String::new();
+        let mut buf = [0u8; 1024];
+        let read_until = |received: &String, marker: &str, count: usize| received.matches(marker).count() >= count;
+        while !read_until(&received, "\r\n\r\n", 1) {
+            let n = stream.read(&mut buf).await.unwrap();
+            received.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        let (head, _) = received.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains("Content-Type: text/event-stream"));
+
+        cache.update("SOLUSD", 1_000, trade("SOLUSD", 1_000, "145.10"));
+        cache.update("BTCUSD", 1_500, trade("BTCUSD", 1_500, "64000.00"));
+        cache.update("SOLUSD", 2_000, trade("SOLUSD", 2_000, "145.20"));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !read_until(&received, "data: ", 2) {
+                let n = stream.read(&mut buf).await.unwrap();
+                received.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+        }).await.expect("two trades streamed");
+
+        let (_, body) = received.split_once("\r\n\r\n").unwrap();
+        let events: Vec<serde_json::Value> = body.split("\n\n")
+            .filter_map(|e| e.strip_prefix("data: "))
+            .map(|d| serde_json::from_str(d).unwrap())
+            .collect();
+        assert_eq!(events, vec![trade("SOLUSD", 1_000, "145.10"), trade("SOLUSD", 2_000, "145.20")]);
+    }
+}
@@ -1,20 +1,607 @@
+mod chain;
+mod last_trade;
+mod migrations;
+mod pg;
+mod rollup;
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+mod sinks;
+
 use anyhow::Result;
+use chain::HashChain;
+use last_trade::LastTrades;
+use pg::Pg;
+use rollup::MinuteRollups;
 #[cfg(feature = "kafka")]
 use rdkafka::{consumer::{Consumer, StreamConsumer}, Message};
 #[cfg(feature = "pulsar")]
 use pulsar::{Consumer as PulsarConsumer, SubType};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
 use tokio_postgres::NoTls;
+use shared::analytics::{OutOfOrderPolicy, TsOrderGuard, VwapWindow};
+use shared::{config, Side, TradeEvent};
+use shared::errors::{self, ErrorCategory};
+#[cfg(any(feature = "kafka", feature = "pulsar"))]
+use sinks::QuoteStore;
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+use sinks::{QuoteSink, Sink};
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncBufReadExt;
 use tracing::{info, warn, error};
 
+/// Per-symbol trailing VWAP windows; `window_ms` is `None` when `VWAP_WINDOW_SECS` is unset.
+struct Vwaps {
+    window_ms: Option<u64>,
+    by_symbol: HashMap<String, VwapWindow>,
+}
+
+/// Transient failures worth retrying on the same client: serialization/deadlock aborts,
+/// connection-class (`08*`) SQLSTATEs, and I/O errors. A disconnected client fails every
+/// retry, so those errors go back to the writer, which reconnects through `Pg`.
+fn is_retryable(e: &tokio_postgres::Error) -> bool {
+    if is_disconnect(e) {
+        return false;
+    }
+    match e.code() {
+        Some(code) => code == &SqlState::T_R_SERIALIZATION_FAILURE
+            || code == &SqlState::T_R_DEADLOCK_DETECTED
+            || code.code().starts_with("08"),
+        None => std::error::Error::source(e).is_some_and(|cause| cause.is::<std::io::Error>()),
+    }
+}
+
+/// The client can't be used again: its connection has closed, or the server is ending
+/// the session (e.g. `pg_terminate_backend` or a shutdown).
+fn is_disconnect(e: &tokio_postgres::Error) -> bool {
+    e.is_closed() || e.code().is_some_and(|code| code == &SqlState::ADMIN_SHUTDOWN || code == &SqlState::CRASH_SHUTDOWN)
+}
+
+/// Errors `with_retry` can tell apart.
+trait Transient: std::fmt::Debug {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for tokio_postgres::Error {
+    fn is_transient(&self) -> bool {
+        is_retryable(self)
+    }
+}
+
+/// Category for a failed Kafka `recv`: broker transport, lookup and auth failures are
+/// `connect`, SSL failures `tls`, and anything else (e.g. a lost offset) `resync`.
+#[cfg(feature = "kafka")]
+fn kafka_recv_category(e: &rdkafka::error::KafkaError) -> ErrorCategory {
+    use rdkafka::types::RDKafkaErrorCode as Code;
+    match e.rdkafka_error_code() {
+        Some(Code::BrokerTransportFailure | Code::AllBrokersDown | Code::Resolve | Code::Authentication) => ErrorCategory::Connect,
+        Some(Code::SSL) => ErrorCategory::Tls,
+        _ => ErrorCategory::Resync,
+    }
+}
+
+/// Runs `op`, retrying transient errors up to `max_retries` times with full-jitter
+/// exponential backoff (50ms base, 2s cap). Fatal errors are returned immediately.
+async fn with_retry<T, E, F, Fut>(max_retries: u32, mut op: F) -> Result<T, E>
+where
+    E: Transient,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < max_retries && e.is_transient() => {
+                let cap_ms = (50u64 << attempt.min(6)).min(2000);
+                let delay = Duration::from_millis(rand::random::<u64>() % (cap_ms + 1));
+                attempt += 1;
+                let total = errors::record(ErrorCategory::Persist);
+                warn!(?e, category = %ErrorCategory::Persist, errors_total = total, attempt, ?delay, "retrying postgres operation");
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Connects to Postgres, retrying with exponential backoff (500ms doubling to 10s)
+/// until `timeout` has elapsed, so the consumer can start before the database is up.
+/// The connection task is spawned before the client is returned.
+async fn connect_with_retry(dsn: &str, timeout: Duration) -> Result<tokio_postgres::Client, tokio_postgres::Error> {
+    let (client, conn) = retry_connect(timeout, || tokio_postgres::connect(dsn, NoTls)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            let total = errors::record(ErrorCategory::Connect);
+            error!(?e, category = %ErrorCategory::Connect, errors_total = total, "pg conn error");
+        }
+    });
+    Ok(client)
+}
+
+/// The retry loop of `connect_with_retry` over any `connect`: the last error is returned
+/// once the next backoff would pass the deadline.
+async fn retry_connect<T, E, F, Fut>(timeout: Duration, mut connect: F) -> Result<T, E>
+where
+    E: std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut delay = Duration::from_millis(500);
+    let mut attempt = 1u32;
+    loop {
+        match connect().await {
+            Ok(connected) => {
+                if attempt > 1 { info!(attempt, "connected to postgres"); }
+                return Ok(connected);
+            }
+            Err(e) if tokio::time::Instant::now() + delay < deadline => {
+                let total = errors::record(ErrorCategory::Connect);
+                warn!(?e, category = %ErrorCategory::Connect, errors_total = total, attempt, ?delay, "postgres not reachable yet, retrying");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(10));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Subscription type from `PULSAR_SUBSCRIPTION_TYPE`: `exclusive` (default), `failover`,
+/// `shared`, or `key_shared`. `key_shared` lets several consumers split the topic while
+/// each symbol's trades (keyed by symbol at the producer) stay on one consumer, in order.
+#[cfg(feature = "pulsar")]
+fn pulsar_sub_type(name: Option<&str>) -> Result<SubType> {
+    Ok(match name.unwrap_or("exclusive").to_ascii_lowercase().as_str() {
+        "exclusive" => SubType::Exclusive,
+        "failover" => SubType::Failover,
+        "shared" => SubType::Shared,
+        "key_shared" => SubType::KeyShared,
+        other => anyhow::bail!("unknown PULSAR_SUBSCRIPTION_TYPE '{}' (expected exclusive, failover, shared or key_shared)", other),
+    })
+}
+
+/// Backoff between consecutive broker receive errors (100ms doubling to 30s), so a
+/// persistently failing broker can't spin the loop. Reset by any successful receive.
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+#[derive(Default)]
+struct RecvBackoff {
+    failures: u32,
+}
+
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+impl RecvBackoff {
+    /// Wait before the next receive after the current run of failures.
+    fn delay(&self) -> Duration {
+        Duration::from_millis(100u64 << self.failures.min(9)).min(Duration::from_secs(30))
+    }
+
+    async fn failed(&mut self) {
+        let delay = self.delay();
+        self.failures += 1;
+        warn!(failures = self.failures, ?delay, "backing off after receive error");
+        tokio::time::sleep(delay).await;
+    }
+
+    fn succeeded(&mut self) {
+        if self.failures > 0 { info!(failures = self.failures, "receiving again after errors"); }
+        self.failures = 0;
+    }
+}
+
+/// Snaps `ts_ms` down to a multiple of `quantum_ms`; a quantum of 0 or 1 keeps it as is.
+fn quantize_ts(ts_ms: i64, quantum_ms: u64) -> i64 {
+    if quantum_ms <= 1 { return ts_ms; }
+    ts_ms - ts_ms.rem_euclid(quantum_ms as i64)
+}
+
+/// `ts_ms` and `raw_json` as written under `TS_QUANTUM_MS`: the timestamp snaps to the
+/// grid, and the verbatim v1 frame, which holds the exact venue timestamp, is dropped.
+fn quantize_for_sink(ts_ms: i64, raw_json: Option<&str>, quantum_ms: u64) -> (i64, Option<&str>) {
+    if quantum_ms <= 1 { return (ts_ms, raw_json); }
+    (quantize_ts(ts_ms, quantum_ms), None)
+}
+
+/// Stored trade plus derived fields, re-published to `OUTPUT_TOPIC` once persisted:
+/// `venue`, `notional_u` (price x qty in micro-dollars) and `latency_ms` (now minus the
+/// stored `ts_ms`). `stored_ts` is the `ts_ms` actually written, after `TRADE_TS_ORDER`
+/// clamping and `TS_QUANTUM_MS`, and replaces the payload's own. Under `TS_QUANTUM_MS`
+/// `latency_ms` snaps to the same grid and `raw_json` is dropped, as in the stored row.
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+fn enriched_payload(v: &serde_json::Value, stored_ts: i64, quantum_ms: u64) -> Vec<u8> {
+    let field = |k: &str| v.get(k).and_then(|x| x.as_i64()).unwrap_or(0);
+    let mut out = v.clone();
+    if let Some(obj) = out.as_object_mut() {
+        let notional = field("price_u").max(0) as u128 * field("qty_u").max(0) as u128 / 1_000_000;
+        obj.insert("venue".into(), "gemini".into());
+        obj.insert("notional_u".into(), (notional.min(i64::MAX as u128) as i64).into());
+        let latency_ms = chrono::Utc::now().timestamp_millis() - stored_ts;
+        obj.insert("latency_ms".into(), quantize_ts(latency_ms, quantum_ms).into());
+        obj.insert("ts_ms".into(), stored_ts.into());
+        if quantum_ms > 1 {
+            obj.remove("raw_json");
+        }
+    }
+    serde_json::to_vec(&out).unwrap_or_default()
+}
+
+/// What `TradeStore::store` did with a payload. Only `Stored` trades may be passed on
+/// downstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StoreOutcome {
+    /// Inserted, with the `ts_ms` as written.
+    Stored { ts_ms: i64 },
+    /// Dropped by the `TRADE_TS_ORDER` policy; nothing was written.
+    Dropped,
+    /// Failed `STRICT_PAYLOADS` validation and went to `dead_letters` instead.
+    DeadLettered,
+}
+
+/// What to publish to `OUTPUT_TOPIC` for a payload `store` has handled: the enriched
+/// trade once it is stored, nothing for one that was dropped or dead-lettered.
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+fn enrichment(v: &serde_json::Value, outcome: StoreOutcome, quantum_ms: u64) -> Option<Vec<u8>> {
+    match outcome {
+        StoreOutcome::Stored { ts_ms } => Some(enriched_payload(v, ts_ms, quantum_ms)),
+        StoreOutcome::Dropped | StoreOutcome::DeadLettered => None,
+    }
+}
+
+const INSERT_TRADE: &str = "INSERT INTO trades (ts_ms, symbol, price_u, qty_u, side, vwap_u, bid_at, ask_at, trade_id, side_inferred, raw_json, chain_seq, chain_prev, chain_hash, gap_before, chain_version) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16)";
+
+/// Retention for `trades`. Chained rows go oldest `chain_seq` first: one is only deleted
+/// once every row chained before it is past the cutoff too, so a trade stamped out of
+/// order can't leave a hole that `--verify-chain` would report as a gap. Chained rows
+/// are kept while no newer trade has been chained, so the chain always has a tail.
+const PRUNE_TRADES: &str = "DELETE FROM trades WHERE ts_ms < $1 AND (chain_seq IS NULL OR chain_seq < \
+    (SELECT min(chain_seq) FROM trades WHERE chain_seq IS NOT NULL AND ts_ms >= $1))";
+
+const INSERT_DEAD_LETTER: &str = "INSERT INTO dead_letters (received_ms, reason, payload) VALUES ($1,$2,$3)";
+
+/// Strict check of a trade payload: the fields ingest always sends must be present with
+/// the right JSON types, and the values must pass the same checks as `TradeEvent::build`
+/// (non-empty symbol, price and quantity positive and storable, known side).
+fn validate_trade(v: &serde_json::Value) -> Result<(), String> {
+    let u64_field = |k: &str| match v.get(k) {
+        None | Some(serde_json::Value::Null) => Err(format!("missing {}", k)),
+        Some(x) => x.as_u64().ok_or_else(|| format!("{} is not a non-negative integer", k)),
+    };
+    let ts_ms = u64_field("ts_ms")?;
+    if ts_ms == 0 { return Err("ts_ms is zero".into()); }
+    let symbol = v.get("symbol").and_then(|x| x.as_str()).ok_or("missing symbol")?;
+    let side = match v.get("side").and_then(|x| x.as_str()).ok_or("missing side")? {
+        "" => None,
+        s => Some(s.parse::<Side>().map_err(|e| e.to_string())?),
+    };
+    TradeEvent::builder(symbol)
+        .ts_ms(ts_ms).price_u(u64_field("price_u")?).qty_u(u64_field("qty_u")?).side(side, false)
+        .build()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// `TRADE_TS_ORDER`: `accept` (default), `clamp` or `drop`.
+fn ts_order_policy(name: Option<&str>) -> Result<OutOfOrderPolicy> {
+    Ok(match name.unwrap_or("accept").to_ascii_lowercase().as_str() {
+        "accept" => OutOfOrderPolicy::Accept,
+        "clamp" => OutOfOrderPolicy::Clamp,
+        "drop" => OutOfOrderPolicy::Drop,
+        other => anyhow::bail!("unknown TRADE_TS_ORDER '{}' (expected accept, clamp or drop)", other),
+    })
+}
+
+/// Writes trades to Postgres through an insert statement prepared once per connection.
+struct TradeStore {
+    db: Pg,
+    /// `db` generation `pg` belongs to.
+    generation: u64,
+    pg: Arc<tokio_postgres::Client>,
+    insert: tokio_postgres::Statement,
+    vwaps: Vwaps,
+    max_retries: u32,
+    /// `TS_QUANTUM_MS`: grid stored timestamps snap to (dropping `raw_json`); VWAP still
+    /// sees raw timestamps.
+    ts_quantum_ms: u64,
+    /// Fed after each successful insert when `LAST_TRADE_HTTP_ADDR` is set.
+    last_trades: Option<LastTrades>,
+    /// `TRADE_TS_ORDER`: per-symbol handling of trades stamped before the previous one.
+    ts_policy: OutOfOrderPolicy,
+    ts_guards: HashMap<String, TsOrderGuard>,
+    /// `TRADE_HASH_CHAIN`: links each stored row to the previous one for tamper evidence.
+    chain: Option<HashChain>,
+    /// `STRICT_PAYLOADS`: dead-letter insert for payloads failing `validate_trade`.
+    dead_letter: Option<tokio_postgres::Statement>,
+    rejected: u64,
+    /// `MINUTE_STATS`: per-symbol per-minute trade count/volume in `minute_stats`.
+    rollups: Option<MinuteRollups>,
+}
+
+impl TradeStore {
+    async fn new(db: &Pg, vwaps: Vwaps, ts_policy: OutOfOrderPolicy) -> Result<Self, tokio_postgres::Error> {
+        let (generation, pg) = db.client().await;
+        let insert = pg.prepare(INSERT_TRADE).await?;
+        let max_retries = config::var("PG_MAX_RETRIES").ok().and_then(|s| s.parse().ok()).unwrap_or(5);
+        let ts_quantum_ms = config::var("TS_QUANTUM_MS").ok().and_then(|s| s.parse().ok()).unwrap_or(0);
+        let chain = match config::var("TRADE_HASH_CHAIN").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")) {
+            true => Some(HashChain::load(&pg).await?),
+            false => None,
+        };
+        let dead_letter = match config::var("STRICT_PAYLOADS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")) {
+            true => Some(pg.prepare(INSERT_DEAD_LETTER).await?),
+            false => None,
+        };
+        let rollups = match config::var("MINUTE_STATS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")) {
+            true => Some(MinuteRollups::new(Arc::clone(&pg), max_retries).await?),
+            false => None,
+        };
+        Ok(Self { db: db.clone(), generation, pg, insert, vwaps, max_retries, ts_quantum_ms, last_trades: None, ts_policy, ts_guards: HashMap::new(), chain, dead_letter, rejected: 0, rollups })
+    }
+
+    /// Swaps in a fresh connection after the current one closed. Statements are prepared
+    /// again, and the hash chain and minute rollups reload from their tables; the chain
+    /// re-takes its writer lock, which ended with the old session.
+    async fn reconnect(&mut self) -> Result<(), tokio_postgres::Error> {
+        let (generation, pg) = self.db.reconnect(self.generation).await?;
+        self.insert = pg.prepare(INSERT_TRADE).await?;
+        if self.dead_letter.is_some() {
+            self.dead_letter = Some(pg.prepare(INSERT_DEAD_LETTER).await?);
+        }
+        if self.chain.is_some() {
+            self.chain = Some(HashChain::load(&pg).await?);
+        }
+        if self.rollups.is_some() {
+            self.rollups = Some(MinuteRollups::new(Arc::clone(&pg), self.max_retries).await?);
+        }
+        (self.generation, self.pg) = (generation, pg);
+        Ok(())
+    }
+
+    /// Inserts one trade payload, along with the symbol's trailing VWAP when enabled.
+    /// Transient errors are retried, and a closed connection is replaced once; an `Err`
+    /// means the trade was not stored. A trade
+    /// dropped by the `TRADE_TS_ORDER` policy stores nothing and is `Dropped`; one
+    /// rejected by `STRICT_PAYLOADS` goes to `dead_letters` and is `DeadLettered`.
+    async fn store(&mut self, v: &serde_json::Value) -> Result<StoreOutcome, tokio_postgres::Error> {
+        if self.dead_letter.is_some() {
+            if let Err(reason) = validate_trade(v) {
+                let total = errors::record(ErrorCategory::Parse);
+                warn!(category = %ErrorCategory::Parse, errors_total = total, reason, "dead-lettering invalid trade payload");
+                self.reject(&v.to_string(), &reason).await?;
+                return Ok(StoreOutcome::DeadLettered);
+            }
+        }
+        let ts = v.get("ts_ms").and_then(|x| x.as_i64()).unwrap_or(0);
+        let symbol = v.get("symbol").and_then(|x| x.as_str()).unwrap_or("");
+        // Settle out-of-order timestamps before anything time-windowed sees them
+        let guard = self.ts_guards.entry(symbol.to_string()).or_insert_with(|| TsOrderGuard::new(self.ts_policy));
+        let seen = guard.out_of_order;
+        let ts = guard.check(ts.max(0) as u64).map(|t| t as i64);
+        if guard.out_of_order > seen && guard.out_of_order.is_power_of_two() {
+            info!(symbol, count = guard.out_of_order, policy = ?self.ts_policy, "out-of-order trade timestamps so far");
+        }
+        let Some(ts) = ts else { return Ok(StoreOutcome::Dropped) };
+        let price = v.get("price_u").and_then(|x| x.as_i64()).unwrap_or(0);
+        let qty = v.get("qty_u").and_then(|x| x.as_i64()).unwrap_or(0);
+        let side = v.get("side").and_then(|x| x.as_str()).unwrap_or("");
+        let bid_at = v.get("bid_at").and_then(|x| x.as_i64());
+        let ask_at = v.get("ask_at").and_then(|x| x.as_i64());
+        let trade_id = v.get("trade_id").and_then(|x| x.as_i64());
+        let side_inferred = v.get("side_inferred").and_then(|x| x.as_bool()).unwrap_or(false);
+        let raw_json = v.get("raw_json").and_then(|x| x.as_str());
+        let gap_before = v.get("gap_before").and_then(|x| x.as_bool()).unwrap_or(false);
+        let vwaps = &mut self.vwaps;
+        let vwap = vwaps.window_ms.and_then(|window_ms| {
+            let w = vwaps.by_symbol.entry(symbol.to_string()).or_insert_with(|| VwapWindow::new(window_ms));
+            w.push(ts.max(0) as u64, price.max(0) as u64, qty.max(0) as u64);
+            w.vwap_u().map(|x| x as i64)
+        });
+        let (stored_ts, raw_json) = quantize_for_sink(ts, raw_json, self.ts_quantum_ms);
+        let mut reconnected = false;
+        let link = loop {
+            // Linked per attempt: a reconnect reloads the chain head
+            let link = self.chain.as_ref().map(|c| c.next(&chain::record_bytes(
+                chain::RECORD_VERSION, stored_ts, symbol, price, qty, side, vwap, bid_at, ask_at, trade_id, side_inferred, raw_json, gap_before,
+            ).expect("current record version")));
+            let (chain_seq, chain_prev, chain_hash, chain_version) = match &link {
+                Some((seq, prev, hash)) => (Some(*seq), Some(prev.as_slice()), Some(hash.as_slice()), Some(chain::RECORD_VERSION)),
+                None => (None, None, None, None),
+            };
+            let params: [&(dyn ToSql + Sync); 16] = [&stored_ts, &symbol, &price, &qty, &side, &vwap, &bid_at, &ask_at, &trade_id, &side_inferred, &raw_json, &chain_seq, &chain_prev, &chain_hash, &gap_before, &chain_version];
+            match with_retry(self.max_retries, || self.pg.execute(&self.insert, &params)).await {
+                Ok(_) => break link,
+                Err(e) if is_disconnect(&e) && !reconnected => {
+                    let total = errors::record(ErrorCategory::Connect);
+                    warn!(?e, category = %ErrorCategory::Connect, errors_total = total, "postgres connection closed, reconnecting");
+                    self.reconnect().await?;
+                    reconnected = true;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        if let (Some(chain), Some((seq, _, hash))) = (&mut self.chain, link) {
+            chain.advance(seq, hash);
+        }
+        // The trade is stored by now, so a failed rollup only costs the activity count
+        if let Some(rollups) = &mut self.rollups {
+            if let Err(e) = rollups.add(symbol, ts, qty).await {
+                let total = errors::record(ErrorCategory::Persist);
+                warn!(?e, category = %ErrorCategory::Persist, errors_total = total, symbol, "failed to update minute_stats");
+            }
+        }
+        if let Some(last) = &self.last_trades {
+            last.update(symbol, stored_ts, serde_json::json!({
+                "ts_ms": stored_ts, "symbol": symbol, "price_u": price, "qty_u": qty, "side": side, "trade_id": trade_id, "vwap_u": vwap,
+            }));
+        }
+        Ok(StoreOutcome::Stored { ts_ms: stored_ts })
+    }
+
+    /// Records a payload that can't be stored as a trade, after the caller has logged
+    /// why. Only dead-letters with `STRICT_PAYLOADS`; otherwise the log line is all that's kept.
+    async fn reject(&mut self, payload: &str, reason: &str) -> Result<(), tokio_postgres::Error> {
+        let Some(dead_letter) = &self.dead_letter else { return Ok(()) };
+        self.rejected += 1;
+        if self.rejected.is_power_of_two() {
+            info!(rejected = self.rejected, "payloads dead-lettered so far");
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let params: [&(dyn ToSql + Sync); 3] = [&now, &reason, &payload];
+        match with_retry(self.max_retries, || self.pg.execute(dead_letter, &params)).await {
+            Err(e) if is_disconnect(&e) => {
+                let total = errors::record(ErrorCategory::Connect);
+                warn!(?e, category = %ErrorCategory::Connect, errors_total = total, "postgres connection closed, reconnecting");
+                self.reconnect().await?;
+                let dead_letter = self.dead_letter.as_ref().expect("prepared on reconnect");
+                with_retry(self.max_retries, || self.pg.execute(dead_letter, &params)).await?;
+            }
+            result => { result?; }
+        }
+        Ok(())
+    }
+}
+
+/// Where trade payloads are stored: the `TradeStore`, for replays and routed bus messages.
+trait TradeSink {
+    async fn store(&mut self, v: &serde_json::Value) -> Result<StoreOutcome, tokio_postgres::Error>;
+    async fn reject(&mut self, payload: &str, reason: &str) -> Result<(), tokio_postgres::Error>;
+}
+
+impl TradeSink for TradeStore {
+    async fn store(&mut self, v: &serde_json::Value) -> Result<StoreOutcome, tokio_postgres::Error> {
+        TradeStore::store(self, v).await
+    }
+
+    async fn reject(&mut self, payload: &str, reason: &str) -> Result<(), tokio_postgres::Error> {
+        TradeStore::reject(self, payload, reason).await
+    }
+}
+
+/// Stores a parsed bus payload in the sink `TOPIC_SINKS` routes its topic to, returning
+/// the trade's outcome, or `None` for a quote. An `Err` means nothing was stored.
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+async fn route_payload(
+    routes: &[(String, Sink)], topic: &str, v: &serde_json::Value,
+    trades: &mut impl TradeSink, quotes: &mut impl QuoteSink,
+) -> Result<Option<StoreOutcome>> {
+    match sinks::sink_for(routes, topic) {
+        Sink::Quotes => match quotes.store(v).await {
+            Ok(_) => Ok(None),
+            Err(e) => {
+                let total = errors::record(ErrorCategory::Persist);
+                error!(?e, category = %ErrorCategory::Persist, errors_total = total, "failed to store quote");
+                Err(e.into())
+            }
+        },
+        Sink::Trades => match trades.store(v).await {
+            Ok(outcome) => Ok(Some(outcome)),
+            Err(e) => {
+                let total = errors::record(ErrorCategory::Persist);
+                error!(?e, category = %ErrorCategory::Persist, errors_total = total, "failed to store trade");
+                Err(e.into())
+            }
+        },
+    }
+}
+
+/// Loads a recorded file of bus trade payloads (one JSON object per line) straight
+/// into Postgres, bypassing Kafka/Pulsar.
+async fn replay_file(store: &mut impl TradeSink, path: &str) -> Result<()> {
+    let mut lines = tokio::io::BufReader::new(tokio::fs::File::open(path).await?).lines();
+    let (mut stored, mut dropped, mut skipped) = (0u64, 0u64, 0u64);
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() { continue; }
+        match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(v) => match store.store(&v).await? {
+                StoreOutcome::Stored { .. } => stored += 1,
+                StoreOutcome::Dropped => dropped += 1,
+                StoreOutcome::DeadLettered => skipped += 1,
+            },
+            Err(e) => {
+                let total = errors::record(ErrorCategory::Parse);
+                warn!(?e, category = %ErrorCategory::Parse, errors_total = total, "skipping unparsable replay line");
+                store.reject(&line, &e.to_string()).await?;
+                skipped += 1;
+            }
+        }
+    }
+    info!(stored, dropped, skipped, "replay of {} complete", path);
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt().with_env_filter("info").init();
+    // Trades of every symbol on the topic are stored, so there is no symbol set to report
+    let instance = shared::metrics::Instance { build_version: env!("CARGO_PKG_VERSION"), symbols: "*", symbol: None };
+    info!(build_version = instance.build_version, symbols = instance.symbols, "build_info");
+
+    #[cfg(feature = "kafka")]
+    let brokers = config::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".into());
+    #[cfg(any(feature = "kafka", feature = "pulsar"))]
+    let topic = config::var("KAFKA_TOPIC").unwrap_or_else(|_| "gemini.trades".into());
+    #[cfg(feature = "pulsar")]
+    let pulsar_url = config::var("PULSAR_URL").unwrap_or_else(|_| "pulsar://localhost:6650".into());
+    let pg_dsn = config::var("PG_DSN").unwrap_or_else(|_| "host=localhost user=postgres password=postgres dbname=trades".into());
+    let args: Vec<String> = std::env::args().collect();
+    let replay_path = args.iter().position(|a| a == "--replay").and_then(|i| args.get(i + 1)).cloned();
+    let verify_chain = args.iter().any(|a| a == "--verify-chain");
+    let ts_policy = ts_order_policy(config::var("TRADE_TS_ORDER").ok().as_deref())?;
+    #[cfg(feature = "pulsar")]
+    let sub_type = pulsar_sub_type(config::var("PULSAR_SUBSCRIPTION_TYPE").ok().as_deref())?;
+
+    let connect_timeout = Duration::from_secs(config::var("PG_CONNECT_TIMEOUT_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(60));
+    let db = Pg::connect(&pg_dsn, connect_timeout).await?;
+    let (_, pg_client) = db.client().await;
 
-    let brokers = std::env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".into());
-    let topic = std::env::var("KAFKA_TOPIC").unwrap_or_else(|_| "gemini.trades".into());
-    let pulsar_url = std::env::var("PULSAR_URL").unwrap_or_else(|_| "pulsar://localhost:6650".into());
-    let pg_dsn = std::env::var("PG_DSN").unwrap_or_else(|_| "host=localhost user=postgres password=postgres dbname=trades".into());
+    // Create or upgrade the schema
+    migrations::run(&pg_client).await?;
+
+    if verify_chain {
+        let report = chain::verify(&pg_client).await?;
+        if let Some((seq, reason)) = report.broken {
+            anyhow::bail!("trade hash chain broken at chain_seq {}: {} ({} rows verified before it)", seq, reason, report.checked);
+        }
+        info!(rows = report.checked, head = %report.head.as_deref().map(chain::hex).unwrap_or_default(), "trade hash chain verified");
+        return Ok(());
+    }
+
+    // Trailing VWAP per symbol, kept in memory only: windows start cold after a restart
+    let vwap_window_ms = config::var("VWAP_WINDOW_SECS").ok().and_then(|s| s.parse::<u64>().ok()).map(|s| s * 1000);
+    let vwaps = Vwaps { window_ms: vwap_window_ms, by_symbol: HashMap::new() };
+    let mut store = TradeStore::new(&db, vwaps, ts_policy).await?;
+
+    if let Some(path) = replay_path {
+        return replay_file(&mut store, &path).await;
+    }
+
+    // Build info and error totals for Prometheus; trades of every symbol share the
+    // totals, so they carry no symbol label
+    if let Ok(addr) = config::var("METRICS_ADDR") {
+        let bound = shared::metrics::serve(&addr, instance)?;
+        info!(%bound, "serving /metrics");
+    }
+
+    // Latest stored trade per symbol over HTTP, for dashboards that shouldn't hit Postgres
+    if let Ok(addr) = config::var("LAST_TRADE_HTTP_ADDR") {
+        let cache = LastTrades::default();
+        store.last_trades = Some(cache.clone());
+        tokio::spawn(async move {
+            if let Err(e) = last_trade::serve(&addr, cache).await {
+                error!(?e, "last-trade HTTP server stopped");
+            }
+        });
+    }
+
+    // Topic -> sink routing; without `TOPIC_SINKS` only `KAFKA_TOPIC`, carrying trades
+    #[cfg(any(feature = "kafka", feature = "pulsar"))]
+    let routes = sinks::topic_sinks(&topic)?;
+    #[cfg(any(feature = "kafka", feature = "pulsar"))]
+    let topics: Vec<&str> = routes.iter().map(|(t, _)| t.as_str()).collect();
+    #[cfg(any(feature = "kafka", feature = "pulsar"))]
+    let mut quotes = QuoteStore::new(&db, store.max_retries).await?;
 
     #[cfg(feature = "kafka")]
     let consumer: StreamConsumer = rdkafka::config::ClientConfig::new()
@@ -22,57 +609,84 @@ async fn main() -> Result<()> {
         .set("group.id", "gemini-consumer")
         .set("enable.partition.eof", "false")
         .set("auto.offset.reset", "earliest")
+        // Offsets are stored explicitly once a trade is persisted, then auto-committed
+        .set("enable.auto.offset.store", "false")
         .create()?;
     #[cfg(feature = "kafka")]
-    consumer.subscribe(&[&topic])?;
+    consumer.subscribe(&topics)?;
+    // Optional downstream topic for enriched trades, only fed after a trade is stored
+    #[cfg(feature = "kafka")]
+    let output: Option<(rdkafka::producer::FutureProducer, String)> = match config::var("OUTPUT_TOPIC") {
+        Ok(out_topic) => Some((rdkafka::config::ClientConfig::new().set("bootstrap.servers", &brokers).create()?, out_topic)),
+        Err(_) => None,
+    };
 
     #[cfg(feature = "pulsar")]
     let pulsar: pulsar::Pulsar<_> = pulsar::PulsarBuilder::new(pulsar_url, pulsar::TokioExecutor).build().await?;
     #[cfg(feature = "pulsar")]
     let mut consumer: PulsarConsumer<Vec<u8>, _> = pulsar.consumer()
-        .with_topic(&topic)
+        .with_topics(&topics)
         .with_consumer_name("gemini-consumer")
-        .with_subscription_type(SubType::Exclusive)
+        .with_subscription_type(sub_type)
         .with_subscription("gemini-trades-sub")
         .build()
         .await?;
+    #[cfg(feature = "pulsar")]
+    let mut output = match config::var("OUTPUT_TOPIC") {
+        Ok(out_topic) => Some(pulsar.producer().with_topic(out_topic).with_name("gemini-consumer-enriched").build().await?),
+        Err(_) => None,
+    };
 
-    let (pg_client_raw, pg_conn) = tokio_postgres::connect(&pg_dsn, NoTls).await?;
-    let pg_client = Arc::new(pg_client_raw);
-    tokio::spawn(async move { if let Err(e) = pg_conn.await { error!(?e, "pg conn error"); }});
 
-    // Create table if not exists
-    pg_client.execute("CREATE TABLE IF NOT EXISTS trades (ts_ms BIGINT, symbol TEXT, price_u BIGINT, qty_u BIGINT, side TEXT)", &[]).await?;
     // Retention: delete older than 7 days
     let _retention_task = {
-        let pg = Arc::clone(&pg_client);
+        let db = db.clone();
         tokio::spawn(async move {
             loop {
+                let (_, pg) = db.client().await;
                 let cutoff = (chrono::Utc::now() - chrono::Duration::days(7)).timestamp_millis();
-                let _ = pg.execute("DELETE FROM trades WHERE ts_ms < $1", &[&cutoff]).await;
+                let _ = pg.execute(PRUNE_TRADES, &[&cutoff]).await;
+                let _ = pg.execute("DELETE FROM quotes WHERE ts_ms < $1", &[&cutoff]).await;
+                let _ = pg.execute("DELETE FROM minute_stats WHERE minute_ms < $1", &[&cutoff]).await;
                 tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
             }
         })
     };
 
+    #[cfg(any(feature = "kafka", feature = "pulsar"))]
+    let mut backoff = RecvBackoff::default();
+
     #[cfg(feature = "kafka")]
     loop {
         match consumer.recv().await {
-            Err(e) => warn!(?e, "kafka error"),
+            Err(e) => {
+                let category = kafka_recv_category(&e);
+                let total = errors::record(category);
+                warn!(?e, %category, errors_total = total, "kafka error");
+                backoff.failed().await;
+            }
             Ok(m) => {
+                backoff.succeeded();
                 if let Some(payload) = m.payload() {
-                    if let Ok(v) = serde_json::from_slice::<serde_json::Value>(payload) {
-                        let ts = v.get("ts_ms").and_then(|x| x.as_i64()).unwrap_or(0);
-                        let symbol = v.get("symbol").and_then(|x| x.as_str()).unwrap_or("");
-                        let price = v.get("price_u").and_then(|x| x.as_i64()).unwrap_or(0);
-                        let qty = v.get("qty_u").and_then(|x| x.as_i64()).unwrap_or(0);
-                        let side = v.get("side").and_then(|x| x.as_str()).unwrap_or("");
-                        let _ = pg_client.execute(
-                            "INSERT INTO trades (ts_ms, symbol, price_u, qty_u, side) VALUES ($1,$2,$3,$4,$5)",
-                            &[&ts, &symbol, &price, &qty, &side]
-                        ).await;
+                    let parsed = serde_json::from_slice::<serde_json::Value>(payload);
+                    if let Err(e) = &parsed {
+                        let total = errors::record(ErrorCategory::Parse);
+                        warn!(?e, category = %ErrorCategory::Parse, errors_total = total, "skipping unparsable bus payload");
+                        store.reject(&String::from_utf8_lossy(payload), &e.to_string()).await?;
+                    }
+                    if let Ok(v) = parsed {
+                        let outcome = route_payload(&routes, m.topic(), &v, &mut store, &mut quotes).await?;
+                        let enriched = outcome.filter(|_| output.is_some()).and_then(|outcome| enrichment(&v, outcome, store.ts_quantum_ms));
+                        if let Some(((producer, out_topic), payload)) = output.as_ref().zip(enriched) {
+                            let record = rdkafka::producer::FutureRecord::<(), _>::to(out_topic).payload(&payload);
+                            if let Err((e, _)) = producer.send(record, Duration::from_secs(0)).await {
+                                let total = errors::record(ErrorCategory::Produce);
+                                warn!(?e, category = %ErrorCategory::Produce, errors_total = total, "failed to publish enriched trade");
+                            }
+                        }
                     }
                 }
+                consumer.store_offset_from_message(&m)?;
             }
         }
     }
@@ -80,20 +694,30 @@ async fn main() -> Result<()> {
     #[cfg(feature = "pulsar")]
     loop {
         match consumer.try_next().await {
-            Err(e) => warn!(?e, "pulsar error"),
+            Err(e) => {
+                let total = errors::record(ErrorCategory::Connect);
+                warn!(?e, category = %ErrorCategory::Connect, errors_total = total, "pulsar error");
+                backoff.failed().await;
+            }
             Ok(Some(msg)) => {
-                if let Ok(v) = serde_json::from_slice::<serde_json::Value>(&msg.payload.data) {
-                    let ts = v.get("ts_ms").and_then(|x| x.as_i64()).unwrap_or(0);
-                    let symbol = v.get("symbol").and_then(|x| x.as_str()).unwrap_or("");
-                    let price = v.get("price_u").and_then(|x| x.as_i64()).unwrap_or(0);
-                    let qty = v.get("qty_u").and_then(|x| x.as_i64()).unwrap_or(0);
-                    let side = v.get("side").and_then(|x| x.as_str()).unwrap_or("");
-                    let _ = pg_client.execute(
-                        "INSERT INTO trades (ts_ms, symbol, price_u, qty_u, side) VALUES ($1,$2,$3,$4,$5)",
-                        &[&ts, &symbol, &price, &qty, &side]
-                    ).await;
-                    let _ = consumer.ack(&msg).await;
+                backoff.succeeded();
+                let parsed = serde_json::from_slice::<serde_json::Value>(&msg.payload.data);
+                if let Err(e) = &parsed {
+                    let total = errors::record(ErrorCategory::Parse);
+                    warn!(?e, category = %ErrorCategory::Parse, errors_total = total, "skipping unparsable bus payload");
+                    store.reject(&String::from_utf8_lossy(&msg.payload.data), &e.to_string()).await?;
                 }
+                if let Ok(v) = parsed {
+                    let outcome = route_payload(&routes, &msg.topic, &v, &mut store, &mut quotes).await?;
+                    let enriched = outcome.filter(|_| output.is_some()).and_then(|outcome| enrichment(&v, outcome, store.ts_quantum_ms));
+                    if let Some((producer, payload)) = output.as_mut().zip(enriched) {
+                        if let Err(e) = producer.send(payload).await {
+                            let total = errors::record(ErrorCategory::Produce);
+                            warn!(?e, category = %ErrorCategory::Produce, errors_total = total, "failed to publish enriched trade");
+                        }
+                    }
+                }
+                let _ = consumer.ack(&msg).await;
             }
             Ok(None) => {
                 tokio::time::sleep(std::time::Duration::from_millis(100)).await;
@@ -107,3 +731,236 @@ async fn main() -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn trade() -> serde_json::Value {
+        json!({"ts_ms": 1_700_000_000_000u64, "symbol": "SOLUSD", "price_u": 145_850_000, "qty_u": 3_500_000, "side": "buy"})
+    }
+
+    #[test]
+    fn validate_trade_accepts_ingest_payload() {
+        assert_eq!(validate_trade(&trade()), Ok(()));
+        let mut unknown_side = trade();
+        unknown_side["side"] = json!("");
+        assert_eq!(validate_trade(&unknown_side), Ok(()));
+    }
+
+    #[test]
+    fn validate_trade_rejects_malformed_payloads() {
+        let with = |k: &str, v: serde_json::Value| { let mut t = trade(); t[k] = v; t };
+        let mut missing_symbol = trade();
+        missing_symbol.as_object_mut().unwrap().remove("symbol");
+        assert_eq!(validate_trade(&missing_symbol), Err("missing symbol".to_string()));
+        assert_eq!(validate_trade(&with("price_u", json!("145.85"))), Err("price_u is not a non-negative integer".to_string()));
+        assert_eq!(validate_trade(&with("ts_ms", json!(0))), Err("ts_ms is zero".to_string()));
+        assert_eq!(validate_trade(&with("qty_u", json!(0))), Err("invalid quantity 0".to_string()));
+        assert_eq!(validate_trade(&with("side", json!("short"))), Err("invalid side 'short'".to_string()));
+        assert!(validate_trade(&json!("not an object")).is_err());
+    }
+
+    #[derive(Debug)]
+    struct Flaky {
+        transient: bool,
+    }
+
+    impl Transient for Flaky {
+        fn is_transient(&self) -> bool {
+            self.transient
+        }
+    }
+
+    #[tokio::test]
+    async fn insert_lands_after_two_transient_failures_and_commits_once() {
+        let attempts = std::cell::Cell::new(0);
+        let mut commits = 0;
+        let stored = with_retry(5, || {
+            attempts.set(attempts.get() + 1);
+            let n = attempts.get();
+            async move { if n <= 2 { Err(Flaky { transient: true }) } else { Ok(n) } }
+        }).await;
+        // As in the receive loop: the offset is stored only once the insert has landed
+        if stored.is_ok() { commits += 1; }
+        assert_eq!(stored.unwrap(), 3);
+        assert_eq!(commits, 1);
+    }
+
+    #[tokio::test]
+    async fn fatal_errors_and_exhausted_retries_surface() {
+        let attempts = std::cell::Cell::new(0);
+        let fatal = with_retry(5, || { attempts.set(attempts.get() + 1); async { Err::<(), _>(Flaky { transient: false }) } }).await;
+        assert!(fatal.is_err());
+        assert_eq!(attempts.get(), 1);
+
+        attempts.set(0);
+        let exhausted = with_retry(2, || { attempts.set(attempts.get() + 1); async { Err::<(), _>(Flaky { transient: true }) } }).await;
+        assert!(exhausted.is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn quantized_timestamps_land_on_the_grid() {
+        for ts in [1_700_000_000_000i64, 1_700_000_000_099, 1_700_000_000_123, 7, -1] {
+            let (stored, _) = quantize_for_sink(ts, None, 100);
+            assert_eq!(stored % 100, 0, "{} stored as {}", ts, stored);
+            assert!(stored <= ts && ts - stored < 100);
+        }
+        assert_eq!(quantize_for_sink(1_700_000_000_123, None, 0).0, 1_700_000_000_123);
+        assert_eq!(quantize_for_sink(1_700_000_000_123, None, 1).0, 1_700_000_000_123);
+    }
+
+    #[test]
+    fn quantizing_drops_the_raw_frame() {
+        let raw = Some("{\"timestampms\":1700000000123}");
+        assert_eq!(quantize_for_sink(1_700_000_000_123, raw, 100), (1_700_000_000_100, None));
+        assert_eq!(quantize_for_sink(1_700_000_000_123, raw, 0), (1_700_000_000_123, raw));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn receive_backoff_grows_with_errors_and_resets_on_success() {
+        let mut backoff = RecvBackoff::default();
+        let mut waited = Vec::new();
+        for _ in 0..12 {
+            let start = tokio::time::Instant::now();
+            backoff.failed().await;
+            waited.push(start.elapsed().as_millis());
+        }
+        assert_eq!(waited, vec![100, 200, 400, 800, 1_600, 3_200, 6_400, 12_800, 25_600, 30_000, 30_000, 30_000]);
+
+        backoff.succeeded();
+        assert_eq!(backoff.delay(), Duration::from_millis(100));
+        let start = tokio::time::Instant::now();
+        backoff.failed().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(100), "a fresh run of errors starts short again");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_retries_until_the_database_is_up() {
+        let attempts = std::cell::Cell::new(0);
+        let start = tokio::time::Instant::now();
+        let connected = retry_connect(Duration::from_secs(60), || {
+            attempts.set(attempts.get() + 1);
+            let n = attempts.get();
+            async move { if n <= 3 { Err("connection refused") } else { Ok("client") } }
+        }).await;
+        assert_eq!(connected, Ok("client"));
+        assert_eq!(attempts.get(), 4);
+        assert_eq!(start.elapsed(), Duration::from_millis(500 + 1_000 + 2_000), "backoff doubles between attempts");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_gives_up_at_the_deadline() {
+        let attempts = std::cell::Cell::new(0);
+        let start = tokio::time::Instant::now();
+        let connected = retry_connect(Duration::from_secs(5), || {
+            attempts.set(attempts.get() + 1);
+            async { Err::<(), _>("connection refused") }
+        }).await;
+        assert_eq!(connected, Err("connection refused"));
+        // Waits of 0.5, 1 and 2s fit; the next 4s wait would end past the deadline
+        assert_eq!((attempts.get(), start.elapsed()), (4, Duration::from_millis(3_500)));
+    }
+
+
+    #[test]
+    fn only_stored_trades_are_enriched() {
+        let v = serde_json::json!({"ts_ms": 1_700_000_000_123_i64, "symbol": "SOLUSD", "price_u": 145_900_000, "qty_u": 2_000_000, "raw_json": "{}"});
+
+        let out: serde_json::Value = serde_json::from_slice(&enrichment(&v, StoreOutcome::Stored { ts_ms: 1_700_000_000_100 }, 0).unwrap()).unwrap();
+        assert_eq!(out["venue"], "gemini");
+        assert_eq!(out["notional_u"], 291_800_000);
+        assert_eq!(out["ts_ms"], 1_700_000_000_100_i64, "the stored timestamp");
+        assert!(out["latency_ms"].as_i64().unwrap() > 0);
+        assert_eq!(out["raw_json"], "{}");
+
+        assert_eq!(enrichment(&v, StoreOutcome::Dropped, 0), None, "rejected by the ts order policy");
+        assert_eq!(enrichment(&v, StoreOutcome::DeadLettered, 0), None, "failed validation");
+    }
+
+
+    #[tokio::test]
+    async fn replay_feeds_every_line_in_file_order() {
+        #[derive(Default)]
+        struct Recorded { stored: Vec<i64>, rejected: Vec<String> }
+        impl TradeSink for Recorded {
+            async fn store(&mut self, v: &serde_json::Value) -> Result<StoreOutcome, tokio_postgres::Error> {
+                let ts_ms = v["ts_ms"].as_i64().unwrap();
+                self.stored.push(ts_ms);
+                Ok(StoreOutcome::Stored { ts_ms })
+            }
+            async fn reject(&mut self, payload: &str, _reason: &str) -> Result<(), tokio_postgres::Error> {
+                self.rejected.push(payload.to_string());
+                Ok(())
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("consumer-test-{}-replay.jsonl", std::process::id()));
+        let line = |ts: i64| serde_json::json!({"ts_ms": ts, "symbol": "SOLUSD", "price_u": 145_900_000, "qty_u": 1_000_000, "side": "buy"}).to_string();
+        let fixture = [line(3), line(1), String::new(), "not json".to_string(), line(2), "  ".to_string(), line(4)].join("\n");
+        std::fs::write(&path, fixture).unwrap();
+
+        let mut sink = Recorded::default();
+        replay_file(&mut sink, path.to_str().unwrap()).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sink.stored, vec![3, 1, 2, 4], "file order, not timestamp order");
+        assert_eq!(sink.rejected, vec!["not json"], "blank lines are skipped, bad ones dead-lettered");
+    }
+
+
+    #[tokio::test]
+    async fn two_topics_route_to_their_own_sinks() {
+        #[derive(Default)]
+        struct Recorded(Vec<i64>);
+        impl TradeSink for Recorded {
+            async fn store(&mut self, v: &serde_json::Value) -> Result<StoreOutcome, tokio_postgres::Error> {
+                self.0.push(v["ts_ms"].as_i64().unwrap());
+                Ok(StoreOutcome::Stored { ts_ms: v["ts_ms"].as_i64().unwrap() })
+            }
+            async fn reject(&mut self, _payload: &str, _reason: &str) -> Result<(), tokio_postgres::Error> { Ok(()) }
+        }
+        impl QuoteSink for Recorded {
+            async fn store(&mut self, v: &serde_json::Value) -> Result<u64, tokio_postgres::Error> {
+                self.0.push(v["ts_ms"].as_i64().unwrap());
+                Ok(1)
+            }
+        }
+
+        let routes = vec![("gemini.trades".to_string(), Sink::Trades), ("gemini.quotes".to_string(), Sink::Quotes)];
+        let (mut trades, mut quotes) = (Recorded::default(), Recorded::default());
+        let messages = [("gemini.trades", 1), ("gemini.quotes", 2), ("persistent://public/default/gemini.quotes", 3), ("gemini.trades", 4)];
+        let mut outcomes = Vec::new();
+        for (topic, ts_ms) in messages {
+            let v = serde_json::json!({"ts_ms": ts_ms, "symbol": "SOLUSD"});
+            outcomes.push(route_payload(&routes, topic, &v, &mut trades, &mut quotes).await.unwrap());
+        }
+
+        assert_eq!(trades.0, vec![1, 4]);
+        assert_eq!(quotes.0, vec![2, 3]);
+        assert_eq!(outcomes, vec![Some(StoreOutcome::Stored { ts_ms: 1 }), None, None, Some(StoreOutcome::Stored { ts_ms: 4 })], "only trades are enriched");
+    }
+
+
+    #[test]
+    fn ts_order_policy_rejects_unknown_values() {
+        assert_eq!(ts_order_policy(None).unwrap(), OutOfOrderPolicy::Accept);
+        assert_eq!(ts_order_policy(Some("Clamp")).unwrap(), OutOfOrderPolicy::Clamp);
+        assert_eq!(ts_order_policy(Some("drop")).unwrap(), OutOfOrderPolicy::Drop);
+        let err = ts_order_policy(Some("reorder")).unwrap_err().to_string();
+        assert!(err.starts_with("unknown TRADE_TS_ORDER 'reorder'"), "{}", err);
+    }
+
+    #[cfg(feature = "pulsar")]
+    #[test]
+    fn pulsar_sub_type_maps_every_name() {
+        assert_eq!(pulsar_sub_type(None).unwrap(), SubType::Exclusive);
+        assert_eq!(pulsar_sub_type(Some("failover")).unwrap(), SubType::Failover);
+        assert_eq!(pulsar_sub_type(Some("shared")).unwrap(), SubType::Shared);
+        assert_eq!(pulsar_sub_type(Some("Key_Shared")).unwrap(), SubType::KeyShared);
+        let err = pulsar_sub_type(Some("keyshared")).unwrap_err().to_string();
+        assert!(err.starts_with("unknown PULSAR_SUBSCRIPTION_TYPE 'keyshared'"), "{}", err);
+    }
+}
@@ -0,0 +1,77 @@
+use tokio_postgres::Client;
+use tracing::info;
+
+/// Ordered schema steps. Each one is idempotent so it also applies cleanly to tables
+/// created before versions were tracked. Append new steps; never edit applied ones.
+const MIGRATIONS: &[(i64, &str)] = &[
+    (1, "CREATE TABLE IF NOT EXISTS trades (ts_ms BIGINT, symbol TEXT, price_u BIGINT, qty_u BIGINT, side TEXT)"),
+    (2, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS vwap_u BIGINT"),
+    (3, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS bid_at BIGINT; ALTER TABLE trades ADD COLUMN IF NOT EXISTS ask_at BIGINT"),
+    (4, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS trade_id BIGINT"),
+    (5, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS side_inferred BOOLEAN NOT NULL DEFAULT false"),
+    (6, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS raw_json TEXT"),
+    (7, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS chain_seq BIGINT; ALTER TABLE trades ADD COLUMN IF NOT EXISTS chain_prev BYTEA; ALTER TABLE trades ADD COLUMN IF NOT EXISTS chain_hash BYTEA"),
+    (8, "CREATE TABLE IF NOT EXISTS quotes (ts_ms BIGINT, symbol TEXT, bid_u BIGINT, bid_qty_u BIGINT, ask_u BIGINT, ask_qty_u BIGINT)"),
+    (9, "CREATE TABLE IF NOT EXISTS dead_letters (received_ms BIGINT NOT NULL, reason TEXT NOT NULL, payload TEXT NOT NULL)"),
+    (10, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS gap_before BOOLEAN NOT NULL DEFAULT false"),
+    (11, "CREATE TABLE IF NOT EXISTS minute_stats (symbol TEXT NOT NULL, minute_ms BIGINT NOT NULL, trades BIGINT NOT NULL, volume_u BIGINT NOT NULL, final BOOLEAN NOT NULL DEFAULT false, PRIMARY KEY (symbol, minute_ms))"),
+    (12, "CREATE UNIQUE INDEX IF NOT EXISTS trades_chain_seq ON trades (chain_seq)"),
+    (13, "ALTER TABLE trades ADD COLUMN IF NOT EXISTS chain_version SMALLINT"),
+];
+
+/// Applies pending migrations in order, recording each in `schema_migrations` in the
+/// same transaction as its DDL. Safe to run on every start.
+pub async fn run(pg: &Client) -> Result<(), tokio_postgres::Error> {
+    pg.execute("CREATE TABLE IF NOT EXISTS schema_migrations (version BIGINT PRIMARY KEY, applied_at TIMESTAMPTZ NOT NULL DEFAULT now())", &[]).await?;
+    let applied: Vec<i64> = pg.query("SELECT version FROM schema_migrations", &[]).await?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+    for &(version, sql) in MIGRATIONS {
+        if applied.contains(&version) { continue; }
+        pg.batch_execute(&format!(
+            "BEGIN; {}; INSERT INTO schema_migrations (version) VALUES ({}) ON CONFLICT DO NOTHING; COMMIT;",
+            sql, version
+        )).await?;
+        info!(version, "applied schema migration");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pg::tests::{drop_scratch, scratch_db};
+
+    #[test]
+    fn every_step_is_guarded_and_versions_ascend() {
+        for (i, &(version, sql)) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(version, i as i64 + 1, "versions are 1.. without gaps");
+            for stmt in sql.split(';').map(str::trim) {
+                let guarded = ["CREATE TABLE IF NOT EXISTS", "CREATE UNIQUE INDEX IF NOT EXISTS", "CREATE INDEX IF NOT EXISTS"].iter().any(|p| stmt.starts_with(p))
+                    || (stmt.starts_with("ALTER TABLE") && stmt.contains("ADD COLUMN IF NOT EXISTS"));
+                assert!(guarded, "migration {} is not idempotent: {}", version, stmt);
+            }
+        }
+    }
+
+    async fn applied(pg: &Client) -> Vec<i64> {
+        pg.query("SELECT version FROM schema_migrations ORDER BY version", &[]).await.unwrap().iter().map(|r| r.get(0)).collect()
+    }
+
+    #[tokio::test]
+    async fn running_twice_applies_nothing_the_second_time() {
+        let Some(pg) = scratch_db("migrations").await else { return };
+        run(&pg).await.unwrap();
+        let first = applied(&pg).await;
+        assert_eq!(first, MIGRATIONS.iter().map(|(v, _)| *v).collect::<Vec<_>>());
+
+        run(&pg).await.unwrap();
+        assert_eq!(applied(&pg).await, first);
+        // As on tables that predate version tracking: every step re-applies cleanly
+        for (_, sql) in MIGRATIONS {
+            pg.batch_execute(sql).await.unwrap();
+        }
+        drop_scratch(&pg).await;
+    }
+}
@@ -0,0 +1,96 @@
+use crate::connect_with_retry;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+use tracing::info;
+
+/// The consumer's Postgres connection, shared by its writers. A client whose connection
+/// closed never recovers, so a writer that gets an `is_disconnect` error calls `reconnect`
+/// and re-prepares its statements on the client it gets back. Each client carries a
+/// generation, bumped per reconnect, so writers hitting the same closed client connect
+/// only once.
+#[derive(Clone)]
+pub struct Pg {
+    dsn: Arc<str>,
+    timeout: Duration,
+    current: Arc<Mutex<(u64, Arc<Client>)>>,
+}
+
+impl Pg {
+    /// Connects through `connect_with_retry`, waiting up to `timeout` for the database;
+    /// reconnects wait as long.
+    pub async fn connect(dsn: &str, timeout: Duration) -> Result<Self, tokio_postgres::Error> {
+        let client = Arc::new(connect_with_retry(dsn, timeout).await?);
+        Ok(Self { dsn: dsn.into(), timeout, current: Arc::new(Mutex::new((0, client))) })
+    }
+
+    /// The live client and its generation.
+    pub async fn client(&self) -> (u64, Arc<Client>) {
+        self.current.lock().await.clone()
+    }
+
+    /// Replaces the client of generation `stale` with a fresh connection. If another
+    /// writer already replaced it, that newer client is returned instead.
+    pub async fn reconnect(&self, stale: u64) -> Result<(u64, Arc<Client>), tokio_postgres::Error> {
+        let mut current = self.current.lock().await;
+        if current.0 == stale {
+            *current = (stale + 1, Arc::new(connect_with_retry(&self.dsn, self.timeout).await?));
+            info!(generation = current.0, "reconnected to postgres");
+        }
+        Ok(current.clone())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tokio_postgres::Client;
+
+    /// A connection to `TEST_PG_DSN` working in a fresh schema of its own (dropped first
+    /// if a previous run left it), or `None` when the variable is unset and the calling
+    /// test has no database to run against.
+    pub(crate) async fn scratch_db(name: &str) -> Option<Client> {
+        let dsn = std::env::var("TEST_PG_DSN").ok()?;
+        let (client, conn) = tokio_postgres::connect(&dsn, tokio_postgres::NoTls).await.expect("TEST_PG_DSN connects");
+        tokio::spawn(conn);
+        let schema = format!("consumer_test_{}_{}", std::process::id(), name);
+        client.batch_execute(&format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}; SET search_path TO {0}", schema)).await.unwrap();
+        Some(client)
+    }
+
+    pub(crate) async fn drop_scratch(client: &Client) {
+        client.batch_execute("DO $$ BEGIN EXECUTE format('DROP SCHEMA %I CASCADE', current_schema()); END $$").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn prepared_and_ad_hoc_trade_inserts_store_the_same_row() {
+        let Some(pg) = scratch_db("prepared").await else { return };
+        crate::migrations::run(&pg).await.unwrap();
+        let prepared = pg.prepare(crate::INSERT_TRADE).await.unwrap();
+        assert_eq!(prepared.params().len(), 16, "one bound param per listed column");
+
+        let insert = |chain_seq: i64| {
+            let pg = &pg;
+            let prepared = &prepared;
+            async move {
+                let (ts, symbol, price, qty, side) = (1_700_000_000_123_i64, "SOLUSD", 145_900_000_i64, 2_000_000_i64, "buy");
+                let (vwap, bid_at, ask_at, trade_id) = (Some(145_880_000_i64), Some(145_850_000_i64), None::<i64>, Some(42_i64));
+                let (side_inferred, raw_json, gap_before, chain_version) = (true, Some("{\"type\":\"trade\"}"), false, 2_i16);
+                let (chain_prev, chain_hash) = (vec![0u8; 32], vec![7u8; 32]);
+                let params: [&(dyn tokio_postgres::types::ToSql + Sync); 16] = [&ts, &symbol, &price, &qty, &side, &vwap, &bid_at, &ask_at, &trade_id, &side_inferred, &raw_json, &chain_seq, &chain_prev, &chain_hash, &gap_before, &chain_version];
+                match chain_seq {
+                    1 => pg.execute(crate::INSERT_TRADE, &params).await,
+                    _ => pg.execute(prepared, &params).await,
+                }
+            }
+        };
+        assert_eq!(insert(1).await.unwrap(), 1, "ad hoc");
+        assert_eq!(insert(2).await.unwrap(), 1, "prepared");
+
+        let rows: Vec<String> = pg.query("SELECT (to_jsonb(t) - 'chain_seq')::text FROM trades t ORDER BY chain_seq", &[]).await.unwrap()
+            .iter().map(|r| r.get(0)).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], rows[1]);
+        drop_scratch(&pg).await;
+    }
+}
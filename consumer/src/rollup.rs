@@ -0,0 +1,86 @@
+use crate::with_retry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_postgres::types::ToSql;
+
+const MINUTE_MS: i64 = 60_000;
+
+const UPSERT_MINUTE: &str = "INSERT INTO minute_stats (symbol, minute_ms, trades, volume_u) VALUES ($1,$2,1,$3) \
+    ON CONFLICT (symbol, minute_ms) DO UPDATE SET trades = minute_stats.trades + 1, volume_u = minute_stats.volume_u + EXCLUDED.volume_u";
+const FINALIZE_MINUTE: &str = "UPDATE minute_stats SET final = true WHERE symbol = $1 AND minute_ms = $2";
+/// Only a symbol's newest unfinalized row can still be open; older ones were left by
+/// a crash between opening a minute and finalizing the one before it.
+const FINALIZE_STALE: &str = "UPDATE minute_stats m SET final = true WHERE NOT final \
+    AND minute_ms < (SELECT max(minute_ms) FROM minute_stats o WHERE o.symbol = m.symbol AND NOT o.final)";
+const OPEN_MINUTES: &str = "SELECT symbol, minute_ms FROM minute_stats WHERE NOT final";
+
+/// Per-symbol, per-minute trade count and volume in `minute_stats`, kept current as
+/// trades are stored. Each trade is added to its symbol's open minute by an
+/// incremental upsert. Open minutes are reloaded from the table at startup, so a
+/// restart keeps counting on the same row and still finalizes it on rollover. The
+/// first trade of a later minute marks the open row `final` and opens the next one; a
+/// quiet symbol's last minute stays open until its next trade. Late trades, stamped
+/// before the open minute, count towards the open minute rather than reopening a
+/// finalized one.
+pub struct MinuteRollups {
+    pg: Arc<tokio_postgres::Client>,
+    upsert: tokio_postgres::Statement,
+    finalize: tokio_postgres::Statement,
+    max_retries: u32,
+    /// Open minute (start, in ms) per symbol.
+    open: HashMap<String, i64>,
+}
+
+impl MinuteRollups {
+    pub async fn new(pg: Arc<tokio_postgres::Client>, max_retries: u32) -> Result<Self, tokio_postgres::Error> {
+        let upsert = pg.prepare(UPSERT_MINUTE).await?;
+        let finalize = pg.prepare(FINALIZE_MINUTE).await?;
+        pg.execute(FINALIZE_STALE, &[]).await?;
+        let open = pg.query(OPEN_MINUTES, &[]).await?.iter().map(|r| (r.get(0), r.get(1))).collect();
+        Ok(Self { pg, upsert, finalize, max_retries, open })
+    }
+
+    /// Counts one stored trade, finalizing the symbol's previous minute on rollover.
+    pub async fn add(&mut self, symbol: &str, ts_ms: i64, qty_u: i64) -> Result<(), tokio_postgres::Error> {
+        let (bucket, rolled_over) = bucket(self.open.get(symbol).copied(), ts_ms);
+        if let Some(open) = rolled_over {
+            let params: [&(dyn ToSql + Sync); 2] = [&symbol, &open];
+            with_retry(self.max_retries, || self.pg.execute(&self.finalize, &params)).await?;
+        }
+        self.open.insert(symbol.to_string(), bucket);
+        let params: [&(dyn ToSql + Sync); 3] = [&symbol, &bucket, &qty_u];
+        with_retry(self.max_retries, || self.pg.execute(&self.upsert, &params)).await?;
+        Ok(())
+    }
+}
+
+/// Minute a trade at `ts_ms` counts towards given the symbol's `open` minute, and the
+/// open minute to finalize first if the trade starts a later one.
+fn bucket(open: Option<i64>, ts_ms: i64) -> (i64, Option<i64>) {
+    let minute = ts_ms.div_euclid(MINUTE_MS) * MINUTE_MS;
+    match open {
+        Some(open) if minute <= open => (open, None),
+        Some(open) => (minute, Some(open)),
+        None => (minute, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trades_in_two_minutes_finalize_the_first() {
+        let (first, _) = bucket(None, 120_500);
+        assert_eq!(first, 120_000);
+        assert_eq!(bucket(Some(first), 179_999), (120_000, None));
+        assert_eq!(bucket(Some(first), 180_000), (180_000, Some(120_000)));
+        // A late trade counts towards the open minute instead of reopening a final one
+        assert_eq!(bucket(Some(180_000), 130_000), (180_000, None));
+    }
+
+    #[test]
+    fn minute_before_the_epoch_rounds_down() {
+        assert_eq!(bucket(None, -1), (-60_000, None));
+    }
+}
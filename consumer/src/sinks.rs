@@ -0,0 +1,127 @@
+// Only the routing is reachable in test builds without kafka/pulsar
+#![cfg_attr(not(any(feature = "kafka", feature = "pulsar")), allow(dead_code))]
+
+use crate::pg::Pg;
+use crate::{is_disconnect, with_retry};
+use shared::config;
+use shared::errors::{self, ErrorCategory};
+use std::sync::Arc;
+use tokio_postgres::types::ToSql;
+use tracing::warn;
+
+/// Where a topic's payloads are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    /// Trade payloads as published by ingest, into `trades`.
+    Trades,
+    /// Top-of-book quotes (`ts_ms`, `symbol`, `bid_u`, `bid_qty_u`, `ask_u`, `ask_qty_u`), into `quotes`.
+    Quotes,
+}
+
+/// Topic routing from `TOPIC_SINKS` (`topic:sink,...`, sinks `trades` or `quotes`),
+/// e.g. `gemini.trades:trades,gemini.quotes:quotes`. Unset means `default_topic` alone,
+/// carrying trades.
+pub fn topic_sinks(default_topic: &str) -> anyhow::Result<Vec<(String, Sink)>> {
+    parse_topic_sinks(config::var("TOPIC_SINKS").ok().as_deref(), default_topic)
+}
+
+fn parse_topic_sinks(spec: Option<&str>, default_topic: &str) -> anyhow::Result<Vec<(String, Sink)>> {
+    let Some(spec) = spec else {
+        return Ok(vec![(default_topic.to_string(), Sink::Trades)]);
+    };
+    let mut routes = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (topic, sink) = entry.rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("TOPIC_SINKS entry '{}' is not topic:sink", entry))?;
+        let sink = match sink.trim().to_ascii_lowercase().as_str() {
+            "trades" => Sink::Trades,
+            "quotes" => Sink::Quotes,
+            other => anyhow::bail!("unknown sink '{}' for topic '{}' in TOPIC_SINKS (expected trades or quotes)", other, topic),
+        };
+        routes.push((topic.trim().to_string(), sink));
+    }
+    if routes.is_empty() {
+        anyhow::bail!("TOPIC_SINKS is set but lists no topics");
+    }
+    Ok(routes)
+}
+
+/// The sink for a received message's topic. Pulsar reports fully qualified names
+/// (`persistent://public/default/gemini.quotes`), so a configured short name matches
+/// as the last path segment. Unlisted topics are treated as trades.
+pub fn sink_for(routes: &[(String, Sink)], topic: &str) -> Sink {
+    routes.iter()
+        .find(|(t, _)| topic == t || topic.rsplit('/').next() == Some(t.as_str()))
+        .map_or(Sink::Trades, |(_, sink)| *sink)
+}
+
+/// Where quote payloads are stored: the `QuoteStore`.
+pub trait QuoteSink {
+    async fn store(&mut self, v: &serde_json::Value) -> Result<u64, tokio_postgres::Error>;
+}
+
+impl QuoteSink for QuoteStore {
+    async fn store(&mut self, v: &serde_json::Value) -> Result<u64, tokio_postgres::Error> {
+        QuoteStore::store(self, v).await
+    }
+}
+
+const INSERT_QUOTE: &str = "INSERT INTO quotes (ts_ms, symbol, bid_u, bid_qty_u, ask_u, ask_qty_u) VALUES ($1,$2,$3,$4,$5,$6)";
+
+/// Writes quote payloads to Postgres through a statement prepared once per connection.
+pub struct QuoteStore {
+    db: Pg,
+    /// `db` generation `pg` belongs to.
+    generation: u64,
+    pg: Arc<tokio_postgres::Client>,
+    insert: tokio_postgres::Statement,
+    max_retries: u32,
+}
+
+impl QuoteStore {
+    pub async fn new(db: &Pg, max_retries: u32) -> Result<Self, tokio_postgres::Error> {
+        let (generation, pg) = db.client().await;
+        let insert = pg.prepare(INSERT_QUOTE).await?;
+        Ok(Self { db: db.clone(), generation, pg, insert, max_retries })
+    }
+
+    /// Inserts one quote; a missing side is stored as NULL. Transient errors are
+    /// retried, and a closed connection is replaced once; an `Err` means the quote was
+    /// not stored.
+    pub async fn store(&mut self, v: &serde_json::Value) -> Result<u64, tokio_postgres::Error> {
+        let field = |k: &str| v.get(k).and_then(|x| x.as_i64());
+        let ts = field("ts_ms").unwrap_or(0);
+        let symbol = v.get("symbol").and_then(|x| x.as_str()).unwrap_or("");
+        let (bid, bid_qty, ask, ask_qty) = (field("bid_u"), field("bid_qty_u"), field("ask_u"), field("ask_qty_u"));
+        let params: [&(dyn ToSql + Sync); 6] = [&ts, &symbol, &bid, &bid_qty, &ask, &ask_qty];
+        match with_retry(self.max_retries, || self.pg.execute(&self.insert, &params)).await {
+            Err(e) if is_disconnect(&e) => {
+                let total = errors::record(ErrorCategory::Connect);
+                warn!(?e, category = %ErrorCategory::Connect, errors_total = total, "postgres connection closed, reconnecting");
+                (self.generation, self.pg) = self.db.reconnect(self.generation).await?;
+                self.insert = self.pg.prepare(INSERT_QUOTE).await?;
+                with_retry(self.max_retries, || self.pg.execute(&self.insert, &params)).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_sinks_route_by_full_or_short_name() {
+        assert_eq!(parse_topic_sinks(None, "gemini.trades").unwrap(), vec![("gemini.trades".to_string(), Sink::Trades)]);
+
+        let routes = parse_topic_sinks(Some("gemini.trades:trades, gemini.quotes:QUOTES"), "unused").unwrap();
+        assert_eq!(sink_for(&routes, "gemini.trades"), Sink::Trades);
+        assert_eq!(sink_for(&routes, "gemini.quotes"), Sink::Quotes);
+        assert_eq!(sink_for(&routes, "persistent://public/default/gemini.quotes"), Sink::Quotes, "pulsar topic name");
+        assert_eq!(sink_for(&routes, "other"), Sink::Trades, "unlisted");
+
+        assert!(parse_topic_sinks(Some("gemini.quotes:book"), "unused").is_err());
+        assert!(parse_topic_sinks(Some(" , "), "unused").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use serde::Serialize;
+use shared::config;
+use shared::errors::{self, ErrorCategory};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub symbol: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: f64,
+    pub ts_ms: u64,
+}
+
+impl Alert {
+    pub fn now(symbol: &str, kind: &str, value: f64) -> Self {
+        let ts_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        Self { symbol: symbol.to_string(), kind: kind.to_string(), value, ts_ms }
+    }
+}
+
+/// Destination for operational alerts. `send` must not block the feed loops, so
+/// network sinks deliver in the background.
+pub trait AlertSink: Send + Sync {
+    fn send(&self, alert: Alert);
+}
+
+pub struct NoopSink;
+
+impl AlertSink for NoopSink {
+    fn send(&self, _alert: Alert) {}
+}
+
+/// Writes each alert as one JSON line on stdout.
+pub struct StdoutSink;
+
+impl AlertSink for StdoutSink {
+    fn send(&self, alert: Alert) {
+        if let Ok(line) = serde_json::to_string(&alert) { println!("{}", line); }
+    }
+}
+
+/// POSTs each alert as JSON to a webhook (Slack/PagerDuty relays, generic receivers).
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl AlertSink for WebhookSink {
+    fn send(&self, alert: Alert) {
+        let (client, url) = (self.client.clone(), self.url.clone());
+        tokio::spawn(async move {
+            match client.post(&url).json(&alert).send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => {}
+                Err(e) => {
+                    let total = errors::record(ErrorCategory::Produce);
+                    warn!(category = %ErrorCategory::Produce, errors_total = total, "⚠️  Failed to deliver {} alert to webhook: {}", alert.kind, e);
+                }
+            }
+        });
+    }
+}
+
+/// Builds the sink selected by `ALERT_SINK`: `none` (default), `stdout`, or `webhook`
+/// (POST to `ALERT_WEBHOOK_URL`).
+pub fn from_env() -> anyhow::Result<Arc<dyn AlertSink>> {
+    let kind = config::var("ALERT_SINK").unwrap_or_else(|_| "none".to_string());
+    let sink: Arc<dyn AlertSink> = match kind.as_str() {
+        "none" => Arc::new(NoopSink),
+        "stdout" => Arc::new(StdoutSink),
+        "webhook" => {
+            let url = config::var("ALERT_WEBHOOK_URL").map_err(|_| anyhow::anyhow!("ALERT_SINK=webhook requires ALERT_WEBHOOK_URL"))?;
+            Arc::new(WebhookSink { client: reqwest::Client::new(), url })
+        }
+        other => anyhow::bail!("unknown ALERT_SINK '{}' (expected none, stdout or webhook)", other),
+    };
+    info!("🚨 Alert sink: {}", kind);
+    Ok(sink)
+}
+
+/// Liquidity withdrawal alarm: fires one `thin_book` alert (value = the thinner side's
+/// active level count) once either side has stayed below `min_levels` for `debounce`,
+/// then stays quiet until both sides are back at or above the minimum.
+pub struct ThinBookAlarm {
+    min_levels: usize,
+    debounce: Duration,
+    below_since: Option<Instant>,
+    fired: bool,
+}
+
+impl ThinBookAlarm {
+    pub fn new(min_levels: usize, debounce: Duration) -> Self {
+        Self { min_levels, debounce, below_since: None, fired: false }
+    }
+
+    /// Checks the current active level counts; returns the alert to send, if any.
+    pub fn observe(&mut self, symbol: &str, bid_levels: usize, ask_levels: usize, now: Instant) -> Option<Alert> {
+        let thinnest = bid_levels.min(ask_levels);
+        if thinnest >= self.min_levels {
+            if self.fired {
+                info!("🌊 {} book back to {} bid / {} ask levels", symbol, bid_levels, ask_levels);
+            }
+            self.below_since = None;
+            self.fired = false;
+            return None;
+        }
+        let since = *self.below_since.get_or_insert(now);
+        if self.fired || now.duration_since(since) < self.debounce {
+            return None;
+        }
+        self.fired = true;
+        Some(Alert::now(symbol, "thin_book", thinnest as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Accepts one request, answers 200 and returns its request line and JSON body.
+    async fn webhook_receiver(listener: TcpListener) -> (String, serde_json::Value) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        let (head, body_start, len) = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&request[..end]).to_string();
+                let len = head.lines().find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap())).unwrap();
+                break (head, end + 4, len);
+            }
+        };
+        while request.len() < body_start + len {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await.unwrap();
+        let request_line = head.lines().next().unwrap().to_string();
+        (request_line, serde_json::from_slice(&request[body_start..body_start + len]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_the_alert_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks/market", listener.local_addr().unwrap());
+        let received = tokio::spawn(webhook_receiver(listener));
+        let sink = WebhookSink { client: reqwest::Client::new(), url };
+        sink.send(Alert { symbol: "SOLUSD".to_string(), kind: "v1_sequence_gap".to_string(), value: 3.0, ts_ms: 1_700_000_000_000 });
+
+        let (request_line, body) = tokio::time::timeout(Duration::from_secs(5), received).await.unwrap().unwrap();
+        assert_eq!(request_line, "POST /hooks/market HTTP/1.1");
+        assert_eq!(body, serde_json::json!({"symbol": "SOLUSD", "type": "v1_sequence_gap", "value": 3.0, "ts_ms": 1_700_000_000_000u64}));
+    }
+
+    #[test]
+    fn thin_book_fires_once_per_episode_after_debounce() {
+        let mut alarm = ThinBookAlarm::new(5, Duration::from_secs(2));
+        let t0 = Instant::now();
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+        assert!(alarm.observe("SOLUSD", 10, 10, at(0)).is_none());
+        assert!(alarm.observe("SOLUSD", 10, 3, at(100)).is_none(), "still debouncing");
+        assert!(alarm.observe("SOLUSD", 10, 4, at(1_000)).is_none());
+        let alert = alarm.observe("SOLUSD", 10, 2, at(2_100)).expect("thin for the full debounce");
+        assert_eq!((alert.symbol.as_str(), alert.kind.as_str(), alert.value), ("SOLUSD", "thin_book", 2.0));
+        for ms in [2_200, 5_000, 60_000] {
+            assert!(alarm.observe("SOLUSD", 1, 1, at(ms)).is_none(), "fires once per episode");
+        }
+        // Recovery ends the episode; the next one debounces afresh
+        assert!(alarm.observe("SOLUSD", 5, 5, at(61_000)).is_none());
+        assert!(alarm.observe("SOLUSD", 4, 5, at(62_000)).is_none());
+        assert!(alarm.observe("SOLUSD", 4, 5, at(64_000)).is_some());
+    }
+
+    #[test]
+    fn brief_dip_does_not_fire() {
+        let mut alarm = ThinBookAlarm::new(5, Duration::from_secs(2));
+        let t0 = Instant::now();
+        assert!(alarm.observe("SOLUSD", 1, 9, t0).is_none());
+        assert!(alarm.observe("SOLUSD", 9, 9, t0 + Duration::from_secs(1)).is_none());
+        assert!(alarm.observe("SOLUSD", 1, 9, t0 + Duration::from_secs(3)).is_none(), "the dip restarted the debounce");
+    }
+}
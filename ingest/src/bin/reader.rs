@@ -1,122 +1,952 @@
 use anyhow::Result;
-use shared::{OrderBook, TopOfBook, BOOK_DEPTH};
+use shared::analytics::{cumulative_qty, depth_imbalance, microprice, trend};
+use shared::poll::PollBackoff;
+use shared::{config, format, BookMeta, OrderBook, TopOfBook, TopOfBookSnapshot, BOOK_DEPTH, TS_DISABLED};
+use std::collections::VecDeque;
 use std::path::Path;
 use std::ptr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-fn format_price(price_u: u64) -> String {
-    if price_u == 0 {
-        "0.000000".to_string()
+fn print_meta(meta: &BookMeta) {
+    if meta.symbol().is_empty() {
+        println!("Metadata: not populated (assuming micro units)");
     } else {
-        format!("{:.6}", price_u as f64 / 1_000_000.0)
+        println!("Symbol:   {} (price x{}, qty x{}, tick {}, depth {})",
+                 meta.symbol(), meta.price_scale, meta.qty_scale, meta.tick_size, meta.depth);
     }
 }
 
-fn format_qty(qty_u: u64) -> String {
-    if qty_u == 0 {
-        "0.000000".to_string()
-    } else {
-        format!("{:.6}", qty_u as f64 / 1_000_000.0)
-    }
+/// Allowed clock skew before a timestamp counts as "in the future".
+const FUTURE_SKEW_MS: u64 = 5_000;
+
+/// Renders a file timestamp with its age. Zero is the never-written sentinel and
+/// `TS_DISABLED` marks a symbol stopped by ingest's kill switch; a timestamp further
+/// ahead than `FUTURE_SKEW_MS` or older than `max_age_secs` is garbage rather than a
+/// real update, so it's flagged instead of shown as an age.
+fn format_timestamp(ts_ms: u64, max_age_secs: u64) -> String {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    format_timestamp_at(ts_ms, max_age_secs, now_ms)
 }
 
-fn format_timestamp(ts_ms: u64) -> String {
+fn format_timestamp_at(ts_ms: u64, max_age_secs: u64, now_ms: u64) -> String {
     if ts_ms == 0 {
-        "no timestamp".to_string()
-    } else {
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        let age_ms = if now_ms > ts_ms { now_ms - ts_ms } else { 0 };
-        format!("{} ({:.1}s ago)", ts_ms, age_ms as f64 / 1000.0)
+        return "no timestamp (never written)".to_string();
+    }
+    if ts_ms == TS_DISABLED {
+        return "DISABLED (kill switch)".to_string();
+    }
+    if ts_ms > now_ms.saturating_add(FUTURE_SKEW_MS) {
+        return format!("{} (invalid: in the future)", ts_ms);
     }
+    let age_ms = now_ms.saturating_sub(ts_ms);
+    if age_ms > max_age_secs.saturating_mul(1000) {
+        return format!("{} (invalid: older than {}s)", ts_ms, max_age_secs);
+    }
+    format!("{} ({:.1}s ago)", ts_ms, age_ms as f64 / 1000.0)
 }
 
-fn main() -> Result<()> {
-    let ob_path = std::env::var("OB_MMAP")
-        .unwrap_or_else(|_| "/dev/shm/solusd_order_book.mmap".to_string());
-    let tob_path = std::env::var("TOB_MMAP")
-        .unwrap_or_else(|_| "/dev/shm/solusd_top_of_book.mmap".to_string());
+struct Args {
+    /// Ladder levels to print, 1..=BOOK_DEPTH.
+    levels: usize,
+    /// Decimal places for spread in bps.
+    bps_decimals: usize,
+    /// Multi-symbol mode: summarize these symbols' files under `dir`.
+    symbols: Vec<String>,
+    dir: String,
+    /// In multi-symbol mode, also print each symbol's ladder.
+    ladders: bool,
+    /// Repeat the multi-symbol summary at this interval until Ctrl-C.
+    refresh_ms: Option<u64>,
+    /// When refreshing, print each symbol's ladder at most once per this many cycles.
+    ladder_every: u64,
+    /// Add running-sum quantity columns to the ladder.
+    cumulative: bool,
+    /// Print the order book as CSV rows (`side,level,price,qty`) and nothing else.
+    table: bool,
+    /// Sample the top of book into this CSV every `interval_ms` until stopped (or `samples` rows).
+    csv_out: Option<String>,
+    interval_ms: u64,
+    samples: Option<u64>,
+    /// Ages above this render as invalid.
+    max_age_secs: u64,
+    /// Print the top of book on every change until Ctrl-C.
+    watch: bool,
+    /// Longest idle sleep between watch polls; 0 spins.
+    max_poll_us: u64,
+    /// Compare the book with the state file left by the previous `--diff` run.
+    diff: bool,
+    diff_state: String,
+    /// Print microprice and depth imbalance with trend arrows every `interval_ms`.
+    microprice: bool,
+    /// Recent reads the trend arrows are computed over.
+    trend_samples: usize,
+    /// Wait this long for the writer's heartbeat to advance, then report alive or dead.
+    liveness_ms: Option<u64>,
+    /// List the snapshots kept in this snapshot ring file.
+    ring: Option<String>,
+}
 
-    println!("📊 SOLUSD Market Data Reader");
-    println!("═══════════════════════════");
-    println!("Order Book: {}", ob_path);
-    println!("Top of Book: {}", tob_path);
-    println!();
+impl Default for Args {
+    fn default() -> Self {
+        Args { levels: 10, bps_decimals: 2, symbols: Vec::new(), dir: "/dev/shm".to_string(), ladders: false, refresh_ms: None, ladder_every: 1, cumulative: false, table: false,
+               csv_out: None, interval_ms: 1000, samples: None, max_age_secs: 86_400,
+               watch: false, max_poll_us: 1_000, diff: false, diff_state: "/tmp/solusd_reader_diff.json".to_string(),
+               microprice: false, trend_samples: 10, liveness_ms: None, ring: None }
+    }
+}
 
-    // Read Top of Book
-    if Path::new(&tob_path).exists() {
-        let (_tob_mmap, tob) = TopOfBook::mmap(Path::new(&tob_path))?;
-        let bid_price = unsafe { ptr::read_volatile(&tob.bid_price) };
-        let bid_qty = unsafe { ptr::read_volatile(&tob.bid_qty) };
-        let ask_price = unsafe { ptr::read_volatile(&tob.ask_price) };
-        let ask_qty = unsafe { ptr::read_volatile(&tob.ask_qty) };
-        let timestamp = unsafe { ptr::read_volatile(&tob.timestamp_ms) };
+fn parse_args() -> Result<Args> {
+    let mut args = Args::default();
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--levels" => {
+                let n: usize = it.next().ok_or_else(|| anyhow::anyhow!("--levels needs a value"))?.parse()?;
+                if n == 0 || n > BOOK_DEPTH {
+                    anyhow::bail!("--levels must be between 1 and {} (stored depth)", BOOK_DEPTH);
+                }
+                args.levels = n;
+            }
+            "--bps-decimals" => {
+                args.bps_decimals = it.next().ok_or_else(|| anyhow::anyhow!("--bps-decimals needs a value"))?.parse()?;
+            }
+            "--symbols" => {
+                let list = it.next().ok_or_else(|| anyhow::anyhow!("--symbols needs a comma-separated list"))?;
+                args.symbols = list.split(',').map(|s| s.trim().to_uppercase()).filter(|s| !s.is_empty()).collect();
+            }
+            "--dir" => args.dir = it.next().ok_or_else(|| anyhow::anyhow!("--dir needs a path"))?,
+            "--ladders" => args.ladders = true,
+            "--refresh-ms" => args.refresh_ms = Some(it.next().ok_or_else(|| anyhow::anyhow!("--refresh-ms needs a value"))?.parse::<u64>()?.max(1)),
+            "--ladder-every" => {
+                args.ladder_every = it.next().ok_or_else(|| anyhow::anyhow!("--ladder-every needs a value"))?.parse::<u64>()?.max(1);
+            }
+            "--cumulative" => args.cumulative = true,
+            "--table" => args.table = true,
+            "--csv-out" => args.csv_out = Some(it.next().ok_or_else(|| anyhow::anyhow!("--csv-out needs a path"))?),
+            "--interval-ms" => {
+                args.interval_ms = it.next().ok_or_else(|| anyhow::anyhow!("--interval-ms needs a value"))?.parse::<u64>()?.max(1);
+            }
+            "--samples" => args.samples = Some(it.next().ok_or_else(|| anyhow::anyhow!("--samples needs a value"))?.parse()?),
+            "--watch" => args.watch = true,
+            "--diff" => args.diff = true,
+            "--diff-state" => args.diff_state = it.next().ok_or_else(|| anyhow::anyhow!("--diff-state needs a path"))?,
+            "--microprice" => args.microprice = true,
+            "--ring" => args.ring = Some(it.next().ok_or_else(|| anyhow::anyhow!("--ring needs a path"))?),
+            "--liveness" => args.liveness_ms = Some(it.next().ok_or_else(|| anyhow::anyhow!("--liveness needs a wait in ms"))?.parse()?),
+            "--trend-samples" => {
+                args.trend_samples = it.next().ok_or_else(|| anyhow::anyhow!("--trend-samples needs a value"))?.parse::<usize>()?.max(2);
+            }
+            "--max-poll-us" => {
+                args.max_poll_us = it.next().ok_or_else(|| anyhow::anyhow!("--max-poll-us needs a value"))?.parse()?;
+            }
+            "--max-age-secs" => {
+                args.max_age_secs = it.next().ok_or_else(|| anyhow::anyhow!("--max-age-secs needs a value"))?.parse()?;
+            }
+            other => anyhow::bail!("unknown argument '{}' (usage: reader [--levels N] [--bps-decimals N] [--cumulative] [--table] [--csv-out PATH [--interval-ms N] [--samples N]] [--watch [--max-poll-us N]] [--diff [--diff-state PATH]] [--microprice [--trend-samples N] [--interval-ms N] [--samples N]] [--max-age-secs N] [--liveness MS] [--ring PATH] [--symbols A,B --dir DIR [--ladders] [--refresh-ms N [--ladder-every K]]])", other),
+        }
+    }
+    Ok(args)
+}
 
-        println!("🏆 TOP OF BOOK");
-        println!("──────────────");
-        println!("Best Bid: {} @ {}", format_price(bid_price), format_qty(bid_qty));
-        println!("Best Ask: {} @ {}", format_price(ask_price), format_qty(ask_qty));
-        if bid_price > 0 && ask_price > 0 {
-            let spread = ask_price as f64 - bid_price as f64;
-            let mid = (bid_price as f64 + ask_price as f64) / 2.0;
-            println!("Spread:   {:.6} ({:.2} bps)", spread / 1_000_000.0, (spread / mid) * 10_000.0);
+/// Full re-reads before a crossed or torn book is reported as inconsistent.
+const READ_ATTEMPTS: u32 = 5;
+/// Seqlock spins per read.
+const SEQ_SPINS: u32 = 1_000;
+
+fn crossed(bid_price: u64, ask_price: u64) -> bool {
+    bid_price > 0 && ask_price > 0 && bid_price >= ask_price
+}
+
+/// Re-reads the book until the copy is untorn and uncrossed; `Err` says why the last
+/// of `READ_ATTEMPTS` reads still wasn't.
+fn consistent_book(mapped: &OrderBook) -> std::result::Result<OrderBook, String> {
+    let mut diagnostic = String::new();
+    for _ in 0..READ_ATTEMPTS {
+        match mapped.try_snapshot(SEQ_SPINS) {
+            None => diagnostic = format!("writer mid-update (seq {})", unsafe { ptr::read_volatile(&mapped.seq) }),
+            Some(book) if crossed(book.best_bid().unwrap_or_default().price, book.best_ask().unwrap_or_default().price) => {
+                let (price_scale, _) = book.meta.scales();
+                let (bid, ask) = (book.best_bid().unwrap_or_default(), book.best_ask().unwrap_or_default());
+                diagnostic = format!("crossed book: best bid {} >= best ask {} (seq {})",
+                                     format::scaled(bid.price, price_scale), format::scaled(ask.price, price_scale), book.seq);
+            }
+            Some(book) => return Ok(book),
         }
-        println!("Updated:  {}", format_timestamp(timestamp));
-        println!();
-    } else {
-        println!("❌ Top of Book file not found: {}", tob_path);
-        println!();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    Err(diagnostic)
+}
+
+/// Re-reads the quote while it is crossed, returning the last read and, if it is still
+/// crossed after `READ_ATTEMPTS` reads, why it is inconsistent.
+fn consistent_quote(tob: &TopOfBook) -> (TopOfBookSnapshot, Option<String>) {
+    let mut snap = tob.snapshot();
+    for _ in 1..READ_ATTEMPTS {
+        if !crossed(snap.bid_price, snap.ask_price) { break; }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        snap = tob.snapshot();
     }
+    let (price_scale, _) = tob.meta().scales();
+    let diagnostic = crossed(snap.bid_price, snap.ask_price).then(|| format!("crossed quote, bid {} >= ask {} (seq {})",
+        format::scaled(snap.bid_price, price_scale), format::scaled(snap.ask_price, price_scale), snap.seq));
+    (snap, diagnostic)
+}
+
+/// Populated bid and ask levels beyond the first `shown`, which the ladder leaves out.
+fn hidden_levels(ob: &OrderBook, shown: usize) -> (usize, usize) {
+    (ob.bids_iter().filter(|&(i, _, _)| i >= shown).count(), ob.asks_iter().filter(|&(i, _, _)| i >= shown).count())
+}
 
-    // Read Order Book
-    if Path::new(&ob_path).exists() {
-        let (_ob_mmap, ob) = OrderBook::mmap(Path::new(&ob_path))?;
-        let timestamp = unsafe { ptr::read_volatile(&ob.timestamp_ms) };
+/// Prints the ladder and level stats for the `OrderBook` file at `ob_path`.
+fn print_order_book(ob_path: &str, args: &Args) -> Result<()> {
+    if Path::new(ob_path).exists() {
+        let (_ob_mmap, mapped) = OrderBook::mmap_readonly(Path::new(ob_path))?;
+        // An inconsistent book is reported instead of printing a misleading ladder
+        let book = match consistent_book(mapped) {
+            Ok(book) => book,
+            Err(diagnostic) => {
+                println!("⚠️  ORDER BOOK INCONSISTENT after {} reads: {}", READ_ATTEMPTS, diagnostic);
+                println!();
+                return Ok(());
+            }
+        };
+        let ob = &book;
+        let timestamp = ob.timestamp_ms;
+        let meta = ob.meta;
+        let (price_scale, qty_scale) = meta.scales();
 
-        println!("📈 ORDER BOOK (First 10 levels)");
+        println!("📈 ORDER BOOK (First {} of {} levels)", args.levels, BOOK_DEPTH);
         println!("───────────────────────────────");
-        println!("Updated: {}", format_timestamp(timestamp));
+        print_meta(&meta);
+        println!("Updated: {}", format_timestamp(timestamp, args.max_age_secs));
         println!();
-        println!("{:>3} {:>12} {:>12} | {:>12} {:>12} {:>3}", 
-                 "Lvl", "Bid Size", "Bid Price", "Ask Price", "Ask Size", "Lvl");
-        println!("{}", "─".repeat(65));
+        if args.cumulative {
+            println!("{:>3} {:>12} {:>12} {:>12} | {:>12} {:>12} {:>12} {:>3}",
+                     "Lvl", "Bid Cum", "Bid Size", "Bid Price", "Ask Price", "Ask Size", "Ask Cum", "Lvl");
+            println!("{}", "─".repeat(91));
+        } else {
+            println!("{:>3} {:>12} {:>12} | {:>12} {:>12} {:>3}", 
+                     "Lvl", "Bid Size", "Bid Price", "Ask Price", "Ask Size", "Lvl");
+            println!("{}", "─".repeat(65));
+        }
+
+        let levels_to_show = args.levels;
+        let bid_cum = cumulative_qty(&ob.bids[..levels_to_show]);
+        let ask_cum = cumulative_qty(&ob.asks[..levels_to_show]);
 
-        let levels_to_show = std::cmp::min(10, BOOK_DEPTH);
-        
         for i in 0..levels_to_show {
             let bid_price = ob.bids[i].load_price();
             let bid_qty = ob.bids[i].load_qty();
             let ask_price = ob.asks[i].load_price();
             let ask_qty = ob.asks[i].load_qty();
 
-            let bid_price_str = if bid_price > 0 { format_price(bid_price) } else { "".to_string() };
-            let bid_qty_str = if bid_qty > 0 { format_qty(bid_qty) } else { "".to_string() };
-            let ask_price_str = if ask_price > 0 { format_price(ask_price) } else { "".to_string() };
-            let ask_qty_str = if ask_qty > 0 { format_qty(ask_qty) } else { "".to_string() };
+            let bid_price_str = if bid_price > 0 { format::scaled(bid_price, price_scale) } else { "".to_string() };
+            let bid_qty_str = if bid_qty > 0 { format::scaled(bid_qty, qty_scale) } else { "".to_string() };
+            let ask_price_str = if ask_price > 0 { format::scaled(ask_price, price_scale) } else { "".to_string() };
+            let ask_qty_str = if ask_qty > 0 { format::scaled(ask_qty, qty_scale) } else { "".to_string() };
 
             let lvl_str = if bid_price > 0 || ask_price > 0 { (i + 1).to_string() } else { "".to_string() };
 
-            println!("{:>3} {:>12} {:>12} | {:>12} {:>12} {:>3}", 
-                     lvl_str, bid_qty_str, bid_price_str, ask_price_str, ask_qty_str, lvl_str);
+            if args.cumulative {
+                let bid_cum_str = if bid_price > 0 { format::scaled(bid_cum[i], qty_scale) } else { "".to_string() };
+                let ask_cum_str = if ask_price > 0 { format::scaled(ask_cum[i], qty_scale) } else { "".to_string() };
+                println!("{:>3} {:>12} {:>12} {:>12} | {:>12} {:>12} {:>12} {:>3}",
+                         lvl_str, bid_cum_str, bid_qty_str, bid_price_str, ask_price_str, ask_qty_str, ask_cum_str, lvl_str);
+            } else {
+                println!("{:>3} {:>12} {:>12} | {:>12} {:>12} {:>3}", 
+                         lvl_str, bid_qty_str, bid_price_str, ask_price_str, ask_qty_str, lvl_str);
+            }
+        }
+        let (hidden_bids, hidden_asks) = hidden_levels(ob, levels_to_show);
+        if hidden_bids > 0 || hidden_asks > 0 {
+            println!("… {} more bid / {} more ask levels (use --levels up to {})", hidden_bids, hidden_asks, BOOK_DEPTH);
         }
         println!();
 
         // Summary stats
-        let mut active_bid_levels = 0;
-        let mut active_ask_levels = 0;
-        for i in 0..BOOK_DEPTH {
-            if ob.bids[i].load_price() > 0 { active_bid_levels += 1; }
-            if ob.asks[i].load_price() > 0 { active_ask_levels += 1; }
-        }
+        let (active_bid_levels, active_ask_levels) = ob.active_levels();
         println!("📊 BOOK STATS");
         println!("─────────────");
         println!("Active bid levels: {}/{}", active_bid_levels, BOOK_DEPTH);
         println!("Active ask levels: {}/{}", active_ask_levels, BOOK_DEPTH);
+        println!("Status: {}", ob.status());
+        if let Some(imbalance) = ob.imbalance(levels_to_show) {
+            println!("Imbalance (top {}): {:+.3}", levels_to_show, imbalance);
+        }
+        if let (Some(mid), Some(spread)) = (ob.mid_price_u(), ob.spread_u()) {
+            println!("Mid: {}  Spread: {} ({} bps)", format::scaled(mid, price_scale), format::scaled(spread, price_scale),
+                     format::bps(spread as f64 / mid as f64 * 10_000.0, args.bps_decimals));
+        }
     } else {
         println!("❌ Order Book file not found: {}", ob_path);
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// `--table`: the book as CSV for pandas and friends, from a consistent copy.
+fn print_table(ob_path: &str) -> Result<()> {
+    if !Path::new(ob_path).exists() {
+        anyhow::bail!("Order Book file not found: {}", ob_path);
+    }
+    let (_ob_mmap, mapped) = OrderBook::mmap_readonly(Path::new(ob_path))?;
+    let book = (0..READ_ATTEMPTS).find_map(|_| mapped.try_snapshot(SEQ_SPINS))
+        .ok_or_else(|| anyhow::anyhow!("order book still mid-update after {} reads", READ_ATTEMPTS))?;
+    println!("side,level,price,qty");
+    for (side, level, price, qty) in book.to_rows() {
+        println!("{},{},{},{}", side, level, price, qty);
+    }
+    Ok(())
+}
+
+/// `--ring`: one line per snapshot retained in ingest's snapshot ring, oldest first,
+/// then the newest one's top levels.
+fn print_ring(path: &str, args: &Args) -> Result<()> {
+    let books = shared::ring::load(Path::new(path))?;
+    let Some(newest) = books.last() else {
+        println!("Snapshot ring {} is empty", path);
+        return Ok(());
+    };
+    println!("💾 {} snapshots in {}", books.len(), path);
+    for book in &books {
+        let (price_scale, _) = book.meta.scales();
+        let price = |lvl: Option<shared::OrderLevel>| lvl.map_or("-".to_string(), |l| format::scaled(l.price, price_scale));
+        let (bid_lvls, ask_lvls) = book.active_levels();
+        println!("{}  seq {:>10}  bid {:>14}  ask {:>14}  {:>2}/{:<2} levels  {}",
+                 format_timestamp(book.timestamp_ms, args.max_age_secs), book.seq, price(book.best_bid()), price(book.best_ask()), bid_lvls, ask_lvls, book.status());
+    }
+    println!();
+    println!("Newest: {:?}", newest);
+    Ok(())
+}
+
+/// Prints the top of book whenever its seqlock moves. Polling backs off from spinning
+/// to sleeps of up to `--max-poll-us` while the quote is idle, and tightens again on
+/// the next change.
+fn watch(tob_path: &str, args: &Args) -> Result<()> {
+    if !Path::new(tob_path).exists() {
+        anyhow::bail!("Top of Book file not found: {}", tob_path);
+    }
+    let (_tob_mmap, tob) = TopOfBook::mmap_readonly(Path::new(tob_path))?;
+    let mut backoff = PollBackoff::new(std::time::Duration::from_micros(args.max_poll_us));
+    // Printed from the held snapshot: `Display` on `tob` would read a newer one
+    let mut prev = tob.snapshot();
+    println!("{} @ {}", prev.line(&tob.meta()), prev.timestamp_ms);
+    loop {
+        if tob.changed_since(&prev) {
+            prev = tob.snapshot();
+            println!("{} @ {}", prev.line(&tob.meta()), prev.timestamp_ms);
+            backoff.reset();
+        } else {
+            backoff.idle();
+        }
+    }
+}
+
+/// Per-level change markers for `--diff`: `+` new price, `-` removed price, `~` size
+/// changed (old -> new), blank unchanged. Levels are matched by price, so a level that
+/// shifted position but kept its price and size is unchanged.
+fn diff_side(old: &[(u64, u64)], new: &[(u64, u64)], ascending: bool, price_scale: u64, qty_scale: u64) -> Vec<String> {
+    let mut prices: Vec<u64> = old.iter().chain(new).map(|&(p, _)| p).collect();
+    prices.sort_unstable();
+    prices.dedup();
+    if !ascending { prices.reverse(); }
+    prices.into_iter().map(|price| {
+        let before = old.iter().find(|&&(p, _)| p == price).map(|&(_, q)| q);
+        let after = new.iter().find(|&&(p, _)| p == price).map(|&(_, q)| q);
+        let price = format::scaled(price, price_scale);
+        match (before, after) {
+            (None, Some(q)) => format!("  + {:>12} {:>12}", price, format::scaled(q, qty_scale)),
+            (Some(q), None) => format!("  - {:>12} {:>12}", price, format::scaled(q, qty_scale)),
+            (Some(a), Some(b)) if a != b => format!("  ~ {:>12} {:>12} -> {}", price, format::scaled(a, qty_scale), format::scaled(b, qty_scale)),
+            _ => format!("    {:>12} {:>12}", price, format::scaled(after.unwrap_or(0), qty_scale)),
+        }
+    }).collect()
+}
+
+/// `--diff`: prints the top `--levels` of each side marked against the state saved by
+/// the previous run (everything is new when there is none), then saves this book.
+fn print_diff(ob_path: &str, args: &Args) -> Result<()> {
+    if !Path::new(ob_path).exists() {
+        anyhow::bail!("Order Book file not found: {}", ob_path);
+    }
+    let (_ob_mmap, mapped) = OrderBook::mmap_readonly(Path::new(ob_path))?;
+    let book = (0..READ_ATTEMPTS).find_map(|_| mapped.try_snapshot(SEQ_SPINS))
+        .ok_or_else(|| anyhow::anyhow!("writer mid-update on every read, try again"))?;
+    let top = |(i, price, qty): (usize, u64, u64)| (i < args.levels).then_some((price, qty));
+    let bids: Vec<(u64, u64)> = book.bids_iter().filter_map(top).collect();
+    let asks: Vec<(u64, u64)> = book.asks_iter().filter_map(top).collect();
+    let previous: Option<serde_json::Value> = std::fs::read_to_string(&args.diff_state).ok().and_then(|s| serde_json::from_str(&s).ok());
+    let saved = |key: &str| -> Vec<(u64, u64)> {
+        previous.as_ref().and_then(|v| serde_json::from_value(v.get(key)?.clone()).ok()).unwrap_or_default()
+    };
+    let (price_scale, qty_scale) = book.meta.scales();
+    println!("🔀 ORDER BOOK DIFF (top {} levels) vs {}", args.levels,
+             if previous.is_some() { args.diff_state.as_str() } else { "no saved state, all levels new" });
+    println!("Updated: {}", format_timestamp(book.timestamp_ms, args.max_age_secs));
+    for (label, old, new, ascending) in [("Bids", saved("bids"), &bids, false), ("Asks", saved("asks"), &asks, true)] {
+        println!("{}:", label);
+        for line in diff_side(&old, new, ascending, price_scale, qty_scale) {
+            println!("{}", line);
+        }
+    }
+    std::fs::write(&args.diff_state, serde_json::json!({ "timestamp_ms": book.timestamp_ms, "bids": bids, "asks": asks }).to_string())?;
+    Ok(())
+}
+
+/// `--csv-out`: appends one top-of-book row per interval, writing the header only when
+/// the file is new or empty. Rows are flushed at least once a second, so stopping with
+/// Ctrl-C loses at most the last second.
+fn capture_csv(tob_path: &str, out: &str, args: &Args) -> Result<()> {
+    use std::io::Write;
+    if !Path::new(tob_path).exists() {
+        anyhow::bail!("Top of Book file not found: {}", tob_path);
+    }
+    let (_tob_mmap, tob) = TopOfBook::mmap_readonly(Path::new(tob_path))?;
+    let (price_scale, qty_scale) = tob.meta().scales();
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(out)?;
+    let fresh = file.metadata()?.len() == 0;
+    let mut w = std::io::BufWriter::new(file);
+    if fresh {
+        writeln!(w, "ts,bid,bidsz,ask,asksz,mid,spread_bps")?;
+    }
+    let interval = std::time::Duration::from_millis(args.interval_ms);
+    let mut last_flush = std::time::Instant::now();
+    let mut written = 0u64;
+    while args.samples.is_none_or(|n| written < n) {
+        let q = tob.snapshot();
+        let (mid, bps) = if q.bid_price > 0 && q.ask_price > 0 {
+            let mid = (q.bid_price + q.ask_price) as f64 / 2.0;
+            (format::scaled(mid.round() as u64, price_scale), format::bps((q.ask_price as f64 - q.bid_price as f64) / mid * 10_000.0, args.bps_decimals))
+        } else {
+            (String::new(), String::new())
+        };
+        writeln!(w, "{},{},{},{},{},{},{}", q.timestamp_ms,
+                 format::scaled(q.bid_price, price_scale), format::scaled(q.bid_qty, qty_scale),
+                 format::scaled(q.ask_price, price_scale), format::scaled(q.ask_qty, qty_scale), mid, bps)?;
+        written += 1;
+        if last_flush.elapsed() >= std::time::Duration::from_secs(1) {
+            w.flush()?;
+            last_flush = std::time::Instant::now();
+        }
+        if args.samples.is_none_or(|n| written < n) {
+            std::thread::sleep(interval);
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// `--microprice`: every `--interval-ms`, prints the size-weighted mid and top-level
+/// depth imbalance, each with an arrow for its trend over the last `--trend-samples`
+/// reads. A read with an empty side prints as such and restarts both trends, so a
+/// gap never bridges the quotes on either side of it.
+fn print_microprice(tob_path: &str, args: &Args) -> Result<()> {
+    if !Path::new(tob_path).exists() {
+        anyhow::bail!("Top of Book file not found: {}", tob_path);
+    }
+    let (_tob_mmap, tob) = TopOfBook::mmap_readonly(Path::new(tob_path))?;
+    let meta = tob.meta();
+    let (price_scale, _) = meta.scales();
+    // Moves within half a tick (or any move, tick unknown) and 5% imbalance are noise
+    let (price_flat, imbalance_flat) = (meta.tick_size as f64 / 2.0, 0.05);
+    let interval = std::time::Duration::from_millis(args.interval_ms);
+    let mut prices: VecDeque<f64> = VecDeque::with_capacity(args.trend_samples);
+    let mut imbalances: VecDeque<f64> = VecDeque::with_capacity(args.trend_samples);
+    let mut printed = 0u64;
+    while args.samples.is_none_or(|n| printed < n) {
+        let q = tob.snapshot();
+        match (microprice(q.bid_price, q.bid_qty, q.ask_price, q.ask_qty), depth_imbalance(q.bid_qty, q.ask_qty)) {
+            (Some(mp), Some(imb)) => {
+                if prices.len() == args.trend_samples {
+                    prices.pop_front();
+                    imbalances.pop_front();
+                }
+                prices.push_back(mp);
+                imbalances.push_back(imb);
+                let arrow = |samples: &VecDeque<f64>, flat: f64| {
+                    let (a, b) = samples.as_slices();
+                    trend(&[a, b].concat(), flat).map_or(" ", |t| t.arrow())
+                };
+                println!("{} microprice {} {}  imbalance {:+.3} {}", q.timestamp_ms,
+                         format::scaled(mp.round() as u64, price_scale), arrow(&prices, price_flat),
+                         imb, arrow(&imbalances, imbalance_flat));
+            }
+            _ => {
+                prices.clear();
+                imbalances.clear();
+                println!("{} book empty on one side, trend reset", q.timestamp_ms);
+            }
+        }
+        printed += 1;
+        if args.samples.is_none_or(|n| printed < n) {
+            std::thread::sleep(interval);
+        }
+    }
+    Ok(())
+}
+
+/// `--liveness`: watches the top-of-book heartbeat for `wait_ms` and fails if it never
+/// advances. Pick a wait of a few writer `HEARTBEAT_MS` periods; a quiet market still
+/// passes, a dead (or heartbeat-disabled) writer doesn't.
+fn check_liveness(tob_path: &str, wait_ms: u64) -> Result<()> {
+    if !Path::new(tob_path).exists() {
+        anyhow::bail!("Top of Book file not found: {}", tob_path);
+    }
+    let (_tob_mmap, tob) = TopOfBook::mmap_readonly(Path::new(tob_path))?;
+    let start = tob.heartbeat();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(wait_ms);
+    while std::time::Instant::now() < deadline {
+        let now = tob.heartbeat();
+        if now != start {
+            println!("💓 writer alive (heartbeat {} -> {})", start, now);
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    anyhow::bail!("writer dead: heartbeat stuck at {} for {}ms", start, wait_ms)
+}
+
+/// Per-symbol file paths in multi-symbol mode, following the default naming
+/// (`{dir}/{symbol}_order_book.mmap`, `{dir}/{symbol}_top_of_book.mmap`).
+fn symbol_paths(dir: &str, symbol: &str) -> (String, String) {
+    let dir = dir.trim_end_matches('/');
+    let sym = symbol.to_lowercase();
+    (format!("{}/{}_order_book.mmap", dir, sym), format!("{}/{}_top_of_book.mmap", dir, sym))
+}
+
+/// A symbol's files in multi-symbol mode, kept mapped across refreshes, and what was
+/// last read from them.
+struct SymbolView {
+    symbol: String,
+    ob_path: String,
+    tob_path: String,
+    tob: Option<(memmap2::Mmap, &'static TopOfBook)>,
+    ob: Option<(memmap2::Mmap, &'static OrderBook)>,
+    quote: Option<TopOfBookSnapshot>,
+    price_scale: u64,
+    /// Order book seq the level counts were read at, and the counts.
+    levels: Option<(u64, usize, usize)>,
+    /// Order book seq of the last printed ladder.
+    ladder_seq: Option<u64>,
+    /// Why a file couldn't be mapped on the last cycle; retried every cycle.
+    error: Option<String>,
+}
+
+impl SymbolView {
+    fn new(dir: &str, symbol: &str) -> Self {
+        let (ob_path, tob_path) = symbol_paths(dir, symbol);
+        Self { symbol: symbol.to_string(), ob_path, tob_path, tob: None, ob: None, quote: None, price_scale: 1_000_000, levels: None, ladder_seq: None, error: None }
+    }
+
+    /// Maps files that have appeared since the last cycle, then re-reads only what
+    /// changed: the quote when the top of book's seq moved, the level counts when the
+    /// order book's did. Returns whether anything was re-read. A file that can't be
+    /// mapped (wrong size, no permission) is recorded in `error` for this row only, so
+    /// one bad symbol doesn't stop the others.
+    fn refresh(&mut self) -> bool {
+        let mut errors = Vec::new();
+        if self.tob.is_none() && Path::new(&self.tob_path).exists() {
+            match TopOfBook::mmap_readonly(Path::new(&self.tob_path)) {
+                Ok(mapped) => self.tob = Some(mapped),
+                Err(e) => errors.push(format!("{}: {}", self.tob_path, e)),
+            }
+        }
+        if self.ob.is_none() && Path::new(&self.ob_path).exists() {
+            match OrderBook::mmap_readonly(Path::new(&self.ob_path)) {
+                Ok(mapped) => self.ob = Some(mapped),
+                Err(e) => errors.push(format!("{}: {}", self.ob_path, e)),
+            }
+        }
+        self.error = (!errors.is_empty()).then(|| errors.join("; "));
+        let mut fresh = false;
+        if let Some((_, tob)) = &self.tob {
+            if self.quote.is_none_or(|q| tob.changed_since(&q)) {
+                self.quote = Some(tob.snapshot());
+                self.price_scale = tob.meta().scales().0;
+                fresh = true;
+            }
+        }
+        if let Some((_, ob)) = &self.ob {
+            let seq = ob.load_seq();
+            if self.levels.is_none_or(|(at, _, _)| at != seq) {
+                let (b, a) = ob.active_levels();
+                self.levels = Some((seq, b, a));
+                fresh = true;
+            }
+        }
+        fresh
+    }
+
+    /// The summary row from the last read state. The Updated age keeps counting on
+    /// cached rows, which are marked as such; a mapping error is appended.
+    fn row(&self, fresh: bool, args: &Args) -> String {
+        let Some(TopOfBookSnapshot { bid_price, ask_price, timestamp_ms, .. }) = self.quote else {
+            return match &self.error {
+                Some(e) => format!("{:<10} error: {}", self.symbol, e),
+                None => format!("{:<10} no data ({} not found)", self.symbol, self.tob_path),
+            };
+        };
+        let bps = if bid_price > 0 && ask_price > 0 {
+            let mid = (bid_price as f64 + ask_price as f64) / 2.0;
+            format::bps(ask_price.saturating_sub(bid_price) as f64 / mid * 10_000.0, args.bps_decimals)
+        } else {
+            "-".to_string()
+        };
+        let (bid_lvls, ask_lvls) = match self.levels {
+            Some((_, b, a)) => (b.to_string(), a.to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        format!("{:<10} {:>14} {:>14} {:>10} {:>9} {:>9}  {}{}{}",
+                self.symbol, format::scaled(bid_price, self.price_scale), format::scaled(ask_price, self.price_scale), bps, bid_lvls, ask_lvls,
+                format_timestamp(timestamp_ms, args.max_age_secs), if fresh { "" } else { " (cached)" },
+                self.error.as_ref().map_or(String::new(), |e| format!("  error: {}", e)))
+    }
+}
+
+/// One summary row per symbol; a symbol without a top-of-book file shows "no data",
+/// one whose files can't be mapped shows the error, and the other rows still print.
+/// With `--refresh-ms` the summary repeats until Ctrl-C, re-reading only symbols whose
+/// files changed and showing the rest from the previous read. Ladders are spread over
+/// `--ladder-every` cycles (symbol `i` on cycles where `(cycle + i) % K == 0`) and
+/// skipped when the book hasn't changed since that symbol's last ladder.
+fn print_summary(args: &Args) -> Result<()> {
+    let mut views: Vec<SymbolView> = args.symbols.iter().map(|s| SymbolView::new(&args.dir, s)).collect();
+    let mut cycle: u64 = 0;
+    loop {
+        if cycle > 0 {
+            println!();
+        }
+        println!("📊 Market Data Summary ({})", args.dir);
+        println!("═══════════════════════════");
+        println!("{:<10} {:>14} {:>14} {:>10} {:>9} {:>9}  Updated", "Symbol", "Bid", "Ask", "Spread bps", "Bid lvls", "Ask lvls");
+        println!("{}", "─".repeat(90));
+        for view in views.iter_mut() {
+            let fresh = view.refresh();
+            println!("{}", view.row(fresh, args));
+        }
+        if args.ladders {
+            for (i, view) in views.iter_mut().enumerate() {
+                if cycle > 0 && !(cycle + i as u64).is_multiple_of(args.ladder_every) {
+                    continue;
+                }
+                let seq = view.ob.as_ref().map(|(_, ob)| ob.load_seq());
+                if seq.is_some() && seq == view.ladder_seq {
+                    continue;
+                }
+                println!();
+                println!("── {} ──", view.symbol);
+                if let Err(e) = print_order_book(&view.ob_path, args) {
+                    println!("error: {}", e);
+                }
+                view.ladder_seq = seq;
+            }
+        }
+        let Some(refresh_ms) = args.refresh_ms else { return Ok(()) };
+        cycle += 1;
+        std::thread::sleep(std::time::Duration::from_millis(refresh_ms));
+    }
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    if !args.symbols.is_empty() {
+        return print_summary(&args);
+    }
+    if let Some(ring) = &args.ring {
+        return print_ring(ring, &args);
+    }
+    let ob_path = config::var("OB_MMAP")
+        .unwrap_or_else(|_| "/dev/shm/solusd_order_book.mmap".to_string());
+    let tob_path = config::var("TOB_MMAP")
+        .unwrap_or_else(|_| "/dev/shm/solusd_top_of_book.mmap".to_string());
+    if args.table {
+        return print_table(&ob_path);
+    }
+    if let Some(out) = &args.csv_out {
+        return capture_csv(&tob_path, out, &args);
+    }
+    if args.watch {
+        return watch(&tob_path, &args);
+    }
+    if args.diff {
+        return print_diff(&ob_path, &args);
+    }
+    if args.microprice {
+        return print_microprice(&tob_path, &args);
+    }
+    if let Some(wait_ms) = args.liveness_ms {
+        return check_liveness(&tob_path, wait_ms);
+    }
+
+    println!("📊 SOLUSD Market Data Reader");
+    println!("═══════════════════════════");
+    println!("Order Book: {}", ob_path);
+    println!("Top of Book: {}", tob_path);
+    println!();
+
+    // Read Top of Book
+    if Path::new(&tob_path).exists() {
+        let (_tob_mmap, tob) = TopOfBook::mmap_readonly(Path::new(&tob_path))?;
+        let (snap, diagnostic) = consistent_quote(tob);
+        let TopOfBookSnapshot { bid_price, bid_qty, ask_price, ask_qty, timestamp_ms: timestamp, .. } = snap;
+        let meta = tob.meta();
+        let (price_scale, qty_scale) = meta.scales();
+
+        println!("🏆 TOP OF BOOK");
+        println!("──────────────");
+        print_meta(&meta);
+        if let Some(diagnostic) = diagnostic {
+            println!("⚠️  INCONSISTENT after {} reads: {}", READ_ATTEMPTS, diagnostic);
+        } else {
+            println!("Best Bid: {} @ {}", format::scaled(bid_price, price_scale), format::scaled(bid_qty, qty_scale));
+            println!("Best Ask: {} @ {}", format::scaled(ask_price, price_scale), format::scaled(ask_qty, qty_scale));
+            if bid_price > 0 && ask_price > 0 {
+                let spread = ask_price.saturating_sub(bid_price);
+                let mid = (bid_price as f64 + ask_price as f64) / 2.0;
+                println!("Spread:   {} ({} bps)", format::scaled(spread, price_scale), format::bps((spread as f64 / mid) * 10_000.0, args.bps_decimals));
+            }
+        }
+        println!("Updated:  {}", format_timestamp(timestamp, args.max_age_secs));
+        println!("Heartbeat: {}", tob.heartbeat());
+        println!();
+    } else {
+        println!("❌ Top of Book file not found: {}", tob_path);
+        println!();
+    }
+
+    print_order_book(&ob_path, &args)?;
+
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_is_not_never_written() {
+        let mut tob = TopOfBook::default();
+        tob.mark_disabled();
+        assert!(tob.is_disabled());
+        assert_eq!(format_timestamp(tob.snapshot().timestamp_ms, 60), "DISABLED (kill switch)");
+        assert_eq!(format_timestamp(0, 60), "no timestamp (never written)");
+    }
+
+    #[test]
+    fn bad_file_only_affects_its_own_row() {
+        let dir = std::env::temp_dir().join(format!("reader-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let (_, good_tob) = symbol_paths(dir_str, "GOOD");
+        let (_, other_tob) = symbol_paths(dir_str, "OTHER");
+        let (_, missing_tob) = symbol_paths(dir_str, "MISSING");
+        let (bad_ob, bad_tob) = symbol_paths(dir_str, "BAD");
+        let (_map, tob) = TopOfBook::mmap(Path::new(&good_tob)).unwrap();
+        tob.set_bid(100, 1);
+        let (_other_map, other_tob) = TopOfBook::mmap(Path::new(&other_tob)).unwrap();
+        other_tob.set_ask(300, 1);
+        std::fs::write(&bad_tob, b"truncated").unwrap();
+        std::fs::write(&bad_ob, b"").unwrap();
+
+        let mut bad = SymbolView::new(dir_str, "BAD");
+        let mut good = SymbolView::new(dir_str, "GOOD");
+        let mut other = SymbolView::new(dir_str, "OTHER");
+        let mut missing = SymbolView::new(dir_str, "MISSING");
+        assert!(!bad.refresh());
+        assert!(bad.error.as_deref().is_some_and(|e| e.contains(&bad_tob) && e.contains(&bad_ob)), "{:?}", bad.error);
+        assert!(bad.quote.is_none());
+        assert!(good.refresh());
+        assert_eq!(good.quote.map(|q| q.bid_price), Some(100));
+        assert!(good.error.is_none());
+        assert!(other.refresh());
+        assert_eq!(other.quote.map(|q| q.ask_price), Some(300));
+        assert!(!missing.refresh());
+        assert!(missing.error.is_none(), "a missing file is not an error");
+
+        let args = Args::default();
+        assert!(bad.row(false, &args).starts_with("BAD        error: "));
+        assert!(good.row(true, &args).starts_with("GOOD "));
+        assert!(other.row(true, &args).starts_with("OTHER "));
+        assert_eq!(missing.row(false, &args), format!("MISSING    no data ({} not found)", missing_tob));
+
+        // Retried each cycle, so a file the writer later recreates is picked up
+        std::fs::remove_file(&bad_tob).unwrap();
+        std::fs::remove_file(&bad_ob).unwrap();
+        let (_map, tob) = TopOfBook::mmap(Path::new(&bad_tob)).unwrap();
+        tob.set_ask(200, 1);
+        assert!(bad.refresh());
+        assert!(bad.error.is_none());
+        assert_eq!(bad.quote.map(|q| q.ask_price), Some(200));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn liveness_follows_the_heartbeat() {
+        let path = std::env::temp_dir().join(format!("reader-test-{}-liveness", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path_str = path.to_str().unwrap().to_string();
+        let (map, tob) = TopOfBook::mmap(&path).unwrap();
+
+        // A writer bumping on its cadence reads as alive, even with an unchanged quote
+        let writer = std::thread::spawn(move || {
+            let _map = map;
+            for _ in 0..20 {
+                tob.bump_heartbeat();
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            tob.heartbeat()
+        });
+        assert!(check_liveness(&path_str, 2_000).is_ok());
+        let last = writer.join().unwrap();
+        assert_eq!(last, 20);
+
+        // Once it stops, the counter stalls and the check fails
+        let err = check_liveness(&path_str, 50).unwrap_err().to_string();
+        assert_eq!(err, "writer dead: heartbeat stuck at 20 for 50ms");
+        std::fs::remove_file(&path).unwrap();
+        assert!(check_liveness(&path_str, 50).unwrap_err().to_string().contains("not found"));
+    }
+
+
+    #[test]
+    fn unchanged_symbol_is_not_re_read() {
+        let dir = std::env::temp_dir().join(format!("reader-test-{}-refresh", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let (ob_path, tob_path) = symbol_paths(dir_str, "SOLUSD");
+        let (_tob_map, tob) = TopOfBook::mmap(Path::new(&tob_path)).unwrap();
+        let (_ob_map, ob) = OrderBook::mmap(Path::new(&ob_path)).unwrap();
+        tob.set_bid(14_585, 250);
+
+        let mut view = SymbolView::new(dir_str, "SOLUSD");
+        assert!(view.refresh(), "first read");
+        assert!(!view.refresh(), "no writes since");
+        assert!(!view.refresh());
+
+        tob.set_bid(14_586, 250);
+        assert!(view.refresh(), "quote moved");
+        assert_eq!(view.quote.map(|q| q.bid_price), Some(14_586));
+        assert!(!view.refresh());
+
+        ob.begin_write();
+        ob.update_bid(0, 14_586, 250);
+        ob.end_write();
+        assert!(view.refresh(), "levels moved");
+        assert_eq!(view.levels.map(|(_, bids, _)| bids), Some(1));
+        assert!(!view.refresh());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    #[test]
+    fn crossed_reads_are_reported_inconsistent() {
+        let mut tob = TopOfBook::default();
+        tob.set_meta(BookMeta::new("SOLUSD", 100, 100, 1, BOOK_DEPTH));
+        tob.set_bid(14_585, 250);
+        tob.set_ask(14_590, 180);
+        let (snap, diagnostic) = consistent_quote(&tob);
+        assert_eq!((snap.bid_price, diagnostic), (14_585, None));
+
+        tob.set_bid(14_595, 250);
+        let (_, diagnostic) = consistent_quote(&tob);
+        assert_eq!(diagnostic.as_deref(), Some("crossed quote, bid 145.95 >= ask 145.90 (seq 6)"));
+
+        let mut book = OrderBook::default();
+        book.set_meta(BookMeta::new("SOLUSD", 100, 100, 1, BOOK_DEPTH));
+        book.begin_write();
+        book.update_bid(0, 14_585, 250);
+        book.update_ask(0, 14_590, 180);
+        book.end_write();
+        assert!(consistent_book(&book).is_ok());
+
+        book.begin_write();
+        book.update_bid(0, 14_590, 250);
+        book.end_write();
+        assert_eq!(consistent_book(&book).unwrap_err(), "crossed book: best bid 145.90 >= best ask 145.90 (seq 4)");
+
+        // A writer that never finishes is reported as such rather than read torn
+        book.begin_write();
+        assert_eq!(consistent_book(&book).unwrap_err(), "writer mid-update (seq 5)");
+    }
+
+
+    #[test]
+    fn hidden_levels_count_populated_levels_past_the_ladder() {
+        let mut book = OrderBook::default();
+        for i in 0..6 {
+            book.update_bid(i, 14_585 - i as u64, 10);
+        }
+        book.update_ask(0, 14_590, 10);
+        book.update_ask(1, 14_591, 10);
+        // A gap past the shown levels: only populated levels count
+        book.update_ask(7, 14_597, 10);
+
+        assert_eq!(hidden_levels(&book, 2), (4, 1));
+        assert_eq!(hidden_levels(&book, 6), (0, 1));
+        assert_eq!(hidden_levels(&book, BOOK_DEPTH), (0, 0));
+    }
+
+
+    #[test]
+    fn csv_capture_writes_the_header_once() {
+        let base = std::env::temp_dir().join(format!("reader-test-{}-csv", std::process::id()));
+        let (tob_path, out) = (base.with_extension("mmap"), base.with_extension("csv"));
+        let _ = std::fs::remove_file(&out);
+        let (_map, tob) = TopOfBook::mmap(&tob_path).unwrap();
+        tob.set_meta(BookMeta::new("SOLUSD", 100, 100, 1, BOOK_DEPTH));
+        tob.set_bid(14_585, 250);
+        tob.set_ask(14_590, 180);
+        let args = Args { samples: Some(3), interval_ms: 1, ..Args::default() };
+        let (tob_str, out_str) = (tob_path.to_str().unwrap(), out.to_str().unwrap());
+
+        capture_csv(tob_str, out_str, &args).unwrap();
+        capture_csv(tob_str, out_str, &Args { samples: Some(2), ..args }).unwrap();
+        let text = std::fs::read_to_string(&out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + 3 + 2, "appended without a second header");
+        assert_eq!(lines[0], "ts,bid,bidsz,ask,asksz,mid,spread_bps");
+        assert_eq!(lines.iter().filter(|l| l.starts_with("ts,")).count(), 1);
+        assert!(lines[1..].iter().all(|l| l.ends_with(",145.85,2.50,145.90,1.80,145.88,3.43")), "{:?}", lines);
+
+        std::fs::remove_file(&tob_path).unwrap();
+        std::fs::remove_file(&out).unwrap();
+    }
+
+
+    #[test]
+    fn diff_marks_levels_against_the_previous_run() {
+        let first = [(14_585, 250), (14_584, 100), (14_583, 50)];
+        let markers = |lines: Vec<String>| lines.iter().map(|l| l.trim_start().chars().next().unwrap()).collect::<String>();
+
+        // No saved state: everything is new
+        assert_eq!(markers(diff_side(&[], &first, false, 100, 100)), "+++");
+
+        // Second run: 145.85 resized, 145.84 unchanged (now at level 0), 145.83 gone, 145.82 new
+        let second = [(14_584, 100), (14_582, 75), (14_585, 300)];
+        let lines = diff_side(&first, &second, false, 100, 100);
+        assert_eq!(lines, vec![
+            "  ~       145.85         2.50 -> 3.00",
+            "          145.84         1.00",
+            "  -       145.83         0.50",
+            "  +       145.82         0.75",
+        ]);
+        // Asks run the other way
+        assert_eq!(markers(diff_side(&[(14_590, 1)], &[(14_591, 1)], true, 100, 100)), "-+");
+    }
+
+
+    #[test]
+    fn timestamps_render_with_age_or_as_invalid() {
+        let now = 1_700_000_000_000;
+        assert_eq!(format_timestamp_at(0, 60, now), "no timestamp (never written)");
+        assert_eq!(format_timestamp_at(now - 1_500, 60, now), "1699999998500 (1.5s ago)");
+        assert_eq!(format_timestamp_at(now, 60, now), "1700000000000 (0.0s ago)");
+        // Small writer clock skew still reads as fresh
+        assert_eq!(format_timestamp_at(now + FUTURE_SKEW_MS, 60, now), "1700000005000 (0.0s ago)");
+        assert_eq!(format_timestamp_at(now + FUTURE_SKEW_MS + 1, 60, now), "1700000005001 (invalid: in the future)");
+        assert_eq!(format_timestamp_at(now - 61_000, 60, now), "1699999939000 (invalid: older than 60s)");
+    }
+}
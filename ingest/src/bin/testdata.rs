@@ -1,58 +1,140 @@
 use anyhow::Result;
-use shared::{OrderBook, TopOfBook};
+use shared::{config, BookMeta, OrderBook, OrderLevel, TopOfBook, BOOK_DEPTH};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shape of the generated book. Prices are in micro-dollars, spreads and level gaps
+/// in ticks, so every price is tick-aligned and the spread is at least one tick.
+struct Params {
+    tick: u64,
+    min_spread_ticks: u64,
+    max_spread_ticks: u64,
+    /// Ticks between consecutive levels on each side.
+    level_gap_ticks: u64,
+    levels: usize,
+    seed: u64,
+}
+
+fn parse_args() -> Result<Params> {
+    let mut p = Params { tick: 10_000, min_spread_ticks: 5, max_spread_ticks: 5, level_gap_ticks: 5, levels: 5, seed: 1 };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or_else(|| anyhow::anyhow!("{} needs a value", arg)).and_then(|v| Ok(v.parse::<u64>()?));
+        match arg.as_str() {
+            "--tick" => p.tick = value()?,
+            "--min-spread-ticks" => p.min_spread_ticks = value()?,
+            "--max-spread-ticks" => p.max_spread_ticks = value()?,
+            "--level-gap-ticks" => p.level_gap_ticks = value()?,
+            "--levels" => p.levels = value()? as usize,
+            "--seed" => p.seed = value()?,
+            other => anyhow::bail!("unknown argument '{}' (usage: testdata [--tick U] [--min-spread-ticks N] [--max-spread-ticks N] [--level-gap-ticks N] [--levels N] [--seed N])", other),
+        }
+    }
+    if p.tick == 0 || p.level_gap_ticks == 0 {
+        anyhow::bail!("--tick and --level-gap-ticks must be positive");
+    }
+    if p.min_spread_ticks == 0 || p.max_spread_ticks < p.min_spread_ticks {
+        anyhow::bail!("spread bounds must satisfy 1 <= --min-spread-ticks <= --max-spread-ticks");
+    }
+    if p.levels == 0 || p.levels > BOOK_DEPTH {
+        anyhow::bail!("--levels must be between 1 and {}", BOOK_DEPTH);
+    }
+    Ok(p)
+}
+
+/// Small deterministic PRNG (xorshift64) so a seed reproduces the same book.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+/// Builds tick-aligned bid and ask ladders around ~$145.85, best level first. The
+/// spread is drawn from the configured bounds, so the book is never crossed or locked.
+fn generate(p: &Params) -> Result<(Vec<OrderLevel>, Vec<OrderLevel>)> {
+    let mut rng = Rng(p.seed.max(1));
+    let best_bid = 145_850_000 / p.tick * p.tick;
+    let spread = (p.min_spread_ticks + rng.below(p.max_spread_ticks - p.min_spread_ticks + 1)) * p.tick;
+    let gap = p.level_gap_ticks * p.tick;
+    if best_bid < gap * (p.levels as u64 - 1) + p.tick {
+        anyhow::bail!("--levels/--level-gap-ticks/--tick push the bid ladder below zero");
+    }
+    // 1.0 to 5.0 SOL in 0.1 steps
+    let mut qty = || (10 + rng.below(41)) * 100_000;
+    let bids = (0..p.levels as u64).map(|i| OrderLevel { price: best_bid - i * gap, qty: qty() }).collect();
+    let asks = (0..p.levels as u64).map(|i| OrderLevel { price: best_bid + spread + i * gap, qty: qty() }).collect();
+    Ok((bids, asks))
+}
 
 fn main() -> Result<()> {
+    let params = parse_args()?;
     println!("🔧 Creating test data in memory-mapped files...");
-    
-    let ob_path = std::env::var("OB_MMAP")
+
+    let ob_path = config::var("OB_MMAP")
         .unwrap_or_else(|_| "/dev/shm/solusd_order_book.mmap".to_string());
-    let tob_path = std::env::var("TOB_MMAP")
+    let tob_path = config::var("TOB_MMAP")
         .unwrap_or_else(|_| "/dev/shm/solusd_top_of_book.mmap".to_string());
 
-    // Create and populate TopOfBook
+    // Stamp with the current time so the reader shows a sane age
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let (bids, asks) = generate(&params)?;
+
+    // Create and populate TopOfBook from the best levels
     let (_tob_mmap, tob) = TopOfBook::mmap(Path::new(&tob_path))?;
-    tob.set_bid(145_850_000, 2_500_000); // $145.85 @ 2.5 SOL
-    tob.set_ask(145_900_000, 1_800_000); // $145.90 @ 1.8 SOL
-    tob.set_ts(1726311234567); // Sample timestamp
-    
-    // Create and populate OrderBook with sample ladder
+    tob.set_meta(BookMeta::new("SOLUSD", 1_000_000, 1_000_000, params.tick, 1)); // micro units
+    tob.set_bid(bids[0].price, bids[0].qty);
+    tob.set_ask(asks[0].price, asks[0].qty);
+    tob.set_ts(now_ms);
+
+    // Create and populate OrderBook with the generated ladder
     let (_ob_mmap, ob) = OrderBook::mmap(Path::new(&ob_path))?;
-    
-    // Sample bid ladder (decreasing prices)
-    let bid_data = [
-        (145_850_000, 2_500_000), // L1: $145.85 @ 2.5 SOL
-        (145_800_000, 3_200_000), // L2: $145.80 @ 3.2 SOL
-        (145_750_000, 1_100_000), // L3: $145.75 @ 1.1 SOL
-        (145_700_000, 4_500_000), // L4: $145.70 @ 4.5 SOL
-        (145_650_000, 2_800_000), // L5: $145.65 @ 2.8 SOL
-    ];
-    
-    // Sample ask ladder (increasing prices)
-    let ask_data = [
-        (145_900_000, 1_800_000), // L1: $145.90 @ 1.8 SOL
-        (145_950_000, 2_300_000), // L2: $145.95 @ 2.3 SOL
-        (146_000_000, 3_700_000), // L3: $146.00 @ 3.7 SOL
-        (146_050_000, 1_600_000), // L4: $146.05 @ 1.6 SOL
-        (146_100_000, 5_200_000), // L5: $146.10 @ 5.2 SOL
-    ];
-    
-    for (i, &(price, qty)) in bid_data.iter().enumerate() {
-        ob.update_bid(i, price, qty);
-    }
-    
-    for (i, &(price, qty)) in ask_data.iter().enumerate() {
-        ob.update_ask(i, price, qty);
-    }
-    
-    ob.set_ts(1726311234567); // Same timestamp
-    
+    ob.set_meta(BookMeta::new("SOLUSD", 1_000_000, 1_000_000, params.tick, BOOK_DEPTH));
+    ob.publish(&bids, &asks, now_ms);
+
     println!("✅ Test data created successfully!");
     println!("📁 Files created:");
     println!("   TopOfBook: {}", tob_path);
     println!("   OrderBook: {}", ob_path);
+    println!("📐 {} levels per side, spread {} ticks of {}", params.levels, (asks[0].price - bids[0].price) / params.tick, params.tick);
     println!();
     println!("💡 Now run: cargo run -p ingest --bin reader");
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_books_stay_within_the_spread_bounds_on_ticks() {
+        let mut spreads = std::collections::BTreeSet::new();
+        for seed in 1..=200 {
+            let p = Params { tick: 5_000, min_spread_ticks: 2, max_spread_ticks: 6, level_gap_ticks: 3, levels: 8, seed };
+            let (bids, asks) = generate(&p).unwrap();
+            assert_eq!((bids.len(), asks.len()), (8, 8));
+            for lvl in bids.iter().chain(&asks) {
+                assert_eq!(lvl.price % p.tick, 0, "seed {}: {} not on a tick", seed, lvl.price);
+                assert!((1_000_000..=5_000_000).contains(&lvl.qty), "seed {}: qty {}", seed, lvl.qty);
+            }
+            let spread_ticks = (asks[0].price - bids[0].price) / p.tick;
+            assert!((p.min_spread_ticks..=p.max_spread_ticks).contains(&spread_ticks), "seed {}: spread {} ticks", seed, spread_ticks);
+            spreads.insert(spread_ticks);
+            assert!(bids.windows(2).all(|w| w[0].price - w[1].price == p.level_gap_ticks * p.tick), "seed {}: bids descend by the gap", seed);
+            assert!(asks.windows(2).all(|w| w[1].price - w[0].price == p.level_gap_ticks * p.tick), "seed {}: asks ascend by the gap", seed);
+        }
+        assert_eq!(spreads.into_iter().collect::<Vec<_>>(), vec![2, 3, 4, 5, 6], "every allowed spread drawn");
+    }
+
+    #[test]
+    fn same_seed_same_book() {
+        let p = Params { tick: 10_000, min_spread_ticks: 1, max_spread_ticks: 9, level_gap_ticks: 5, levels: 5, seed: 42 };
+        let prices = |(b, a): (Vec<OrderLevel>, Vec<OrderLevel>)| b.iter().chain(&a).map(|l| (l.price, l.qty)).collect::<Vec<_>>();
+        assert_eq!(prices(generate(&p).unwrap()), prices(generate(&p).unwrap()));
+    }
+}
@@ -0,0 +1,128 @@
+use shared::config;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Per-symbol kill switch driven by `CONTROL_FILE`: a text file listing one disabled
+/// symbol per line (`#` starts a comment). A missing file means everything is enabled.
+/// Feed loops select on `disabled()`, so a disable takes effect as soon as the file is
+/// polled rather than on the next frame.
+#[derive(Clone)]
+pub struct KillSwitch(Arc<watch::Sender<bool>>);
+
+impl Default for KillSwitch {
+    fn default() -> Self { Self(Arc::new(watch::Sender::new(false))) }
+}
+
+impl KillSwitch {
+    /// Starts polling `CONTROL_FILE` every `CONTROL_POLL_MS` (default 1000) for `symbol`.
+    /// Without `CONTROL_FILE` the switch stays enabled and no task is spawned.
+    pub fn from_env(symbol: &'static str) -> Self {
+        let Ok(path) = config::var("CONTROL_FILE") else { return Self::default() };
+        let poll = Duration::from_millis(config::var("CONTROL_POLL_MS").ok().and_then(|s| s.parse().ok()).unwrap_or(1000));
+        Self::watching(path, symbol, poll)
+    }
+
+    /// Polls `path` every `poll` for `symbol` from a spawned task.
+    pub fn watching(path: String, symbol: &'static str, poll: Duration) -> Self {
+        let ks = Self::default();
+        let flag = Arc::clone(&ks.0);
+        tokio::spawn(async move {
+            loop {
+                let disabled = tokio::fs::read_to_string(&path).await
+                    .map(|s| s.lines().map(|l| l.split('#').next().unwrap_or("").trim()).any(|l| l.eq_ignore_ascii_case(symbol)))
+                    .unwrap_or(false);
+                if flag.send_if_modified(|d| std::mem::replace(d, disabled) != disabled) {
+                    if disabled { warn!("⛔ {} disabled via {}", symbol, path); } else { info!("✅ {} re-enabled via {}", symbol, path); }
+                }
+                tokio::time::sleep(poll).await;
+            }
+        });
+        ks
+    }
+
+    /// Resolves once the symbol is disabled (at once if it already is).
+    pub async fn disabled(&self) {
+        let _ = self.0.subscribe().wait_for(|d| *d).await;
+    }
+
+    /// Resolves once the symbol is enabled (at once if it already is).
+    pub async fn wait_enabled(&self) {
+        let _ = self.0.subscribe().wait_for(|d| !*d).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::{OrderBook, OrderLevel, TS_DISABLED};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const POLL: Duration = Duration::from_millis(10);
+
+    /// Stands in for a feed loop: publishes a fresh timestamp every millisecond while
+    /// enabled and marks the book disabled, then waits, when the switch flips.
+    async fn writer(ks: KillSwitch, book: &'static mut OrderBook, stop: Arc<AtomicBool>) {
+        let mut ts = 1;
+        while !stop.load(Ordering::Relaxed) {
+            ks.wait_enabled().await;
+            loop {
+                tokio::select! {
+                    _ = ks.disabled() => { book.mark_disabled(); break; }
+                    _ = tokio::time::sleep(Duration::from_millis(1)) => {
+                        ts += 1;
+                        book.publish(&[OrderLevel { price: 100, qty: 1 }], &[OrderLevel { price: 101, qty: 1 }], ts);
+                    }
+                }
+                if stop.load(Ordering::Relaxed) { return; }
+            }
+        }
+    }
+
+    async fn until(what: &str, check: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async { while !check() { tokio::time::sleep(POLL).await; } })
+            .await.unwrap_or_else(|_| panic!("timed out waiting for {}", what));
+    }
+
+    #[tokio::test]
+    async fn toggling_the_control_file_stops_and_resumes_the_mmap() {
+        let dir = std::env::temp_dir().join(format!("control-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (control, book_path) = (dir.join("control"), dir.join("order_book.bin"));
+        let _ = std::fs::remove_file(&book_path);
+        let (_map, book) = OrderBook::mmap(&book_path).unwrap();
+        let (_reader_map, reader) = OrderBook::mmap_readonly(&book_path).unwrap();
+        let ts = || reader.snapshot().timestamp_ms;
+
+        let ks = KillSwitch::watching(control.to_string_lossy().into_owned(), "SOLUSD", POLL);
+        let stop = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(writer(ks.clone(), book, Arc::clone(&stop)));
+        until("first publish", || ts() > 1).await;
+
+        // Other symbols in the file leave this one alone
+        std::fs::write(&control, "BTCUSD\n# SOLUSD\n").unwrap();
+        let before = ts();
+        until("updates to continue", || ts() > before + 5).await;
+        assert!(!*ks.0.borrow());
+
+        std::fs::write(&control, "btcusd\nsolusd  # incident\n").unwrap();
+        until("disable", || ts() == TS_DISABLED).await;
+        assert!(*ks.0.borrow());
+        tokio::time::sleep(POLL * 5).await;
+        let frozen = reader.snapshot();
+        assert_eq!(frozen.timestamp_ms, TS_DISABLED, "no publishes while disabled");
+        assert_eq!(frozen.best_bid().map(|l| l.price), Some(100), "levels kept as they were");
+        assert_eq!(reader.load_seq() % 2, 0, "marked inside a closed write section");
+
+        std::fs::remove_file(&control).unwrap();
+        until("re-enable", || ts() != TS_DISABLED).await;
+        let resumed = ts();
+        until("updates to resume", || ts() > resumed + 5).await;
+
+        stop.store(true, Ordering::Relaxed);
+        std::fs::write(&control, "SOLUSD\n").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,89 @@
+/// Detects missed messages from a feed's contiguous per-connection sequence number
+/// (Gemini v1 `socket_sequence`, which restarts at 0 on every connection).
+#[derive(Default)]
+pub struct GapDetector {
+    last: Option<u64>,
+}
+
+impl GapDetector {
+    /// Records `seq` and returns the inclusive range of sequence numbers skipped before it.
+    pub fn observe(&mut self, seq: u64) -> Option<(u64, u64)> {
+        let gap = match self.last {
+            Some(last) if seq > last + 1 => Some((last + 1, seq - 1)),
+            _ => None,
+        };
+        self.last = Some(seq);
+        gap
+    }
+}
+
+/// Whether the next published trade follows data that may have been lost: a sequence
+/// gap, or a reconnect after the first connection. Cleared only once a trade carrying
+/// the flag has been handed to the bus, so a trade that fails to enqueue doesn't take
+/// the flag with it.
+#[derive(Default)]
+pub struct GapFlag {
+    pending: bool,
+    connected_before: bool,
+}
+
+impl GapFlag {
+    /// Trades during the disconnect were never seen.
+    pub fn on_connected(&mut self) {
+        self.pending |= self.connected_before;
+        self.connected_before = true;
+    }
+
+    pub fn on_gap(&mut self) { self.pending = true; }
+
+    pub fn pending(&self) -> bool { self.pending }
+
+    /// A trade built with `pending()` was enqueued.
+    pub fn on_published(&mut self) { self.pending = false; }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_skipped_range() {
+        let mut gaps = GapDetector::default();
+        assert_eq!(gaps.observe(7), None, "the first message has nothing to compare against");
+        assert_eq!(gaps.observe(8), None);
+        assert_eq!(gaps.observe(10), Some((9, 9)));
+        assert_eq!(gaps.observe(20), Some((11, 19)));
+        // A restarted sequence is not a gap; the detector follows it from there
+        assert_eq!(gaps.observe(0), None);
+        assert_eq!(gaps.observe(2), Some((1, 1)));
+    }
+
+    #[test]
+    fn gapped_sequence_flags_the_next_published_trade() {
+        let (mut gaps, mut flag) = (GapDetector::default(), GapFlag::default());
+        flag.on_connected();
+        assert!(!flag.pending(), "the first connection follows no lost data");
+        for seq in [0, 1, 2] {
+            assert_eq!(gaps.observe(seq), None);
+        }
+        assert_eq!(gaps.observe(5), Some((3, 4)));
+        flag.on_gap();
+        assert!(flag.pending());
+        flag.on_published();
+        assert!(!flag.pending());
+        assert_eq!(gaps.observe(6), None);
+    }
+
+    #[test]
+    fn reconnect_flags_and_restarts_the_sequence() {
+        let mut flag = GapFlag::default();
+        flag.on_connected();
+        flag.on_connected();
+        assert!(flag.pending());
+        // `socket_sequence` restarts at 0 per connection, with a fresh detector
+        let mut gaps = GapDetector::default();
+        assert_eq!(gaps.observe(0), None);
+        assert_eq!(gaps.observe(1), None);
+        assert_eq!(gaps.observe(1), None, "a repeat is not a gap");
+    }
+}
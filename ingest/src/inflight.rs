@@ -0,0 +1,70 @@
+use shared::config;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// Caps trade produce requests awaiting a bus acknowledgement at `MAX_INFLIGHT_PRODUCE`
+/// (default 1000). When all slots are taken, `acquire` waits, which stalls the v1 read
+/// loop instead of queueing more trades in memory; nothing is dropped.
+pub struct ProduceLimit {
+    slots: Arc<Semaphore>,
+    max: usize,
+    stalls: u64,
+}
+
+impl ProduceLimit {
+    // Tests build this module without a bus, where nothing reads the env
+    #[cfg_attr(not(any(feature = "kafka", feature = "pulsar")), allow(dead_code))]
+    pub fn from_env() -> Self {
+        Self::new(config::var("MAX_INFLIGHT_PRODUCE").ok().and_then(|s| s.parse::<usize>().ok()).filter(|n| *n > 0).unwrap_or(1000))
+    }
+
+    pub fn new(max: usize) -> Self {
+        Self { slots: Arc::new(Semaphore::new(max)), max, stalls: 0 }
+    }
+
+    /// Takes a slot, waiting for one to free up if the limit is reached. Hold the permit
+    /// until the produce request completes.
+    pub async fn acquire(&mut self) -> OwnedSemaphorePermit {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return permit;
+        }
+        self.stalls += 1;
+        if self.stalls.is_power_of_two() {
+            warn!("⏳ {} produce requests in flight, applying backpressure ({} stalls so far)", self.max, self.stalls);
+        }
+        self.slots.clone().acquire_owned().await.expect("produce semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn in_flight_sends_never_exceed_the_limit() {
+        let mut limit = ProduceLimit::new(3);
+        let (in_flight, peak, delivered) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut sends = Vec::new();
+        // Enqueued one after another like the v1 loop; each delivery completes later
+        for _ in 0..20 {
+            let permit = limit.acquire().await;
+            let (in_flight, peak, delivered) = (Arc::clone(&in_flight), Arc::clone(&peak), Arc::clone(&delivered));
+            sends.push(tokio::spawn(async move {
+                let _permit = permit;
+                peak.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                delivered.fetch_add(1, Ordering::SeqCst);
+            }));
+        }
+        for send in sends {
+            send.await.unwrap();
+        }
+        assert_eq!(delivered.load(Ordering::SeqCst), 20, "backpressure waits, it never drops");
+        assert_eq!(peak.load(Ordering::SeqCst), 3, "the limit is reached but never exceeded");
+        assert!(limit.stalls > 0);
+    }
+}
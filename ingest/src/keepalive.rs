@@ -0,0 +1,96 @@
+use shared::config;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// Client-initiated WebSocket keepalive: tracks the outstanding ping, pong RTT and
+/// consecutive missed pongs for one connection.
+pub struct Keepalive {
+    feed: &'static str,
+    pub interval: Duration,
+    max_missed: u32,
+    outstanding: Option<Instant>,
+    missed: u32,
+    pub last_rtt: Option<Duration>,
+}
+
+impl Keepalive {
+    /// `WS_PING_INTERVAL_SECS` (default 15) and `WS_MAX_MISSED_PONGS` (default 3).
+    pub fn from_env(feed: &'static str) -> Self {
+        let secs = config::var("WS_PING_INTERVAL_SECS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(15).max(1);
+        let max_missed = config::var("WS_MAX_MISSED_PONGS").ok().and_then(|s| s.parse::<u32>().ok()).unwrap_or(3).max(1);
+        Self { feed, interval: Duration::from_secs(secs), max_missed, outstanding: None, missed: 0, last_rtt: None }
+    }
+
+    /// Timer that fires every `interval`, skipping the immediate first tick.
+    pub fn timer(&self) -> tokio::time::Interval {
+        tokio::time::interval_at(tokio::time::Instant::now() + self.interval, self.interval)
+    }
+
+    /// Called when the ping timer fires. Returns `false` once too many pongs were
+    /// missed and the connection should be dropped, `true` if a ping should be sent.
+    pub fn on_tick(&mut self) -> bool {
+        if self.outstanding.is_some() {
+            self.missed += 1;
+            warn!("⏱️  {} pong missing ({}/{})", self.feed, self.missed, self.max_missed);
+            if self.missed >= self.max_missed {
+                return false;
+            }
+        }
+        self.outstanding = Some(Instant::now());
+        true
+    }
+
+    pub fn missed(&self) -> u32 { self.missed }
+
+    pub fn on_pong(&mut self) {
+        if let Some(sent) = self.outstanding.take() {
+            let rtt = sent.elapsed();
+            debug!("{} pong rtt {:?}", self.feed, rtt);
+            self.last_rtt = Some(rtt);
+        }
+        self.missed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn pings_on_the_interval_and_missed_pongs_force_a_reconnect() {
+        let mut keepalive = Keepalive { feed: "v2", interval: Duration::from_secs(15), max_missed: 3, outstanding: None, missed: 0, last_rtt: None };
+        let start = Instant::now();
+        let mut timer = keepalive.timer();
+
+        timer.tick().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(15), "no ping right after connecting");
+        assert!(keepalive.on_tick(), "first ping");
+        tokio::time::advance(Duration::from_millis(40)).await;
+        keepalive.on_pong();
+        assert_eq!(keepalive.last_rtt, Some(Duration::from_millis(40)));
+
+        // The venue stops answering: each tick without a pong counts as a miss
+        let mut ticks = Vec::new();
+        loop {
+            timer.tick().await;
+            let keep = keepalive.on_tick();
+            ticks.push((start.elapsed().as_secs(), keepalive.missed(), keep));
+            if !keep { break; }
+        }
+        assert_eq!(ticks, vec![(30, 0, true), (45, 1, true), (60, 2, true), (75, 3, false)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_late_pong_clears_the_misses() {
+        let mut keepalive = Keepalive { feed: "v1", interval: Duration::from_secs(15), max_missed: 2, outstanding: None, missed: 0, last_rtt: None };
+        assert!(keepalive.on_tick());
+        tokio::time::advance(Duration::from_secs(15)).await;
+        assert!(keepalive.on_tick());
+        assert_eq!(keepalive.missed(), 1);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        keepalive.on_pong();
+        assert_eq!((keepalive.missed(), keepalive.last_rtt), (0, Some(Duration::from_secs(1))));
+        assert!(keepalive.on_tick(), "the connection survives");
+    }
+}
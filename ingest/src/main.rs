@@ -1,125 +1,663 @@
-use std::env;
+mod alert;
+mod control;
+mod gaps;
+#[cfg(any(feature = "kafka", feature = "pulsar", test))]
+mod inflight;
+mod keepalive;
+mod parse;
+mod reconnect;
+#[cfg(feature = "redis")]
+mod redis_tob;
+mod sides;
+mod stats;
+mod symbol_details;
+mod warmup;
+
+use std::sync::Arc;
 use anyhow::Result;
 use tracing::{info, error, warn};
-use shared::{OrderBook, TopOfBook, BOOK_DEPTH, TradeEvent};
+use shared::analytics::{book_quality_score, infer_taker_side, BookQuality, QtyCap, QtyFilter};
+use shared::config;
+use shared::errors::{self, ErrorCategory};
+use shared::metrics::Feed;
+use shared::ring::SnapshotRing;
+use shared::{BookMeta, OrderBook, OrderLevel, Side, TopOfBook, BOOK_DEPTH, TradeEvent};
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 use futures_util::{StreamExt, SinkExt};
-use serde_json;
+use alert::{Alert, ThinBookAlarm};
+use control::KillSwitch;
+use gaps::{GapDetector, GapFlag};
+#[cfg(any(feature = "kafka", feature = "pulsar"))]
+use inflight::ProduceLimit;
+use keepalive::Keepalive;
+use parse::{parse_min_notional, parse_scaled, PriceConvention};
+use reconnect::Reconnect;
+use sides::{CrossCheck, ResyncDwell, SideBuffer};
+use stats::{CrossedRate, InterArrival, PublishLatency};
+use symbol_details::Scales;
+
+const SYMBOL: &str = "SOLUSD";
+
+/// True if `SYMBOL` appears in the comma-separated list held by env var `var`.
+fn symbol_listed(var: &str) -> bool {
+    config::var(var)
+        .map(|v| v.split(',').any(|s| s.trim().eq_ignore_ascii_case(SYMBOL)))
+        .unwrap_or(false)
+}
+
+/// Where a symbol's TopOfBook comes from, per `TOB_SOURCE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TobSource {
+    /// The v1 socket, which also carries the trades
+    V1,
+    /// The v2 book's best levels; the v1 socket is never opened
+    V2Derived,
+}
+
+impl TobSource {
+    /// Parses a `TOB_SOURCE` value (unset means v1). v2-derived needs the v2 feed, so it
+    /// is refused for an l1-only symbol.
+    fn parse(setting: Option<&str>, l1_only: bool) -> Result<Self> {
+        match setting {
+            None | Some("v1") => Ok(Self::V1),
+            Some("v2-derived") if l1_only => anyhow::bail!("TOB_SOURCE=v2-derived needs the v2 feed, but {} is in L1_ONLY_SYMBOLS", SYMBOL),
+            Some("v2-derived") => Ok(Self::V2Derived),
+            Some(other) => anyhow::bail!("unknown TOB_SOURCE '{}' for {} (expected v1 or v2-derived)", other, SYMBOL),
+        }
+    }
+
+    /// Hands `top` to the v1 task or to the v2 task: `(v1, derived)`. The v1 task returns
+    /// before connecting when it gets `None`.
+    fn route<T>(self, top: T) -> (Option<T>, Option<T>) {
+        match self {
+            Self::V1 => (Some(top), None),
+            Self::V2Derived => (None, Some(top)),
+        }
+    }
+}
+
+/// Mirrors the book's best levels into `top` for symbols whose top of book is derived
+/// from v2. The timestamp only moves when the best bid or ask actually changed, or to
+/// clear a kill-switch `TS_DISABLED` stamp. Best
+/// levels skip emptied slots, so a delete at level 0 quotes the next level; an empty
+/// side is quoted as zero.
+fn derive_top(book: &OrderBook, top: &mut TopOfBook) {
+    let (bid, ask) = (book.best_bid().unwrap_or_default(), book.best_ask().unwrap_or_default());
+    let bid_changed = top.set_bid_if_changed(bid.price, bid.qty);
+    let ask_changed = top.set_ask_if_changed(ask.price, ask.qty);
+    if bid_changed || ask_changed || top.is_disabled() {
+        top.set_ts(book.timestamp_ms);
+        mirror_top(top);
+    }
+}
+
+/// Hands a changed top of book to the optional out-of-process sinks (Redis with the
+/// `redis` feature); the mmap file itself is already written.
+#[inline]
+fn mirror_top(_top: &TopOfBook) {
+    #[cfg(feature = "redis")]
+    redis_tob::publish(_top.snapshot());
+}
+
+/// Counts a trade handed to the bus (or stdout) on `/metrics`, as its latest exemplar.
+fn count_published(tr: &TradeEvent, scales: Scales) {
+    shared::metrics::record_trade(tr.trade_id, tr.ts_ms, symbol_details::micro(tr.qty_u, scales.qty));
+}
+
+/// Connect failures split into TLS problems and everything else.
+fn connect_category(e: &tokio_tungstenite::tungstenite::Error) -> ErrorCategory {
+    match e {
+        tokio_tungstenite::tungstenite::Error::Tls(_) => ErrorCategory::Tls,
+        _ => ErrorCategory::Connect,
+    }
+}
+
+/// `SYMBOL`'s value in a comma-separated `SYMBOL:value` list held by env var `var`.
+fn symbol_setting(var: &str) -> Option<String> {
+    config::var(var).ok()?.split(',').find_map(|entry| {
+        let (sym, value) = entry.split_once(':')?;
+        sym.trim().eq_ignore_ascii_case(SYMBOL).then(|| value.trim().to_string())
+    })
+}
+
+/// Trade size filter from `TRADE_MAX_QTY` (absolute cap in base units) or, failing
+/// that, `TRADE_MAX_QTY_MULTIPLE` x the median of the last `TRADE_QTY_MEDIAN_WINDOW`
+/// (default 200) trades. `None` when neither is set.
+fn qty_filter_from_env(qty_scale: u64) -> Option<QtyFilter> {
+    let var = |k: &str| config::var(k).ok().and_then(|s| s.parse::<f64>().ok()).filter(|v| v.is_finite() && *v > 0.0);
+    let cap = if let Some(max) = var("TRADE_MAX_QTY") {
+        QtyCap::Absolute((max * qty_scale as f64).round() as u64)
+    } else {
+        let multiple = var("TRADE_MAX_QTY_MULTIPLE")?;
+        let window = config::var("TRADE_QTY_MEDIAN_WINDOW").ok().and_then(|s| s.parse::<usize>().ok()).unwrap_or(200).max(1);
+        QtyCap::MedianMultiple { multiple, window }
+    };
+    info!("📏 Trade size filter: {:?}", cap);
+    Some(QtyFilter::new(cap))
+}
+
+/// Applies a v2 `changes` batch (`[side, price, qty]` entries) to copies of the book's
+/// levels, so the caller can publish the result under one seqlock bump.
+fn apply_changes(changes: &[serde_json::Value], bids: &mut [OrderLevel], asks: &mut [OrderLevel], depth: usize, prices: PriceConvention, qty_scale: u64) {
+    for ch in changes.iter() {
+        if let (Some(side), Some(pu), Some(qu)) = (
+            ch.get(0).and_then(|x| x.as_str()?.parse::<Side>().ok()).map(|s| prices.side(s)),
+            ch.get(1).and_then(|x| prices.price(x)),
+            ch.get(2).and_then(|x| parse_scaled(x, qty_scale)),
+        ) {
+            // Simple approach: update first few levels based on price ordering
+            if side == Side::Buy {
+                // For bids, higher prices should be at lower indices
+                for lvl in bids.iter_mut().take(depth.min(10)) {
+                    let current_price = lvl.price;
+                    if qu == 0 && current_price == pu {
+                        // Remove this level by shifting everything up
+                        *lvl = OrderLevel::default();
+                        break;
+                    } else if current_price == 0 || pu > current_price {
+                        // Insert/update at this level
+                        *lvl = OrderLevel { price: pu, qty: qu };
+                        break;
+                    } else if current_price == pu {
+                        // Update existing level
+                        *lvl = OrderLevel { price: pu, qty: qu };
+                        break;
+                    }
+                }
+            } else {
+                // For asks, lower prices should be at lower indices  
+                for lvl in asks.iter_mut().take(depth.min(10)) {
+                    let current_price = lvl.price;
+                    if qu == 0 && current_price == pu {
+                        // Remove this level
+                        *lvl = OrderLevel::default();
+                        break;
+                    } else if current_price == 0 || (current_price > pu && pu > 0) {
+                        // Insert/update at this level
+                        *lvl = OrderLevel { price: pu, qty: qu };
+                        break;
+                    } else if current_price == pu {
+                        // Update existing level
+                        *lvl = OrderLevel { price: pu, qty: qu };
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Maps the `OrderBook` file, or nothing at all for an l1-only symbol, whose file is
+/// never created, opened or written.
+fn map_order_book(path: &str, l1_only: bool) -> std::io::Result<Option<(memmap2::MmapMut, &'static mut OrderBook)>> {
+    if l1_only { return Ok(None); }
+    OrderBook::mmap(std::path::Path::new(path)).map(Some)
+}
+
+/// Best bid and ask prevailing when a trade arrives, for effective spread analysis;
+/// `None` for a side with no quote yet.
+fn prevailing_quote(top: &TopOfBook) -> (Option<u64>, Option<u64>) {
+    let quote = top.snapshot();
+    ((quote.bid_price > 0).then_some(quote.bid_price), (quote.ask_price > 0).then_some(quote.ask_price))
+}
+
+/// Taker side of a v1 trade and whether it was inferred. v1 reports the maker side; the
+/// taker is on the other one. Falls back to the side implied by the mid when the feed
+/// omits it.
+fn taker_side(maker: Option<Side>, prices: &PriceConvention, price: u64, bid_at: Option<u64>, ask_at: Option<u64>) -> (Option<Side>, bool) {
+    match maker {
+        Some(s) => (Some(prices.side(s).opposite()), false),
+        None => { let s = infer_taker_side(price, bid_at, ask_at); (s, s.is_some()) }
+    }
+}
+
+/// Bus payload for a trade, shared by the Kafka and Pulsar producers and the
+/// `TRADE_STDOUT` output of builds without either. Prices and quantities go out in
+/// micro units whatever the symbol's `scales`.
+fn trade_payload(tr: &TradeEvent, scales: Scales) -> Vec<u8> {
+    let price = |p: u64| symbol_details::micro(p, scales.price);
+    serde_json::to_vec(&serde_json::json!({
+        "ts_ms": tr.ts_ms, "symbol": tr.symbol, "price_u": price(tr.price_u), "qty_u": symbol_details::micro(tr.qty_u, scales.qty),
+        "side": tr.side.map_or("", Side::as_str), "side_inferred": tr.side_inferred,
+        "bid_at": tr.bid_at.map(price), "ask_at": tr.ask_at.map(price), "trade_id": tr.trade_id, "raw_json": tr.raw, "gap_before": tr.gap_before,
+    })).unwrap()
+}
+
+/// Without a bus, trades are only useful if printed; say so up front instead of
+/// dropping them silently.
+#[cfg(any(not(any(feature = "kafka", feature = "pulsar")), test))]
+fn announce_trade_stdout(trade_stdout: bool) {
+    if trade_stdout {
+        info!("🖨️  Built without kafka/pulsar: writing trades to stdout as JSON lines");
+    } else {
+        warn!("⚠️  Built without kafka/pulsar: trades will NOT be published (set TRADE_STDOUT=1 to print them as JSON lines)");
+    }
+}
+
+/// One `TRADE_STDOUT` line: the bus payload, which never contains a raw newline.
+#[cfg(any(not(any(feature = "kafka", feature = "pulsar")), test))]
+fn write_trade_line(out: &mut impl std::io::Write, tr: &TradeEvent, scales: Scales) -> std::io::Result<()> {
+    out.write_all(&trade_payload(tr, scales))?;
+    out.write_all(b"\n")
+}
+
+/// With `KAFKA_AUTO_CREATE_TOPIC=1`, creates `topic` if the cluster doesn't have it yet,
+/// using `KAFKA_TOPIC_PARTITIONS` and `KAFKA_TOPIC_REPLICATION` (both default 1).
+/// An existing topic is left untouched.
+#[cfg(feature = "kafka")]
+async fn ensure_topic(brokers: &str, topic: &str) -> Result<()> {
+    use rdkafka::admin::AdminClient;
+    use rdkafka::client::DefaultClientContext;
+
+    if !config::var("KAFKA_AUTO_CREATE_TOPIC").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")) {
+        return Ok(());
+    }
+    let partitions = config::var("KAFKA_TOPIC_PARTITIONS").ok().and_then(|s| s.parse::<i32>().ok()).unwrap_or(1);
+    let replication = config::var("KAFKA_TOPIC_REPLICATION").ok().and_then(|s| s.parse::<i32>().ok()).unwrap_or(1);
+    let admin: AdminClient<DefaultClientContext> = rdkafka::config::ClientConfig::new().set("bootstrap.servers", brokers).create()?;
+    create_topic_if_missing(&admin, topic, partitions, replication).await.map(|_| ())
+}
+
+/// The cluster calls `ensure_topic` makes.
+#[cfg(feature = "kafka")]
+trait TopicAdmin {
+    fn has_topic(&self, topic: &str) -> Result<bool>;
+    async fn create_topic(&self, topic: &str, partitions: i32, replication: i32) -> Result<Result<(), rdkafka::types::RDKafkaErrorCode>>;
+}
+
+#[cfg(feature = "kafka")]
+impl TopicAdmin for rdkafka::admin::AdminClient<rdkafka::client::DefaultClientContext> {
+    fn has_topic(&self, topic: &str) -> Result<bool> {
+        let metadata = self.inner().fetch_metadata(None, std::time::Duration::from_secs(10))?;
+        Ok(metadata.topics().iter().any(|t| t.name() == topic && t.error().is_none()))
+    }
+
+    async fn create_topic(&self, topic: &str, partitions: i32, replication: i32) -> Result<Result<(), rdkafka::types::RDKafkaErrorCode>> {
+        use rdkafka::admin::{AdminOptions, NewTopic, TopicReplication};
+        let new_topic = NewTopic::new(topic, partitions, TopicReplication::Fixed(replication));
+        let results = self.create_topics(&[new_topic], &AdminOptions::new()).await?;
+        Ok(results.into_iter().next().map_or(Ok(()), |r| r.map(|_| ()).map_err(|(_, code)| code)))
+    }
+}
+
+/// Creates `topic` unless the cluster already has it; `true` if this call created it.
+/// Losing a creation race to another producer counts as already existing.
+#[cfg(feature = "kafka")]
+async fn create_topic_if_missing(admin: &impl TopicAdmin, topic: &str, partitions: i32, replication: i32) -> Result<bool> {
+    use rdkafka::types::RDKafkaErrorCode;
+
+    if admin.has_topic(topic)? {
+        info!("🧾 Kafka topic {} already exists", topic);
+        return Ok(false);
+    }
+    match admin.create_topic(topic, partitions, replication).await? {
+        Ok(()) => {
+            info!("🧾 Created Kafka topic {} ({} partitions, replication {})", topic, partitions, replication);
+            Ok(true)
+        }
+        Err(RDKafkaErrorCode::TopicAlreadyExists) => Ok(false),
+        Err(code) => anyhow::bail!("failed to create Kafka topic {}: {}", topic, code),
+    }
+}
+
+/// Picks the rustls crypto provider named by `TLS_CRYPTO_PROVIDER`: `ring` (default)
+/// or `aws-lc-rs`, which needs the `aws-lc-rs` feature (e.g. for FIPS deployments).
+fn crypto_provider(name: Option<&str>) -> Result<(&'static str, rustls::crypto::CryptoProvider)> {
+    match name.unwrap_or("ring") {
+        "ring" => Ok(("ring", rustls::crypto::ring::default_provider())),
+        #[cfg(feature = "aws-lc-rs")]
+        "aws-lc-rs" => Ok(("aws-lc-rs", rustls::crypto::aws_lc_rs::default_provider())),
+        #[cfg(not(feature = "aws-lc-rs"))]
+        "aws-lc-rs" => anyhow::bail!("TLS_CRYPTO_PROVIDER=aws-lc-rs but ingest was built without the aws-lc-rs feature"),
+        other => anyhow::bail!("unknown TLS_CRYPTO_PROVIDER '{}' (expected ring or aws-lc-rs)", other),
+    }
+}
+
+fn install_crypto_provider() -> Result<()> {
+    let (name, provider) = crypto_provider(config::var("TLS_CRYPTO_PROVIDER").ok().as_deref())?;
+    let _ = provider.install_default();
+    info!("🔐 rustls crypto provider: {}", name);
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    // Logs go to stderr, so stdout carries only the `TRADE_STDOUT` / `ALERT_SINK=stdout` JSON lines
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+    // Info line for slicing logs-derived dashboards by build and instance; the periodic
+    // stats lines carry the same `symbol` field, as do the `/metrics` series
+    let instance = shared::metrics::Instance { build_version: env!("CARGO_PKG_VERSION"), symbols: SYMBOL, symbol: Some(SYMBOL) };
+    info!(build_version = instance.build_version, symbols = instance.symbols, "build_info");
+    if let Ok(addr) = config::var("METRICS_ADDR") {
+        let bound = shared::metrics::serve(&addr, instance)?;
+        info!(%bound, "📈 Serving /metrics");
+    }
     
-    // Initialize rustls crypto provider
-    let _ = rustls::crypto::ring::default_provider().install_default();
+    install_crypto_provider()?;
     
-    let _kafka_brokers = env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string());
-    let kafka_topic = env::var("KAFKA_TOPIC").unwrap_or_else(|_| "solusd-trades".to_string());
+    let _kafka_brokers = config::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string());
+    let kafka_topic = config::var("KAFKA_TOPIC").unwrap_or_else(|_| "solusd-trades".to_string());
     
-    let data_dir = env::var("DATA_DIR").unwrap_or_else(|_| "/tmp/solana_market_data".to_string());
+    let data_dir = config::var("DATA_DIR").unwrap_or_else(|_| "/tmp/solana_market_data".to_string());
     let ob_path = format!("{}/order_book.bin", data_dir);
     let tob_path = format!("{}/top_of_book.bin", data_dir);
+    let scales = symbol_details::load_scales(SYMBOL, &data_dir).await;
     
-    let (_ob_mmap, order_book) = OrderBook::mmap(std::path::Path::new(&ob_path))?;
-    let (_tob_mmap, top) = TopOfBook::mmap(std::path::Path::new(&tob_path))?;
-    
-    info!("📁 Order Book: {}", ob_path);
+    // l1-only symbols skip the v2 depth feed and never touch the OrderBook file
+    let l1_only = symbol_listed("L1_ONLY_SYMBOLS");
+    // Coalesced symbols only rewrite TopOfBook (and its timestamp) when a quote actually changes
+    let coalesce_tob = symbol_listed("TOB_COALESCE_SYMBOLS");
+    // Attach the received frame text to each trade for audit storage
+    let store_raw = symbol_listed("RAW_TRADE_SYMBOLS");
+    // Symbols quoted the other way round are stored as 1/price with sides swapped
+    let prices = PriceConvention { scale: scales.price, invert: symbol_listed("INVERT_PRICE_SYMBOLS") };
+    // Seed the book from REST on each v2 connect instead of waiting for the feed's snapshot
+    let rest_warmup = symbol_listed("REST_WARMUP_SYMBOLS");
+    if prices.invert {
+        info!("🔃 {} prices are inverted (1/price, bid/ask swapped)", SYMBOL);
+    }
+    let (ob_mmap, mut order_book) = match map_order_book(&ob_path, l1_only)? {
+        Some((mmap, ob)) => {
+            info!("📁 Order Book: {}", ob_path);
+            (Some(mmap), Some(ob))
+        }
+        None => {
+            info!("📉 {} is l1-only: skipping order book depth feed", SYMBOL);
+            (None, None)
+        }
+    };
+    // Per-symbol depth from `BOOK_DEPTHS` (e.g. `SOLUSD:20`), checked against the file so a
+    // misconfiguration fails here instead of being silently truncated
+    let depth = match (symbol_setting("BOOK_DEPTHS"), order_book.as_deref()) {
+        (Some(v), Some(ob)) => {
+            let d = v.parse::<usize>().map_err(|e| anyhow::anyhow!("BOOK_DEPTHS value '{}' for {}: {}", v, SYMBOL, e))?;
+            ob.check_depth(d)?
+        }
+        _ => BOOK_DEPTH,
+    };
+    let (tob_mmap, top) = TopOfBook::mmap(std::path::Path::new(&tob_path))?;
     info!("📁 Top of Book: {}", tob_path);
+    // `TOB_SOURCE` (`SYMBOL:v1|v2-derived`): v2-derived fills TopOfBook from the v2 book
+    // and never opens the v1 socket, which is also the only trade source
+    let tob_source = TobSource::parse(symbol_setting("TOB_SOURCE").as_deref(), l1_only)?;
+    // Dust filter from `TRADE_MIN_NOTIONAL` (`SYMBOL:venue quote units`, 0 = off); a trade exactly
+    // at the threshold is kept. A value that does not parse fails here rather than disabling it
+    let min_notional = match symbol_setting("TRADE_MIN_NOTIONAL") {
+        Some(v) => parse_min_notional(&v)
+            .ok_or_else(|| anyhow::anyhow!("TRADE_MIN_NOTIONAL value '{}' for {} is not a non-negative number", v, SYMBOL))?,
+        None => 0.0,
+    };
+    let min_notional = (min_notional > 0.0).then_some(min_notional);
+    if tob_source == TobSource::V2Derived {
+        warn!("📐 {} top of book derived from v2; v1 is not opened, so no trades are captured", SYMBOL);
+    }
+    let (mut top, mut derived_top) = tob_source.route(top);
+
+    // Make both files self-describing for readers
+    if let Some(ob) = order_book.as_deref_mut() {
+        ob.set_meta(BookMeta::new(SYMBOL, scales.price, scales.qty, scales.price_tick, depth));
+    }
+    if let Some(t) = top.as_deref_mut().or(derived_top.as_deref_mut()) {
+        t.set_meta(BookMeta::new(SYMBOL, scales.price, scales.qty, scales.price_tick, 1));
+    }
+
+    // The mmap survives restarts, so resume from whatever depth was last published
+    // until the next v2 snapshot reconciles it.
+    if let Some(ob) = order_book.as_deref() {
+        let (resumed_bids, resumed_asks) = ob.active_levels();
+        if resumed_bids > 0 || resumed_asks > 0 {
+            info!("♻️  Resuming order book with {} bid / {} ask levels (ts {})", resumed_bids, resumed_asks, ob.timestamp_ms);
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    redis_tob::start(SYMBOL, scales)?;
+
+    let alerts = alert::from_env()?;
+    let alerts_v1 = Arc::clone(&alerts);
+    let kill_switch = KillSwitch::from_env(SYMBOL);
+    let kill_switch_v1 = kill_switch.clone();
+    let mut reconnect = Reconnect::from_env(SYMBOL, "v2")?;
+    let mut reconnect_v1 = Reconnect::from_env(SYMBOL, "v1")?;
+
+    #[cfg(feature = "kafka")]
+    ensure_topic(&_kafka_brokers, &kafka_topic).await?;
+    #[cfg(not(any(feature = "kafka", feature = "pulsar")))]
+    let trade_stdout = config::var("TRADE_STDOUT").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    #[cfg(not(any(feature = "kafka", feature = "pulsar")))]
+    announce_trade_stdout(trade_stdout);
 
     // Clone variables for tasks
+    #[cfg(feature = "kafka")]
     let kafka_brokers_v1 = _kafka_brokers.clone();
     let kafka_topic_v1 = kafka_topic.clone();
 
     // v2 order book (depth) task
     let ob_task = tokio::spawn(async move {
+        let Some(order_book) = order_book else { return };
+        let mut inter_arrival = InterArrival::from_env();
+        let mut publish_latency = PublishLatency::from_env(Feed::V2);
+        let mut crossed_rate = CrossedRate::default();
+        let mut book_status = order_book.status();
+        let mut resync_dwell = ResyncDwell::from_env();
+        let mut cross_check = CrossCheck::from_env();
+        let mut book_crossing = (order_book.is_crossed(), order_book.is_locked());
+        // Alert when either side stays under `MIN_ACTIVE_LEVELS` for `THIN_BOOK_DEBOUNCE_MS` (default 5000)
+        let mut thin_book = symbol_setting("MIN_ACTIVE_LEVELS").and_then(|v| v.parse::<usize>().ok()).filter(|n| *n > 0).map(|min| {
+            let debounce_ms = config::var("THIN_BOOK_DEBOUNCE_MS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(5000);
+            ThinBookAlarm::new(min, std::time::Duration::from_millis(debounce_ms))
+        });
+        let first_data_timeout = std::time::Duration::from_secs(
+            config::var("SUBSCRIBE_DATA_TIMEOUT_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(10));
         loop {
+            kill_switch.wait_enabled().await;
             info!("Connecting to Gemini v2 API...");
             let url = "wss://api.gemini.com/v2/marketdata";
             match connect_async(url).await {
                 Ok((ws, _)) => {
                     info!("✅ Connected to Gemini v2 API");
+                    reconnect.on_connected();
+                    // Fetched before subscribing, so every v2 frame is newer than the seed
+                    let mut warmup_seed = None;
+                    if rest_warmup {
+                        match warmup::fetch_book(SYMBOL, depth, prices, scales.qty).await {
+                            Ok(seed) => {
+                                let (bids, asks) = (seed.bids.clone().unwrap_or_default(), seed.asks.clone().unwrap_or_default());
+                                info!("🌱 Seeded {} book from REST with {} bid / {} ask levels", SYMBOL, bids.len(), asks.len());
+                                seed.publish(order_book, &mut cross_check);
+                                if let Some(top) = derived_top.as_deref_mut() { derive_top(order_book, top); }
+                                warmup_seed = Some((bids, asks));
+                            }
+                            Err(e) => {
+                                let total = errors::record(ErrorCategory::Connect);
+                                warn!(category = %ErrorCategory::Connect, errors_total = total, "⚠️  REST warmup for {} failed ({}), waiting for the v2 snapshot", SYMBOL, e);
+                            }
+                        }
+                    }
                     let (mut write, mut read) = ws.split();
-                    // Subscribe to L2 (order book) for SOLUSD
+                    // Subscribe to L2 (order book) for SYMBOL
                     let sub = serde_json::json!({
                         "type": "subscribe",
-                        "subscriptions": [{"name": "l2","symbols":["SOLUSD"]}]
+                        "subscriptions": [{"name": "l2","symbols":[SYMBOL]}]
                     });
                     let _ = write.send(Message::Text(sub.to_string())).await;
-                    info!("📊 Subscribed to SOLUSD L2 order book");
-                    
-                    while let Some(msg) = read.next().await {
+                    info!("📊 Subscribed to {} L2 order book", SYMBOL);
+                    shared::metrics::expect_data(SYMBOL);
+
+                    let mut keepalive = Keepalive::from_env("v2");
+                    let mut ping_timer = keepalive.timer();
+                    // A silently partial subscription would leave the book empty forever
+                    let first_data_deadline = tokio::time::sleep(first_data_timeout);
+                    tokio::pin!(first_data_deadline);
+                    let (mut got_data, mut no_data_reported) = (false, false);
+                    let mut side_buffer = SideBuffer::from_env();
+                    loop {
+                        let side_deadline = side_buffer.deadline();
+                        let dwell_deadline = resync_dwell.deadline();
+                        let msg = tokio::select! {
+                            msg = read.next() => msg,
+                            _ = kill_switch.disabled() => {
+                                // Readers see the book as disabled; reconnecting re-seeds it on enable
+                                order_book.mark_disabled();
+                                if let Some(top) = derived_top.as_deref_mut() { top.mark_disabled(); }
+                                break;
+                            }
+                            _ = tokio::time::sleep_until(dwell_deadline.unwrap_or_else(tokio::time::Instant::now)), if dwell_deadline.is_some() => {
+                                if let Some(held) = resync_dwell.take_due(tokio::time::Instant::now()) {
+                                    info!("🧊 {} resync dwell over, applying the held snapshot", SYMBOL);
+                                    held.publish(order_book, &mut cross_check);
+                                    if let Some(top) = derived_top.as_deref_mut() { derive_top(order_book, top); }
+                                }
+                                continue;
+                            }
+                            _ = tokio::time::sleep_until(side_deadline.unwrap_or_else(tokio::time::Instant::now)), if side_deadline.is_some() => {
+                                if let Some(partial) = side_buffer.take() {
+                                    info!("🌓 {} snapshot side unpaired after wait, publishing it alone", SYMBOL);
+                                    resync_dwell.publish(partial, order_book, &mut cross_check);
+                                    if let Some(top) = derived_top.as_deref_mut() { derive_top(order_book, top); }
+                                }
+                                continue;
+                            }
+                            _ = ping_timer.tick() => {
+                                if !keepalive.on_tick() {
+                                    let total = errors::record(ErrorCategory::Stale);
+                                    warn!(category = %ErrorCategory::Stale, errors_total = total, "🔄 v2 connection unresponsive, reconnecting");
+                                    alerts.send(Alert::now(SYMBOL, "v2_unresponsive", keepalive.missed() as f64));
+                                    break;
+                                }
+                                let _ = write.send(Message::Ping(Vec::new())).await;
+                                continue;
+                            }
+                            _ = &mut first_data_deadline, if !got_data && !no_data_reported => {
+                                let total = errors::record(ErrorCategory::Stale);
+                                warn!(category = %ErrorCategory::Stale, errors_total = total, "🔇 No {} L2 data within {:?} of subscribing; subscription may not cover it", SYMBOL, first_data_timeout);
+                                alerts.send(Alert::now(SYMBOL, "v2_no_data", first_data_timeout.as_secs_f64()));
+                                no_data_reported = true;
+                                continue;
+                            }
+                        };
+                        let Some(msg) = msg else { break };
+                        match msg {
+                            Ok(Message::Pong(_)) => { keepalive.on_pong(); continue; }
+                            Ok(Message::Ping(payload)) => { let _ = write.send(Message::Pong(payload)).await; continue; }
+                            _ => {}
+                        }
                         if let Ok(Message::Text(txt)) = msg {
-                            if let Ok(v) = serde_json::from_str::<serde_json::Value>(&txt) {
+                            let recv_at = std::time::Instant::now();
+                            let parsed = serde_json::from_str::<serde_json::Value>(&txt);
+                            if let Err(e) = &parsed {
+                                let total = errors::record(ErrorCategory::Parse);
+                                warn!(category = %ErrorCategory::Parse, errors_total = total, "⚠️  Unparsable v2 frame: {}", e);
+                            }
+                            if let Ok(v) = parsed {
+                                let book_frame = v.get("bids").is_some() || v.get("asks").is_some() || v.get("changes").is_some();
+                                if book_frame && !got_data && v.get("symbol").and_then(|s| s.as_str()).is_none_or(|s| s.eq_ignore_ascii_case(SYMBOL)) {
+                                    got_data = true;
+                                    shared::metrics::data_arrived(SYMBOL);
+                                    if no_data_reported { info!("🔊 {} L2 data now streaming", SYMBOL); }
+                                }
                                 // Try to parse snapshot or updates - forgiving schema
-                                if let Some(bids) = v.get("bids").and_then(|x| x.as_array()) {
-                                    for (i, lvl) in bids.iter().take(BOOK_DEPTH).enumerate() {
-                                        let p = lvl.get(0).and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                        let q = lvl.get(1).and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                        order_book.update_bid(i, (p*1_000_000.0) as u64, (q*1_000_000.0) as u64);
-                                    }
-                                    if let Some(ts) = v.get("timestampms").and_then(|t| t.as_u64()) { order_book.set_ts(ts); }
+                                let mut snap_bids = v.get("bids").and_then(|x| x.as_array());
+                                let mut snap_asks = v.get("asks").and_then(|x| x.as_array());
+                                if prices.invert {
+                                    std::mem::swap(&mut snap_bids, &mut snap_asks);
                                 }
-                                if let Some(asks) = v.get("asks").and_then(|x| x.as_array()) {
-                                    for (i, lvl) in asks.iter().take(BOOK_DEPTH).enumerate() {
-                                        let p = lvl.get(0).and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                        let q = lvl.get(1).and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                        order_book.update_ask(i, (p*1_000_000.0) as u64, (q*1_000_000.0) as u64);
+                                if snap_bids.is_some() || snap_asks.is_some() {
+                                    let parse_side = |lvls: &Vec<serde_json::Value>| -> Vec<OrderLevel> {
+                                        lvls.iter().take(depth).map(|lvl| OrderLevel {
+                                            price: lvl.get(0).and_then(|x| prices.price(x)).unwrap_or(0),
+                                            qty: lvl.get(1).and_then(|x| parse_scaled(x, scales.qty)).unwrap_or(0),
+                                        }).collect()
+                                    };
+                                    // A lone side waits briefly for its partner frame so both publish together
+                                    let ts = v.get("timestampms").and_then(|t| t.as_u64()).unwrap_or(order_book.timestamp_ms);
+                                    if let Some(ready) = side_buffer.offer(snap_bids.map(parse_side), snap_asks.map(parse_side), ts) {
+                                        resync_dwell.publish(ready, order_book, &mut cross_check);
                                     }
-                                    if let Some(ts) = v.get("timestampms").and_then(|t| t.as_u64()) { order_book.set_ts(ts); }
                                 }
                                 // Handle incremental change-like messages (best-effort)
                                 if let Some(changes) = v.get("changes").and_then(|x| x.as_array()) {
-                                    for ch in changes.iter() {
-                                        if let (Some(side), Some(price), Some(qty)) = (
-                                            ch.get(0).and_then(|x| x.as_str()),
-                                            ch.get(1).and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()),
-                                            ch.get(2).and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()),
-                                        ) {
-                                            let pu = (price*1_000_000.0) as u64; 
-                                            let qu = (qty*1_000_000.0) as u64;
-                                            
-                                            // Simple approach: update first few levels based on price ordering
-                                            if side.eq_ignore_ascii_case("buy") {
-                                                // For bids, higher prices should be at lower indices
-                                                for i in 0..BOOK_DEPTH.min(10) {
-                                                    let current_price = order_book.bids[i].load_price();
-                                                    if qu == 0 && current_price == pu {
-                                                        // Remove this level by shifting everything up
-                                                        order_book.update_bid(i, 0, 0);
-                                                        break;
-                                                    } else if current_price == 0 || pu > current_price {
-                                                        // Insert/update at this level
-                                                        order_book.update_bid(i, pu, qu);
-                                                        break;
-                                                    } else if current_price == pu {
-                                                        // Update existing level
-                                                        order_book.update_bid(i, pu, qu);
-                                                        break;
-                                                    }
-                                                }
-                                            } else {
-                                                // For asks, lower prices should be at lower indices  
-                                                for i in 0..BOOK_DEPTH.min(10) {
-                                                    let current_price = order_book.asks[i].load_price();
-                                                    if qu == 0 && current_price == pu {
-                                                        // Remove this level
-                                                        order_book.update_ask(i, 0, 0);
-                                                        break;
-                                                    } else if current_price == 0 || (current_price > pu && pu > 0) {
-                                                        // Insert/update at this level
-                                                        order_book.update_ask(i, pu, qu);
-                                                        break;
-                                                    } else if current_price == pu {
-                                                        // Update existing level
-                                                        order_book.update_ask(i, pu, qu);
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                        }
+                                    // Changes build on the snapshot, so a held side goes out first
+                                    if let Some(partial) = side_buffer.take() {
+                                        resync_dwell.publish(partial, order_book, &mut cross_check);
                                     }
-                                    if let Some(ts) = v.get("timestampms").and_then(|t| t.as_u64()) { 
-                                        order_book.set_ts(ts); 
+                                    // Apply the whole batch to a copy, then publish it under one seqlock
+                                    // bump so readers see all of a frame's changes or none of them
+                                    let mut bids: Vec<OrderLevel> = order_book.bids.iter().map(OrderLevel::load).collect();
+                                    let mut asks: Vec<OrderLevel> = order_book.asks.iter().map(OrderLevel::load).collect();
+                                    apply_changes(changes, &mut bids, &mut asks, depth, prices, scales.qty);
+                                    // A snapshot held back by the resync dwell follows the deltas too
+                                    if let Some(held) = resync_dwell.held_mut() {
+                                        apply_changes(changes, held.bids.as_deref_mut().unwrap_or_default(), held.asks.as_deref_mut().unwrap_or_default(), depth, prices, scales.qty);
+                                    }
+                                    let ts = v.get("timestampms").and_then(|t| t.as_u64()).unwrap_or(order_book.timestamp_ms);
+                                    cross_check.publish(order_book, &bids, &asks, ts);
+                                }
+                                if let Some(top) = derived_top.as_deref_mut() {
+                                    derive_top(order_book, top);
+                                }
+                                // One-sided or empty books (e.g. halts) have no mid; say so once per transition
+                                let status = order_book.status();
+                                if status != book_status {
+                                    info!(symbol = SYMBOL, %status, "⚖️  book is now {}", status);
+                                    book_status = status;
+                                }
+                                // A crossed or locked book corrupts downstream signals; warn once per episode
+                                let crossing = (order_book.is_crossed(), order_book.is_locked());
+                                if crossing != book_crossing {
+                                    if crossing.0 || crossing.1 {
+                                        let (bid, ask) = (order_book.best_bid().unwrap_or_default(), order_book.best_ask().unwrap_or_default());
+                                        let total = errors::record(ErrorCategory::Crossed);
+                                        warn!(category = %ErrorCategory::Crossed, errors_total = total, symbol = SYMBOL, "❌ book is {}: best bid {} vs best ask {}",
+                                            if crossing.0 { "crossed" } else { "locked" }, shared::format::scaled(bid.price, scales.price), shared::format::scaled(ask.price, scales.price));
+                                    } else {
+                                        info!(symbol = SYMBOL, "✅ book no longer crossed or locked");
+                                    }
+                                    book_crossing = crossing;
+                                }
+                                // Sampled after the frame is applied, so the stats describe the book readers see
+                                if book_frame {
+                                    crossed_rate.observe(crossing.0 || crossing.1);
+                                    if let Some(h) = inter_arrival.record(recv_at) {
+                                        info!(symbol = SYMBOL, "⏲️  book update gaps: n={} p50={:?} p99={:?} max={:?}", h.count(), h.quantile(0.50), h.quantile(0.99), h.max());
+                                        let (bid_levels, ask_levels) = order_book.active_levels();
+                                        let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+                                        let quality = BookQuality {
+                                            age_ms: now_ms.saturating_sub(order_book.timestamp_ms),
+                                            bid_levels,
+                                            ask_levels,
+                                            spread_bps: order_book.spread_bps(),
+                                            crossed_rate: crossed_rate.take(),
+                                        };
+                                        let score = book_quality_score(&quality);
+                                        shared::metrics::set_book_quality(score);
+                                        info!(symbol = SYMBOL, "🩺 book quality {}/100 ({:?})", score, quality);
+                                        let totals: Vec<String> = errors::totals().iter().map(|(c, n)| format!("{}={}", c, n)).collect();
+                                        info!(symbol = SYMBOL, "🧮 errors_total {}", totals.join(" "));
+                                    }
+                                }
+                                if let Some(alarm) = thin_book.as_mut() {
+                                    let (bid_levels, ask_levels) = order_book.active_levels();
+                                    if let Some(alert) = alarm.observe(SYMBOL, bid_levels, ask_levels, tokio::time::Instant::now()) {
+                                        warn!(symbol = SYMBOL, "🏜️  book thinned to {} bid / {} ask levels", bid_levels, ask_levels);
+                                        alerts.send(alert);
+                                    }
+                                }
+                                // The first v2 book frame supersedes the REST seed; report how far apart they were
+                                if v.get("changes").is_some() || (!side_buffer.has_pending() && (snap_bids.is_some() || snap_asks.is_some())) {
+                                    if let Some((seed_bids, seed_asks)) = warmup_seed.take() {
+                                        let bids: Vec<OrderLevel> = order_book.bids.iter().map(OrderLevel::load).collect();
+                                        let asks: Vec<OrderLevel> = order_book.asks.iter().map(OrderLevel::load).collect();
+                                        info!("🔁 {} v2 snapshot reconciled REST warmup: {} bid / {} ask levels differed",
+                                            SYMBOL, warmup::differing_levels(&seed_bids, &bids), warmup::differing_levels(&seed_asks, &asks));
+                                    }
+                                }
+                                if snap_bids.is_some() || snap_asks.is_some() || v.get("changes").is_some() {
+                                    if let Some(h) = publish_latency.record(recv_at) {
+                                        info!(symbol = SYMBOL, "⚙️  v2 receive-to-publish: n={} p50={:?} p99={:?} max={:?}", h.count(), h.quantile(0.50), h.quantile(0.99), h.max());
                                     }
                                 }
                             }
@@ -127,9 +665,10 @@ async fn main() -> Result<()> {
                     }
                 }
                 Err(e) => {
-                    error!("❌ Failed to connect to Gemini v2 API: {}", e);
-                    warn!("🔄 Retrying v2 connection in 5 seconds...");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    let category = connect_category(&e);
+                    let total = errors::record(category);
+                    error!(%category, errors_total = total, "❌ Failed to connect to Gemini v2 API: {}", e);
+                    reconnect.on_failure(alerts.as_ref()).await;
                 }
             }
         }
@@ -137,71 +676,228 @@ async fn main() -> Result<()> {
 
     // v1 top-of-book + trades task
     let top_task = tokio::spawn(async move {
+        let Some(top) = top else { return };
+        let mut rejected_trades = 0u64;
+        let mut qty_filter = qty_filter_from_env(scales.qty);
+        let mut oversized_trades = 0u64;
+        let mut dust_trades = 0u64;
+        let mut publish_latency = PublishLatency::from_env(Feed::V1);
+        // Set by a sequence gap or a reconnect, cleared once a trade carrying it is enqueued
+        let mut gap_flag = GapFlag::default();
+        #[cfg(any(feature = "kafka", feature = "pulsar"))]
+        let mut produce_limit = ProduceLimit::from_env();
+        // One producer for the task's lifetime, so trades are enqueued in feed order
+        #[cfg(feature = "kafka")]
+        let producer: rdkafka::producer::FutureProducer = rdkafka::config::ClientConfig::new()
+            .set("bootstrap.servers", &kafka_brokers_v1)
+            .create()
+            .expect("producer");
+        #[cfg(feature = "pulsar")]
+        let mut producer = {
+            let pulsar_url = config::var("PULSAR_URL").unwrap_or_else(|_| "pulsar://localhost:6650".to_string());
+            let pulsar: pulsar::Pulsar<_> = pulsar::PulsarBuilder::new(pulsar_url, pulsar::TokioExecutor).build().await.expect("pulsar client");
+            pulsar.producer()
+                .with_topic(&kafka_topic_v1) // reuse topic env var
+                .with_name("gemini-trades")
+                .build()
+                .await
+                .expect("pulsar producer")
+        };
         loop {
+            kill_switch_v1.wait_enabled().await;
             info!("Connecting to Gemini v1 API...");
-            let url = "wss://api.gemini.com/v1/marketdata/SOLUSD";
-            match connect_async(url).await {
+            let url = format!("wss://api.gemini.com/v1/marketdata/{}", SYMBOL);
+            match connect_async(url.as_str()).await {
                 Ok((ws, _)) => {
                     info!("✅ Connected to Gemini v1 API");
+                    reconnect_v1.on_connected();
                     let (mut write, mut read) = ws.split();
-                    info!("📈 Subscribed to SOLUSD top-of-book and trades");
-                    
-                    while let Some(msg) = read.next().await {
+                    info!("📈 Subscribed to {} top-of-book and trades", SYMBOL);
+
+                    let mut keepalive = Keepalive::from_env("v1");
+                    let mut ping_timer = keepalive.timer();
+                    let mut gaps = GapDetector::default();
+                    let mut last_tid: Option<u64> = None;
+                    gap_flag.on_connected();
+                    loop {
+                        let msg = tokio::select! {
+                            msg = read.next() => msg,
+                            _ = kill_switch_v1.disabled() => {
+                                top.mark_disabled();
+                                break;
+                            }
+                            _ = ping_timer.tick() => {
+                                if !keepalive.on_tick() {
+                                    let total = errors::record(ErrorCategory::Stale);
+                                    warn!(category = %ErrorCategory::Stale, errors_total = total, "🔄 v1 connection unresponsive, reconnecting");
+                                    alerts_v1.send(Alert::now(SYMBOL, "v1_unresponsive", keepalive.missed() as f64));
+                                    break;
+                                }
+                                let _ = write.send(Message::Ping(Vec::new())).await;
+                                continue;
+                            }
+                        };
+                        let Some(msg) = msg else { break };
                         match msg {
                             Ok(Message::Text(txt)) => {
-                                if let Ok(v) = serde_json::from_str::<serde_json::Value>(&txt) {
+                                let recv_at = std::time::Instant::now();
+                                let parsed = serde_json::from_str::<serde_json::Value>(&txt);
+                                if let Err(e) = &parsed {
+                                    let total = errors::record(ErrorCategory::Parse);
+                                    warn!(category = %ErrorCategory::Parse, errors_total = total, "⚠️  Unparsable v1 frame: {}", e);
+                                }
+                                if let Ok(v) = parsed {
+                                    if let Some(seq) = v.get("socket_sequence").and_then(|s| s.as_u64()) {
+                                        if let Some((from, to)) = gaps.observe(seq) {
+                                            gap_flag.on_gap();
+                                            let total = errors::record(ErrorCategory::Resync);
+                                            warn!(category = %ErrorCategory::Resync, errors_total = total, "🕳️  {} v1 sequence gap: missed {}..={} after trade id {:?}; trades in that range may be missing", SYMBOL, from, to, last_tid);
+                                            alerts_v1.send(Alert::now(SYMBOL, "v1_sequence_gap", (to - from + 1) as f64));
+                                        }
+                                    }
                                     if let Some(events) = v.get("events").and_then(|e| e.as_array()) {
                                         let ts = v.get("timestampms").and_then(|t| t.as_u64()).unwrap_or(0);
                                         for e in events {
                                             if let Some(t) = e.get("type").and_then(|x| x.as_str()) {
                                                 match t {
                                                     "change" => {
-                                                        let side = e.get("side").and_then(|x| x.as_str()).unwrap_or("");
-                                                        let price = e.get("price").and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                                        let rem = e.get("remaining").and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                                        if side == "bid" { top.set_bid((price*1_000_000.0) as u64, (rem*1_000_000.0) as u64); }
-                                                        if side == "ask" { top.set_ask((price*1_000_000.0) as u64, (rem*1_000_000.0) as u64); }
-                                                        top.set_ts(ts);
+                                                        let side = e.get("side").and_then(|x| x.as_str()?.parse::<Side>().ok()).map(|s| prices.side(s));
+                                                        let price = e.get("price").and_then(|x| prices.price(x)).unwrap_or(0);
+                                                        let rem = e.get("remaining").and_then(|x| parse_scaled(x, scales.qty)).unwrap_or(0);
+                                                        let changed = if coalesce_tob {
+                                                            match side {
+                                                                Some(Side::Buy) => top.set_bid_if_changed(price, rem),
+                                                                Some(Side::Sell) => top.set_ask_if_changed(price, rem),
+                                                                None => false,
+                                                            }
+                                                        } else {
+                                                            match side {
+                                                                Some(Side::Buy) => top.set_bid(price, rem),
+                                                                Some(Side::Sell) => top.set_ask(price, rem),
+                                                                None => {}
+                                                            }
+                                                            true
+                                                        };
+                                                        // After a re-enable the first quote clears the disabled stamp even if unchanged
+                                                        if changed || top.is_disabled() {
+                                                            top.set_ts(ts);
+                                                            mirror_top(top);
+                                                            if let Some(h) = publish_latency.record(recv_at) {
+                                                                info!(symbol = SYMBOL, "⚙️  v1 receive-to-publish: n={} p50={:?} p99={:?} max={:?}", h.count(), h.quantile(0.50), h.quantile(0.99), h.max());
+                                                            }
+                                                        }
                                                     },
                                                     "trade" => {
-                                                        let price = e.get("price").and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                                        let qty = e.get("amount").and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                                                        let side = e.get("makerSide").and_then(|x| x.as_str()).unwrap_or("");
-                                                        let tr = TradeEvent { ts_ms: ts, symbol: "SOLUSD".into(), price_u: (price*1_000_000.0) as u64, qty_u: (qty*1_000_000.0) as u64, side: side.into() };
+                                                        let venue_price = e.get("price").and_then(|x| parse_scaled(x, scales.price)).unwrap_or(0);
+                                                        let price = prices.normalize(venue_price);
+                                                        let qty = e.get("amount").and_then(|x| parse_scaled(x, scales.qty)).unwrap_or(0);
+                                                        let maker_side = e.get("makerSide").and_then(|x| x.as_str()).filter(|s| !s.is_empty()).map(str::parse::<Side>).transpose();
+                                                        let trade_id = e.get("tid").and_then(|x| x.as_u64());
+                                                        if trade_id.is_some() { last_tid = trade_id; }
+                                                        if qty_filter.as_mut().is_some_and(|f| !f.accept(qty)) {
+                                                            oversized_trades += 1;
+                                                            warn!("🚫 Dropping {} trade {:?} with implausible size {} ({} oversized so far)", SYMBOL, trade_id, qty, oversized_trades);
+                                                            continue;
+                                                        }
+                                                        if let Some(min) = min_notional {
+                                                            let notional = prices.venue_notional(venue_price, qty, scales.qty);
+                                                            if notional < min {
+                                                                dust_trades += 1;
+                                                                if dust_trades.is_power_of_two() {
+                                                                    info!(symbol = SYMBOL, "🧹 {} dust trades below {} notional dropped so far", dust_trades, min);
+                                                                }
+                                                                continue;
+                                                            }
+                                                        }
+                                                        let (bid_at, ask_at) = prevailing_quote(top);
+                                                        let built = maker_side.and_then(|maker| {
+                                                            let (side, side_inferred) = taker_side(maker, &prices, price, bid_at, ask_at);
+                                                            TradeEvent::builder(SYMBOL)
+                                                                .ts_ms(ts).price_u(price).qty_u(qty).side(side, side_inferred)
+                                                                .trade_id(trade_id).quote(bid_at, ask_at)
+                                                                .raw(store_raw.then(|| txt.clone()))
+                                                                .gap_before(gap_flag.pending())
+                                                                .build()
+                                                        });
+                                                        let tr = match built {
+                                                            Ok(tr) => tr,
+                                                            Err(err) => {
+                                                                rejected_trades += 1;
+                                                                let total = errors::record(ErrorCategory::Parse);
+                                                                warn!(category = %ErrorCategory::Parse, errors_total = total, "🚫 Dropping invalid {} trade {:?}: {} ({} rejected so far)", SYMBOL, trade_id, err, rejected_trades);
+                                                                continue;
+                                                            }
+                                                        };
                                                         #[cfg(feature = "kafka")]
                                                         {
-                                                            let producer: rdkafka::producer::FutureProducer = rdkafka::config::ClientConfig::new()
-                                                                .set("bootstrap.servers", &kafka_brokers_v1)
-                                                                .create()
-                                                                .expect("producer");
-                                                            let payload = serde_json::to_vec(&serde_json::json!({
-                                                                "ts_ms": tr.ts_ms, "symbol": tr.symbol, "price_u": tr.price_u, "qty_u": tr.qty_u, "side": tr.side
-                                                            })).unwrap();
-                                                            let _ = producer
-                                                                .send(
-                                                                    rdkafka::producer::FutureRecord::to(&kafka_topic_v1).payload(&payload),
-                                                                    std::time::Duration::from_secs(0),
-                                                                )
-                                                                .await;
+                                                            let payload = trade_payload(&tr, scales);
+                                                            // Enqueue here to keep trade order; only the delivery wait runs detached
+                                                            let permit = produce_limit.acquire().await;
+                                                            let mut record = rdkafka::producer::FutureRecord::<(), _>::to(&kafka_topic_v1).payload(&payload);
+                                                            // A full local queue drains as librdkafka delivers; wait for room rather than drop the trade
+                                                            let enqueued = loop {
+                                                                match producer.send_result(record) {
+                                                                    Err((rdkafka::error::KafkaError::MessageProduction(rdkafka::types::RDKafkaErrorCode::QueueFull), back)) => {
+                                                                        record = back;
+                                                                        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                                                                    }
+                                                                    other => break other,
+                                                                }
+                                                            };
+                                                            match enqueued {
+                                                                Ok(delivery) => {
+                                                                    gap_flag.on_published();
+                                                                    count_published(&tr, scales);
+                                                                    tokio::spawn(async move {
+                                                                        let _permit = permit;
+                                                                        let err = match delivery.await {
+                                                                            Ok(Ok(_)) => return,
+                                                                            Ok(Err((e, _))) => e.to_string(),
+                                                                            Err(_) => "delivery canceled".to_string(),
+                                                                        };
+                                                                        let total = errors::record(ErrorCategory::Produce);
+                                                                        warn!(category = %ErrorCategory::Produce, errors_total = total, "⚠️  Failed to publish {} trade to Kafka: {}", SYMBOL, err);
+                                                                    });
+                                                                }
+                                                                Err((e, _)) => {
+                                                                    let total = errors::record(ErrorCategory::Produce);
+                                                                    warn!(category = %ErrorCategory::Produce, errors_total = total, "⚠️  Failed to publish {} trade to Kafka: {}", SYMBOL, e);
+                                                                }
+                                                            }
                                                         }
                                                         #[cfg(feature = "pulsar")]
                                                         {
-                                                            let pulsar_url = std::env::var("PULSAR_URL").unwrap_or_else(|_| "pulsar://localhost:6650".to_string());
-                                                            let pulsar: pulsar::Pulsar<_> = pulsar::PulsarBuilder::new(pulsar_url, pulsar::TokioExecutor).build().await.expect("pulsar client");
-                                                            let mut producer = pulsar.producer()
-                                                                .with_topic(&kafka_topic_v1) // reuse topic env var
-                                                                .with_name("gemini-trades")
-                                                                .build()
-                                                                .await
-                                                                .expect("pulsar producer");
-                                                            let payload = serde_json::to_vec(&serde_json::json!({
-                                                                "ts_ms": tr.ts_ms, "symbol": tr.symbol, "price_u": tr.price_u, "qty_u": tr.qty_u, "side": tr.side
-                                                            })).unwrap();
-                                                            let _ = producer.send(payload).await;
+                                                            // Keyed by symbol so Key_Shared consumers keep each symbol's trades in order
+                                                            let permit = produce_limit.acquire().await;
+                                                            let enqueued = producer.create_message().with_content(trade_payload(&tr, scales)).with_key(SYMBOL).send_non_blocking().await;
+                                                            match enqueued {
+                                                                Ok(receipt) => {
+                                                                    gap_flag.on_published();
+                                                                    count_published(&tr, scales);
+                                                                    tokio::spawn(async move {
+                                                                        let _permit = permit;
+                                                                        if let Err(e) = receipt.await {
+                                                                            let total = errors::record(ErrorCategory::Produce);
+                                                                            warn!(category = %ErrorCategory::Produce, errors_total = total, "⚠️  Failed to publish {} trade to Pulsar: {}", SYMBOL, e);
+                                                                        }
+                                                                    });
+                                                                }
+                                                                Err(e) => {
+                                                                    let total = errors::record(ErrorCategory::Produce);
+                                                                    warn!(category = %ErrorCategory::Produce, errors_total = total, "⚠️  Failed to publish {} trade to Pulsar: {}", SYMBOL, e);
+                                                                }
+                                                            }
                                                         }
                                                         #[cfg(not(any(feature = "kafka", feature = "pulsar")))]
                                                         {
-                                                            let _ = (tr, &kafka_topic_v1); // suppress unused warnings
+                                                            let _ = &kafka_topic_v1;
+                                                            gap_flag.on_published();
+                                                            if trade_stdout {
+                                                                if let Err(e) = write_trade_line(&mut std::io::stdout().lock(), &tr, scales) {
+                                                                    warn!(?e, "failed to write trade to stdout");
+                                                                }
+                                                                count_published(&tr, scales);
+                                                            }
                                                         }
                                                     },
                                                     _ => {}
@@ -211,22 +907,292 @@ async fn main() -> Result<()> {
                                     }
                                 }
                             },
-                            Ok(Message::Ping(_)) => {
-                                let _ = write.send(Message::Pong(vec![])).await;
+                            Ok(Message::Ping(payload)) => {
+                                let _ = write.send(Message::Pong(payload)).await;
                             }
+                            Ok(Message::Pong(_)) => keepalive.on_pong(),
                             _ => {}
                         }
                     }
                 }
                 Err(e) => {
-                    error!("❌ Failed to connect to Gemini v1 API: {}", e);
-                    warn!("🔄 Retrying v1 connection in 5 seconds...");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    let category = connect_category(&e);
+                    let total = errors::record(category);
+                    error!(%category, errors_total = total, "❌ Failed to connect to Gemini v1 API: {}", e);
+                    reconnect_v1.on_failure(alerts_v1.as_ref()).await;
                 }
             }
         }
     });
 
-    let _ = tokio::join!(ob_task, top_task);
+    // On a persistent DATA_DIR, periodically msync both files so a crash or reboot keeps
+    // a recent book; tmpfs (/dev/shm) doesn't survive reboots, so flushing there is moot
+    let flush_every = config::var("MMAP_FLUSH_MS").ok().and_then(|s| s.parse::<u64>().ok()).filter(|&ms| ms > 0);
+    let flush = async {
+        let Some(ms) = flush_every else { return std::future::pending().await };
+        info!("💾 Flushing mmap files every {}ms", ms);
+        let mut tick = tokio::time::interval(std::time::Duration::from_millis(ms));
+        loop {
+            tick.tick().await;
+            for m in ob_mmap.iter().chain(std::iter::once(&tob_mmap)) {
+                if let Err(e) = m.flush() {
+                    let total = errors::record(ErrorCategory::Persist);
+                    warn!(category = %ErrorCategory::Persist, errors_total = total, "⚠️  mmap flush failed: {}", e);
+                }
+            }
+        }
+    };
+    // Liveness counter in both files, bumped every `HEARTBEAT_MS` (default 1000, 0 = off)
+    // even with no market data. The files are mapped a second time, like any other
+    // process would, so this never aliases the feed tasks' references.
+    let heartbeat_every = config::var("HEARTBEAT_MS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(1000);
+    let heartbeat = async {
+        if heartbeat_every == 0 { return std::future::pending().await }
+        let mut ob = map_order_book(&ob_path, l1_only)?;
+        let (_tob_map, tob) = TopOfBook::mmap(std::path::Path::new(&tob_path))?;
+        let mut tick = tokio::time::interval(std::time::Duration::from_millis(heartbeat_every));
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tick.tick().await;
+            if let Some((_, ob)) = &mut ob { ob.bump_heartbeat(); }
+            tob.bump_heartbeat();
+        }
+    };
+    // Last `SNAPSHOT_RING_SIZE` (default 60) books kept in the ring file at
+    // `SNAPSHOT_RING_PATH`, one every `SNAPSHOT_RING_MS` (default 1000) when it changed
+    let ring_path = if l1_only { None } else { config::var("SNAPSHOT_RING_PATH").ok() };
+    let snapshot_ring = async {
+        let Some(ring_path) = ring_path else { return std::future::pending().await };
+        let size = config::var("SNAPSHOT_RING_SIZE").ok().and_then(|s| s.parse::<usize>().ok()).unwrap_or(60);
+        let every = config::var("SNAPSHOT_RING_MS").ok().and_then(|s| s.parse::<u64>().ok()).filter(|ms| *ms > 0).unwrap_or(1000);
+        let mut ring = SnapshotRing::open(std::path::Path::new(&ring_path), size)?;
+        info!("💾 Keeping the last {} {} book snapshots in {} ({} written so far)", size, SYMBOL, ring_path, ring.written());
+        let (_ob_map, ob) = OrderBook::mmap(std::path::Path::new(&ob_path))?;
+        let mut tick = tokio::time::interval(std::time::Duration::from_millis(every));
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_seq = None;
+        loop {
+            tick.tick().await;
+            let book = ob.snapshot();
+            if last_seq == Some(book.seq) { continue; }
+            last_seq = Some(book.seq);
+            if let Err(e) = ring.push(&book) {
+                let total = errors::record(ErrorCategory::Persist);
+                warn!(category = %ErrorCategory::Persist, errors_total = total, "⚠️  Snapshot ring write to {} failed: {}", ring_path, e);
+            }
+        }
+    };
+    tokio::select! {
+        _ = async { tokio::join!(ob_task, top_task) } => {}
+        _ = flush => {}
+        r = heartbeat => return r,
+        r = snapshot_ring => return r,
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v2_derived_top_never_reaches_the_v1_task() {
+        assert_eq!(TobSource::parse(None, false).unwrap(), TobSource::V1);
+        assert_eq!(TobSource::parse(Some("v1"), true).unwrap(), TobSource::V1);
+        assert!(TobSource::parse(Some("v2-derived"), true).is_err(), "l1-only has no v2 book");
+        assert!(TobSource::parse(Some("v3"), false).is_err());
+
+        let source = TobSource::parse(Some("v2-derived"), false).unwrap();
+        let (v1, derived) = source.route(TopOfBook::default());
+        assert!(v1.is_none(), "the v1 task returns before connecting without a top of book");
+        assert!(derived.is_some());
+        assert!(matches!(TobSource::V1.route(()), (Some(()), None)));
+    }
+
+    #[test]
+    fn derived_top_follows_a_level_zero_delete() {
+        let level = |price, qty| OrderLevel { price, qty };
+        let mut book = OrderBook::default();
+        let mut top = TopOfBook::default();
+        book.publish(&[level(100, 5), level(99, 7)], &[level(101, 2), level(102, 4)], 10);
+        derive_top(&book, &mut top);
+        let quote = top.snapshot();
+        assert_eq!((quote.bid_price, quote.bid_qty, quote.ask_price, quote.ask_qty, quote.timestamp_ms), (100, 5, 101, 2, 10));
+
+        // Incremental deletes empty level 0 in place and leave the deeper levels
+        book.publish(&[level(0, 0), level(99, 7)], &[level(0, 0), level(102, 4)], 20);
+        derive_top(&book, &mut top);
+        let quote = top.snapshot();
+        assert_eq!((quote.bid_price, quote.bid_qty, quote.ask_price, quote.ask_qty, quote.timestamp_ms), (99, 7, 102, 4, 20));
+
+        // With a side gone entirely, that side is quoted as zero
+        book.publish(&[level(0, 0), level(99, 7)], &[], 30);
+        derive_top(&book, &mut top);
+        let quote = top.snapshot();
+        assert_eq!((quote.bid_price, quote.ask_price, quote.ask_qty), (99, 0, 0));
+    }
+
+    #[test]
+    fn trades_carry_the_quote_prevailing_at_arrival() {
+        let scales = Scales { price: 100, qty: 100, price_tick: 1 };
+        let mut top = TopOfBook::default();
+        top.set_bid(14_585, 250);
+        top.set_ask(14_590, 180);
+        let trade = |(bid_at, ask_at)| TradeEvent::builder("SOLUSD").ts_ms(1).price_u(14_590).qty_u(50).quote(bid_at, ask_at).build().unwrap();
+        let payload = |tr: &TradeEvent| serde_json::from_slice::<serde_json::Value>(&trade_payload(tr, scales)).unwrap();
+
+        let tr = trade(prevailing_quote(&top));
+        assert_eq!((tr.bid_at, tr.ask_at), (Some(14_585), Some(14_590)));
+        let sent = payload(&tr);
+        assert_eq!((sent["bid_at"].as_u64(), sent["ask_at"].as_u64()), (Some(145_850_000), Some(145_900_000)), "micro units on the bus");
+
+        top.set_bid(0, 0);
+        let tr = trade(prevailing_quote(&top));
+        assert_eq!((tr.bid_at, tr.ask_at), (None, Some(14_590)), "unquoted side");
+        assert!(payload(&tr)["bid_at"].is_null());
+    }
+
+    #[test]
+    fn explicit_maker_side_wins_over_inference() {
+        let prices = PriceConvention { scale: 100, invert: false };
+        let quote = (Some(14_580), Some(14_590));
+        // A print at the bid with the maker on the bid: the taker sold, as reported
+        assert_eq!(taker_side(Some(Side::Buy), &prices, 14_580, quote.0, quote.1), (Some(Side::Sell), false));
+        // The feed's side is trusted even where the mid would say otherwise
+        assert_eq!(taker_side(Some(Side::Sell), &prices, 14_580, quote.0, quote.1), (Some(Side::Buy), false));
+        assert_eq!(taker_side(Some(Side::Sell), &prices, 14_580, None, None), (Some(Side::Buy), false));
+
+        // No side from the feed: inferred from where the print sits against the mid
+        assert_eq!(taker_side(None, &prices, 14_590, quote.0, quote.1), (Some(Side::Buy), true));
+        assert_eq!(taker_side(None, &prices, 14_581, quote.0, quote.1), (Some(Side::Sell), true));
+        assert_eq!(taker_side(None, &prices, 14_585, quote.0, quote.1), (None, false), "at mid");
+        assert_eq!(taker_side(None, &prices, 14_590, None, quote.1), (None, false), "one-sided quote");
+    }
+
+    #[test]
+    fn l1_only_never_touches_the_order_book_file() {
+        let dir = std::env::temp_dir().join(format!("ingest-test-{}-l1-only", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("order_book.bin");
+        let path_str = path.to_str().unwrap();
+
+        assert!(map_order_book(path_str, true).unwrap().is_none());
+        assert!(!path.exists(), "not created");
+
+        // A file left by an earlier depth-enabled run is neither resized nor written
+        std::fs::write(&path, b"stale").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert!(map_order_book(path_str, true).unwrap().is_none());
+        assert_eq!(std::fs::read(&path).unwrap(), b"stale");
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(map_order_book(path_str, false).unwrap().is_some());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), std::mem::size_of::<OrderBook>() as u64, "depth symbols map it");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    #[test]
+    fn crypto_provider_follows_the_env_and_features() {
+        assert_eq!(crypto_provider(None).unwrap().0, "ring", "default");
+        assert_eq!(crypto_provider(Some("ring")).unwrap().0, "ring");
+
+        #[cfg(feature = "aws-lc-rs")]
+        assert_eq!(crypto_provider(Some("aws-lc-rs")).unwrap().0, "aws-lc-rs");
+        #[cfg(not(feature = "aws-lc-rs"))]
+        assert!(crypto_provider(Some("aws-lc-rs")).unwrap_err().to_string().contains("without the aws-lc-rs feature"));
+
+        let err = crypto_provider(Some("openssl")).unwrap_err().to_string();
+        assert!(err.contains("unknown TLS_CRYPTO_PROVIDER 'openssl'"), "{}", err);
+    }
+
+
+    #[test]
+    fn raw_frame_is_carried_verbatim() {
+        let scales = Scales { price: 100, qty: 100, price_tick: 1 };
+        // Key order, spacing, escapes and number formatting must all survive untouched
+        let frame = "{\"type\":\"update\",  \"events\":[{\"type\":\"trade\",\"price\":\"145.90\",\"amount\":\"0.50\",\"makerSide\":\"ask\"}],\n\"note\":\"caf\\u00e9 \\\"q\\\"\",\"timestampms\":1.7e12}";
+        let trade = |raw: Option<String>| TradeEvent::builder("SOLUSD").ts_ms(1).price_u(14_590).qty_u(50).raw(raw).build().unwrap();
+
+        let sent: serde_json::Value = serde_json::from_slice(&trade_payload(&trade(Some(frame.to_string())), scales)).unwrap();
+        assert_eq!(sent["raw_json"].as_str(), Some(frame));
+
+        let sent: serde_json::Value = serde_json::from_slice(&trade_payload(&trade(None), scales)).unwrap();
+        assert!(sent["raw_json"].is_null(), "not captured unless enabled");
+    }
+
+
+    #[test]
+    fn trade_stdout_writes_one_json_line_per_trade() {
+        let scales = Scales { price: 100, qty: 100, price_tick: 1 };
+        let trade = |ts, raw: Option<&str>| TradeEvent::builder("SOLUSD").ts_ms(ts).price_u(14_590).qty_u(50).raw(raw.map(String::from)).build().unwrap();
+        let mut out = Vec::new();
+        write_trade_line(&mut out, &trade(1, None), scales).unwrap();
+        write_trade_line(&mut out, &trade(2, Some("{\n\"multi\": \"line\"\n}")), scales).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with('\n'));
+        let lines: Vec<serde_json::Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2, "a newline in the raw frame is escaped, not emitted");
+        assert_eq!((lines[0]["ts_ms"].as_i64(), lines[1]["ts_ms"].as_i64()), (Some(1), Some(2)));
+        assert_eq!(lines[0]["price_u"], 145_900_000);
+    }
+
+    #[test]
+    fn disabled_trade_stdout_warns_at_startup() {
+        #[derive(Clone, Default)]
+        struct Logs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().extend_from_slice(buf); Ok(buf.len()) }
+            fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+        }
+        let announced = |trade_stdout| {
+            let logs = Logs::default();
+            let writer = logs.clone();
+            let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer(move || writer.clone()).finish();
+            tracing::subscriber::with_default(subscriber, || announce_trade_stdout(trade_stdout));
+            let bytes = logs.0.lock().unwrap().clone();
+            String::from_utf8(bytes).unwrap()
+        };
+
+        let off = announced(false);
+        assert!(off.contains("WARN") && off.contains("trades will NOT be published"), "{}", off);
+        let on = announced(true);
+        assert!(on.contains("INFO") && on.contains("writing trades to stdout"), "{}", on);
+        assert!(!on.contains("WARN"));
+    }
+
+
+    #[cfg(feature = "kafka")]
+    #[tokio::test]
+    async fn missing_topic_is_created_once() {
+        use rdkafka::types::RDKafkaErrorCode;
+
+        struct MockAdmin { exists: bool, create: Result<(), RDKafkaErrorCode>, created: std::sync::Mutex<Vec<(String, i32, i32)>> }
+        impl TopicAdmin for MockAdmin {
+            fn has_topic(&self, _topic: &str) -> Result<bool> { Ok(self.exists) }
+            async fn create_topic(&self, topic: &str, partitions: i32, replication: i32) -> Result<Result<(), RDKafkaErrorCode>> {
+                self.created.lock().unwrap().push((topic.to_string(), partitions, replication));
+                Ok(self.create)
+            }
+        }
+        let admin = |exists, create| MockAdmin { exists, create, created: Default::default() };
+
+        let missing = admin(false, Ok(()));
+        assert!(create_topic_if_missing(&missing, "gemini.trades", 6, 3).await.unwrap());
+        assert_eq!(*missing.created.lock().unwrap(), vec![("gemini.trades".to_string(), 6, 3)]);
+
+        let existing = admin(true, Ok(()));
+        assert!(!create_topic_if_missing(&existing, "gemini.trades", 6, 3).await.unwrap());
+        assert!(existing.created.lock().unwrap().is_empty(), "left untouched");
+
+        let raced = admin(false, Err(RDKafkaErrorCode::TopicAlreadyExists));
+        assert!(!create_topic_if_missing(&raced, "gemini.trades", 6, 3).await.unwrap(), "created by someone else meanwhile");
+
+        let refused = admin(false, Err(RDKafkaErrorCode::InvalidReplicationFactor));
+        let err = create_topic_if_missing(&refused, "gemini.trades", 6, 3).await.unwrap_err().to_string();
+        assert!(err.starts_with("failed to create Kafka topic gemini.trades"), "{}", err);
+    }
+}
@@ -0,0 +1,176 @@
+use serde_json::Value;
+use shared::Side;
+
+/// Parses a Gemini decimal field into fixed-point units at `scale`. Gemini sends
+/// prices and quantities as JSON strings on most feeds but as plain numbers on some
+/// v2 variants, so both shapes are accepted.
+pub fn parse_scaled(v: &Value, scale: u64) -> Option<u64> {
+    match v {
+        Value::String(s) => decimal_to_scaled(s.trim(), scale),
+        Value::Number(n) => decimal_to_scaled(&n.to_string(), scale),
+        _ => None,
+    }
+}
+
+/// Converts a plain or scientific-notation decimal (`"145.85"`, `"1E-6"`, `"2.5e1"`)
+/// to `scale` units using integer arithmetic only, rounding half up past the scale's
+/// precision. Negative, non-numeric or out-of-range (> u64) values yield `None`.
+fn decimal_to_scaled(s: &str, scale: u64) -> Option<u64> {
+    let (mantissa, exp) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i32>().ok()?),
+        None => (s, 0),
+    };
+    let mantissa = mantissa.strip_prefix('+').unwrap_or(mantissa);
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int.is_empty() && frac.is_empty() { return None; }
+    if !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) { return None; }
+
+    let mut digits: u128 = 0;
+    for b in int.bytes().chain(frac.bytes()) {
+        digits = digits.checked_mul(10)?.checked_add((b - b'0') as u128)?;
+    }
+    let numerator = digits.checked_mul(scale as u128)?;
+    let shift = exp as i64 - frac.len() as i64;
+    let scaled = if numerator == 0 {
+        0
+    } else if shift >= 0 {
+        numerator.checked_mul(10u128.checked_pow(u32::try_from(shift).ok()?)?)?
+    } else {
+        match 10u128.checked_pow(u32::try_from(-shift).ok()?) {
+            Some(pow) => numerator / pow + u128::from(numerator % pow >= pow / 2),
+            // 10^39 exceeds any u128 numerator, so the value rounds to zero.
+            None => 0,
+        }
+    };
+    u64::try_from(scaled).ok()
+}
+
+/// Reciprocal of a scaled price at the same scale (`scale² / price_u`, rounded), so a
+/// symbol quoted the other way round (USD/SOL vs SOL/USD) shares one convention.
+/// Zero stays zero (an empty level), and results beyond `u64` saturate.
+pub fn invert_price(price_u: u64, scale: u64) -> u64 {
+    if price_u == 0 { return 0; }
+    let scale = scale as u128;
+    let price_u = price_u as u128;
+    u64::try_from((scale * scale + price_u / 2) / price_u).unwrap_or(u64::MAX)
+}
+
+/// A `TRADE_MIN_NOTIONAL` amount: a finite, non-negative number of quote units.
+pub fn parse_min_notional(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0)
+}
+
+/// How one symbol's venue prices are normalized.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceConvention {
+    pub scale: u64,
+    /// Quote the reciprocal price. Inverting also swaps bid/ask and buy/sell, so the
+    /// best bid becomes the best ask; quantities stay in the venue's base units.
+    pub invert: bool,
+}
+
+impl PriceConvention {
+    /// Parses a price field like `parse_scaled`, inverted when configured.
+    pub fn price(&self, v: &Value) -> Option<u64> {
+        parse_scaled(v, self.scale).map(|p| self.normalize(p))
+    }
+
+    /// A venue price already at `scale`, inverted when configured.
+    pub fn normalize(&self, venue_price_u: u64) -> u64 {
+        if self.invert { invert_price(venue_price_u, self.scale) } else { venue_price_u }
+    }
+
+    /// Price x quantity in the venue's quote units. Takes the venue price, before any
+    /// inversion, so a threshold means the same amount whichever way the symbol is quoted.
+    pub fn venue_notional(&self, venue_price_u: u64, qty_u: u64, qty_scale: u64) -> f64 {
+        venue_price_u as f64 / self.scale as f64 * qty_u as f64 / qty_scale as f64
+    }
+
+    /// The side a venue side maps to after inversion.
+    pub fn side(&self, side: Side) -> Side {
+        if self.invert { side.opposite() } else { side }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MICRO: u64 = 1_000_000;
+
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(parse_scaled(&json!("1e-6"), MICRO), Some(1));
+        assert_eq!(parse_scaled(&json!("2.5E1"), MICRO), Some(25_000_000));
+        assert_eq!(parse_scaled(&json!("1.23e-3"), MICRO), Some(1_230));
+        assert_eq!(parse_scaled(&json!("1E+2"), MICRO), Some(100_000_000));
+        assert_eq!(parse_scaled(&json!(1e-6), MICRO), Some(1), "JSON number");
+    }
+
+    #[test]
+    fn plain_decimals_and_rounding() {
+        assert_eq!(parse_scaled(&json!("145.85"), MICRO), Some(145_850_000));
+        assert_eq!(parse_scaled(&json!(" 0.5 "), MICRO), Some(500_000));
+        assert_eq!(parse_scaled(&json!(".5"), MICRO), Some(500_000));
+        assert_eq!(parse_scaled(&json!(12), MICRO), Some(12_000_000));
+        assert_eq!(parse_scaled(&json!("0.0000005"), MICRO), Some(1), "half rounds up");
+        assert_eq!(parse_scaled(&json!("0.00000049"), MICRO), Some(0));
+        assert_eq!(parse_scaled(&json!("1e-60"), MICRO), Some(0));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for v in [json!("-1"), json!("abc"), json!(""), json!("."), json!("1e"), json!("1.2.3"), json!(null), json!(true)] {
+            assert_eq!(parse_scaled(&v, MICRO), None, "{}", v);
+        }
+        assert_eq!(parse_scaled(&json!("1e30"), MICRO), None, "beyond u64");
+    }
+
+    #[test]
+    fn inverted_convention() {
+        let inverted = PriceConvention { scale: MICRO, invert: true };
+        assert_eq!(inverted.price(&json!("200")), Some(5_000));
+        assert_eq!(inverted.side(Side::Buy), Side::Sell);
+        assert_eq!(invert_price(0, MICRO), 0);
+    }
+
+    #[test]
+    fn notional_uses_the_venue_price() {
+        let venue = parse_scaled(&json!("200"), MICRO).unwrap();
+        for invert in [false, true] {
+            let prices = PriceConvention { scale: MICRO, invert };
+            assert_eq!(prices.venue_notional(venue, 2 * MICRO, MICRO), 400.0, "invert={}", invert);
+        }
+        let inverted = PriceConvention { scale: MICRO, invert: true };
+        assert_eq!(inverted.normalize(venue), 5_000);
+    }
+
+    #[test]
+    fn min_notional_threshold() {
+        assert_eq!(parse_min_notional(" 1.5 "), Some(1.5));
+        assert_eq!(parse_min_notional("0"), Some(0.0));
+        for bad in ["", "abc", "1,5", "-1", "NaN", "inf"] {
+            assert_eq!(parse_min_notional(bad), None, "{}", bad);
+        }
+        // 200 x 0.005 = 1 quote unit; a trade exactly at the threshold is kept
+        let min = parse_min_notional("1").unwrap();
+        let prices = PriceConvention { scale: MICRO, invert: true };
+        let notional = |qty_u| prices.venue_notional(200 * MICRO, qty_u, MICRO);
+        assert!(notional(4_999) < min);
+        assert!(notional(5_000) >= min);
+        assert!(notional(5_001) >= min);
+    }
+
+    #[test]
+    fn string_and_number_levels_parse_identically() {
+        let strings: Value = serde_json::from_str(r#"["145.85","2.5"]"#).unwrap();
+        let numbers: Value = serde_json::from_str("[145.85,2.5]").unwrap();
+        for scale in [100, MICRO, 100_000_000] {
+            let level = |v: &Value| (parse_scaled(&v[0], scale), parse_scaled(&v[1], scale));
+            assert_eq!(level(&strings), level(&numbers), "scale {}", scale);
+        }
+        assert_eq!(parse_scaled(&numbers[0], MICRO), Some(145_850_000));
+        assert_eq!(parse_scaled(&numbers[1], MICRO), Some(2_500_000));
+    }
+}
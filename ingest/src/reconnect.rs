@@ -0,0 +1,128 @@
+use crate::alert::{Alert, AlertSink};
+use shared::config;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Time given to network alert sinks to deliver before exiting.
+const EXIT_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnExhausted {
+    Exit,
+    Degraded,
+}
+
+/// Counts consecutive failed connects for one feed. Once `WS_MAX_RECONNECT_ATTEMPTS`
+/// is reached a critical alert fires and the feed either exits the process or keeps
+/// retrying at the slower degraded cadence. A successful connect resets the count.
+pub struct Reconnect {
+    symbol: &'static str,
+    feed: &'static str,
+    max_attempts: Option<u32>,
+    on_exhausted: OnExhausted,
+    degraded_delay: Duration,
+    failures: u32,
+}
+
+impl Reconnect {
+    /// `WS_MAX_RECONNECT_ATTEMPTS` (unset = retry forever), `WS_RECONNECT_EXHAUSTED`
+    /// (`degraded` default, or `exit`) and `WS_DEGRADED_RETRY_SECS` (default 60).
+    pub fn from_env(symbol: &'static str, feed: &'static str) -> anyhow::Result<Self> {
+        let max_attempts = config::var("WS_MAX_RECONNECT_ATTEMPTS").ok().and_then(|s| s.parse::<u32>().ok()).map(|n| n.max(1));
+        let on_exhausted = match config::var("WS_RECONNECT_EXHAUSTED").unwrap_or_else(|_| "degraded".to_string()).as_str() {
+            "degraded" => OnExhausted::Degraded,
+            "exit" => OnExhausted::Exit,
+            other => anyhow::bail!("unknown WS_RECONNECT_EXHAUSTED '{}' (expected degraded or exit)", other),
+        };
+        let degraded_secs = config::var("WS_DEGRADED_RETRY_SECS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(60);
+        Ok(Self { symbol, feed, max_attempts, on_exhausted, degraded_delay: Duration::from_secs(degraded_secs), failures: 0 })
+    }
+
+    pub fn on_connected(&mut self) {
+        if self.max_attempts.is_some_and(|max| self.failures >= max) {
+            info!("✅ {} recovered after {} failed connects", self.feed, self.failures);
+        }
+        self.failures = 0;
+    }
+
+    /// Records a failed connect and waits before the next attempt. Exits the process
+    /// in `exit` mode once the limit is hit.
+    pub async fn on_failure(&mut self, alerts: &dyn AlertSink) {
+        self.failures += 1;
+        let delay = match self.max_attempts {
+            Some(max) if self.failures >= max => {
+                if self.failures == max {
+                    error!("🚨 {} failed to connect {} times in a row", self.feed, self.failures);
+                    alerts.send(Alert::now(self.symbol, &format!("{}_reconnect_exhausted", self.feed), self.failures as f64));
+                    if self.on_exhausted == OnExhausted::Exit {
+                        tokio::time::sleep(EXIT_GRACE).await;
+                        std::process::exit(1);
+                    }
+                }
+                self.degraded_delay
+            }
+            _ => RETRY_DELAY,
+        };
+        warn!("🔄 Retrying {} connection in {:?}...", self.feed, delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<Alert>>);
+
+    impl AlertSink for Recorded {
+        fn send(&self, alert: Alert) { self.0.lock().unwrap().push(alert); }
+    }
+
+    /// Runs one failed connect and returns how long it waited before the next attempt.
+    async fn fail(reconnect: &mut Reconnect, alerts: &Recorded) -> Duration {
+        let start = tokio::time::Instant::now();
+        reconnect.on_failure(alerts).await;
+        start.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn consecutive_failures_alert_once_and_degrade() {
+        let mut reconnect = Reconnect {
+            symbol: "SOLUSD", feed: "v1", max_attempts: Some(3), on_exhausted: OnExhausted::Degraded,
+            degraded_delay: Duration::from_secs(60), failures: 0,
+        };
+        let alerts = Recorded::default();
+        assert_eq!(fail(&mut reconnect, &alerts).await, RETRY_DELAY);
+        assert_eq!(fail(&mut reconnect, &alerts).await, RETRY_DELAY);
+        assert!(alerts.0.lock().unwrap().is_empty(), "below the limit");
+
+        assert_eq!(fail(&mut reconnect, &alerts).await, Duration::from_secs(60), "degraded cadence from the limit on");
+        assert_eq!(fail(&mut reconnect, &alerts).await, Duration::from_secs(60));
+        {
+            let sent = alerts.0.lock().unwrap();
+            assert_eq!(sent.len(), 1, "one alert per run of failures");
+            assert_eq!((sent[0].symbol.as_str(), sent[0].kind.as_str(), sent[0].value), ("SOLUSD", "v1_reconnect_exhausted", 3.0));
+        }
+
+        // A successful connect starts the count over
+        reconnect.on_connected();
+        assert_eq!(fail(&mut reconnect, &alerts).await, RETRY_DELAY);
+        assert_eq!(alerts.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn without_a_limit_failures_never_alert() {
+        let mut reconnect = Reconnect {
+            symbol: "SOLUSD", feed: "v2", max_attempts: None, on_exhausted: OnExhausted::Exit,
+            degraded_delay: Duration::from_secs(60), failures: 0,
+        };
+        let alerts = Recorded::default();
+        for _ in 0..20 {
+            assert_eq!(fail(&mut reconnect, &alerts).await, RETRY_DELAY);
+        }
+        assert!(alerts.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::symbol_details::{micro, Scales};
+use shared::errors::{self, ErrorCategory};
+use shared::{config, TopOfBookSnapshot};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+static LATEST: OnceLock<watch::Sender<Option<TopOfBookSnapshot>>> = OnceLock::new();
+
+/// With `REDIS_URL` set (`redis://[[user]:password@]host[:port][/db]`), starts a task
+/// mirroring the top of book to Redis: each change is `SET` on `tob:{symbol}` and
+/// `PUBLISH`ed on the channel of the same name, as JSON with the same fields as the
+/// consumer's quotes sink, in micro units. Only the latest quote is kept, so a slow or unreachable
+/// Redis skips intermediate quotes instead of queueing them or stalling the feed.
+/// Only plain `redis://` is supported; any other scheme (e.g. `rediss://`) is an error.
+pub fn start(symbol: &str, scales: Scales) -> anyhow::Result<()> {
+    let Ok(url) = config::var("REDIS_URL") else { return Ok(()) };
+    let url = RedisUrl::parse(&url)?;
+    let (tx, rx) = watch::channel(None);
+    if LATEST.set(tx).is_err() { return Ok(()); }
+    let key = format!("tob:{}", symbol);
+    info!("🟥 Mirroring {} top of book to Redis key/channel {}", symbol, key);
+    let symbol = symbol.to_string();
+    tokio::spawn(run(url, key, symbol, scales, rx));
+    Ok(())
+}
+
+/// Connection settings from `REDIS_URL`.
+#[derive(Debug, PartialEq, Eq)]
+struct RedisUrl {
+    addr: String,
+    /// `(user, password)`; an empty user authenticates with the password alone.
+    auth: Option<(String, String)>,
+    db: Option<String>,
+}
+
+impl RedisUrl {
+    fn parse(url: &str) -> anyhow::Result<Self> {
+        let Some(rest) = url.strip_prefix("redis://") else {
+            match url.split_once("://") {
+                Some((scheme, _)) => anyhow::bail!("REDIS_URL scheme {}:// is not supported (TLS included); use redis://", scheme),
+                None => anyhow::bail!("REDIS_URL must start with redis://"),
+            }
+        };
+        let (auth, rest) = match rest.rsplit_once('@') {
+            Some((auth, rest)) => {
+                let (user, password) = auth.split_once(':').unwrap_or(("", auth));
+                (Some((user.to_string(), password.to_string())), rest)
+            }
+            None => (None, rest),
+        };
+        let (host, db) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            anyhow::bail!("REDIS_URL has no host");
+        }
+        let addr = if host.contains(':') { host.to_string() } else { format!("{}:6379", host) };
+        Ok(Self { addr, auth, db: (!db.is_empty()).then(|| db.to_string()) })
+    }
+}
+
+/// Hands the latest quote to the Redis task; a no-op when Redis isn't configured.
+pub fn publish(quote: TopOfBookSnapshot) {
+    if let Some(tx) = LATEST.get() {
+        tx.send_replace(Some(quote));
+    }
+}
+
+async fn run(url: RedisUrl, key: String, symbol: String, scales: Scales, mut rx: watch::Receiver<Option<TopOfBookSnapshot>>) {
+    let mut conn = None;
+    while rx.changed().await.is_ok() {
+        let Some(q) = *rx.borrow_and_update() else { continue };
+        let payload = serde_json::json!({
+            "ts_ms": q.timestamp_ms, "symbol": symbol,
+            "bid_u": micro(q.bid_price, scales.price), "bid_qty_u": micro(q.bid_qty, scales.qty),
+            "ask_u": micro(q.ask_price, scales.price), "ask_qty_u": micro(q.ask_qty, scales.qty),
+        }).to_string();
+        let sent = async {
+            if conn.is_none() {
+                conn = Some(connect(&url).await?);
+            }
+            send(conn.as_mut().expect("connected above"), &key, &payload).await
+        }.await;
+        if let Err(e) = sent {
+            conn = None;
+            let total = errors::record(ErrorCategory::Produce);
+            warn!(category = %ErrorCategory::Produce, errors_total = total, "⚠️  Failed to write {} to Redis: {}", key, e);
+            // The next quote retries on a fresh connection
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// Connects and runs `AUTH`/`SELECT` from the URL.
+async fn connect(url: &RedisUrl) -> std::io::Result<BufStream<TcpStream>> {
+    let stream = tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(&url.addr)).await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, format!("connecting to {}", url.addr)))??;
+    stream.set_nodelay(true)?;
+    let mut conn = BufStream::new(stream);
+    if let Some((user, password)) = &url.auth {
+        match user.as_str() {
+            "" => conn.write_all(&command(&["AUTH", password])).await?,
+            user => conn.write_all(&command(&["AUTH", user, password])).await?,
+        }
+        conn.flush().await?;
+        read_reply(&mut conn).await?;
+    }
+    if let Some(db) = &url.db {
+        conn.write_all(&command(&["SELECT", db])).await?;
+        conn.flush().await?;
+        read_reply(&mut conn).await?;
+    }
+    Ok(conn)
+}
+
+/// Sets `key` to `payload` and publishes it on the channel of the same name, pipelined.
+async fn send(conn: &mut BufStream<TcpStream>, key: &str, payload: &str) -> std::io::Result<()> {
+    conn.write_all(&command(&["SET", key, payload])).await?;
+    conn.write_all(&command(&["PUBLISH", key, payload])).await?;
+    conn.flush().await?;
+    read_reply(conn).await?;
+    read_reply(conn).await
+}
+
+/// Encodes a command as a RESP array of bulk strings.
+fn command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// Reads one reply, failing on a Redis error. The commands sent here only get simple
+/// strings, integers or (never in practice) bulk strings back.
+async fn read_reply(conn: &mut BufStream<TcpStream>) -> std::io::Result<()> {
+    let mut line = String::new();
+    if conn.read_line(&mut line).await? == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    let line = line.trim_end();
+    match line.as_bytes().first() {
+        Some(b'+') | Some(b':') => Ok(()),
+        Some(b'$') => {
+            let len: i64 = line[1..].parse().map_err(|_| std::io::Error::other(format!("bad bulk length: {}", line)))?;
+            if len >= 0 {
+                let mut skip = vec![0u8; len as usize + 2];
+                conn.read_exact(&mut skip).await?;
+            }
+            Ok(())
+        }
+        Some(b'-') => Err(std::io::Error::other(line[1..].to_string())),
+        _ => Err(std::io::Error::other(format!("unexpected reply: {}", line))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn parses_urls_and_rejects_other_schemes() {
+        assert_eq!(RedisUrl::parse("redis://cache").unwrap(), RedisUrl { addr: "cache:6379".into(), auth: None, db: None });
+        assert_eq!(RedisUrl::parse("redis://u:p@cache:6380/2").unwrap(), RedisUrl {
+            addr: "cache:6380".into(), auth: Some(("u".into(), "p".into())), db: Some("2".into()),
+        });
+        assert_eq!(RedisUrl::parse("redis://:secret@cache").unwrap().auth, Some((String::new(), "secret".into())));
+        for bad in ["rediss://cache:6380", "unix:///tmp/redis.sock", "cache:6379", "redis://"] {
+            assert!(RedisUrl::parse(bad).is_err(), "{}", bad);
+        }
+        assert!(RedisUrl::parse("rediss://cache").unwrap_err().to_string().contains("rediss://"));
+    }
+
+    /// Accepts one connection, answers each of `replies` in turn after reading a
+    /// command, and returns everything the client sent.
+    async fn mock_redis(replies: &'static [&'static str]) -> (String, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufStream::new(stream);
+            let mut received = Vec::new();
+            for reply in replies {
+                // Each command is an array header plus two lines per argument
+                let mut header = String::new();
+                stream.read_line(&mut header).await.unwrap();
+                let args: usize = header.trim_end()[1..].parse().unwrap();
+                received.extend_from_slice(header.as_bytes());
+                for _ in 0..args * 2 {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    received.extend_from_slice(line.as_bytes());
+                }
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                stream.flush().await.unwrap();
+            }
+            received
+        });
+        (addr, server)
+    }
+
+    #[tokio::test]
+    async fn sends_auth_select_set_and_publish_frames() {
+        let (addr, server) = mock_redis(&["+OK\r\n", "+OK\r\n", "+OK\r\n", ":1\r\n"]).await;
+        let url = RedisUrl::parse(&format!("redis://:pw@{}/3", addr)).unwrap();
+        let mut conn = connect(&url).await.unwrap();
+        send(&mut conn, "tob:SOLUSD", r#"{"bid_u":1}"#).await.unwrap();
+        let received = String::from_utf8(server.await.unwrap()).unwrap();
+        assert_eq!(received, concat!(
+            "*2\r\n$4\r\nAUTH\r\n$2\r\npw\r\n",
+            "*2\r\n$6\r\nSELECT\r\n$1\r\n3\r\n",
+            "*3\r\n$3\r\nSET\r\n$10\r\ntob:SOLUSD\r\n$11\r\n{\"bid_u\":1}\r\n",
+            "*3\r\n$7\r\nPUBLISH\r\n$10\r\ntob:SOLUSD\r\n$11\r\n{\"bid_u\":1}\r\n",
+        ));
+    }
+
+    #[tokio::test]
+    async fn redis_error_reply_fails_the_send() {
+        let (addr, server) = mock_redis(&["-WRONGTYPE Operation against a key holding the wrong kind of value\r\n", ":0\r\n"]).await;
+        let mut conn = connect(&RedisUrl::parse(&format!("redis://{}", addr)).unwrap()).await.unwrap();
+        let err = send(&mut conn, "tob:SOLUSD", "{}").await.unwrap_err();
+        assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+        server.await.unwrap();
+    }
+}
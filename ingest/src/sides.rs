@@ -0,0 +1,273 @@
+use shared::errors::{self, ErrorCategory};
+use shared::{config, format, BookStatus, OrderBook, OrderLevel};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Snapshot sides ready to publish. A missing side keeps the book's current levels.
+pub struct SnapshotSides {
+    pub bids: Option<Vec<OrderLevel>>,
+    pub asks: Option<Vec<OrderLevel>>,
+    pub ts: u64,
+}
+
+impl SnapshotSides {
+    /// Publishes under one seqlock bump, so readers never see half of the update.
+    pub fn publish(self, book: &mut OrderBook, cross_check: &mut CrossCheck) {
+        let bids = self.bids.unwrap_or_else(|| book.bids.iter().map(OrderLevel::load).collect());
+        let asks = self.asks.unwrap_or_else(|| book.asks.iter().map(OrderLevel::load).collect());
+        cross_check.publish(book, &bids, &asks, self.ts);
+    }
+}
+
+/// With `REJECT_CROSSED` set, levels that would leave the book crossed (best bid above
+/// best ask, ignoring empty levels) are not published and the book keeps its previous
+/// state. Locked books still publish. Without it, everything publishes.
+pub struct CrossCheck {
+    reject: bool,
+    rejected: u64,
+}
+
+impl CrossCheck {
+    pub fn from_env() -> Self {
+        let reject = config::var("REJECT_CROSSED").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+        Self { reject, rejected: 0 }
+    }
+
+    /// Publishes the levels unless they're crossed and crossed books are rejected.
+    pub fn publish(&mut self, book: &mut OrderBook, bids: &[OrderLevel], asks: &[OrderLevel], ts: u64) {
+        let best = |side: &[OrderLevel]| side.iter().find(|l| l.price > 0).map(|l| l.price);
+        if let (true, Some(bid), Some(ask)) = (self.reject, best(bids), best(asks)) {
+            if bid > ask {
+                self.rejected += 1;
+                let total = errors::record(ErrorCategory::Crossed);
+                let meta = book.meta();
+                let (price_scale, _) = meta.scales();
+                warn!(category = %ErrorCategory::Crossed, errors_total = total, "❌ Not publishing crossed {} book: best bid {} > best ask {} ({} rejected so far)",
+                    meta.symbol(), format::scaled(bid, price_scale), format::scaled(ask, price_scale), self.rejected);
+                return;
+            }
+        }
+        book.publish(bids, asks, ts);
+    }
+}
+
+/// Pairs up v2 snapshot sides that arrive in separate frames, so a new bid side isn't
+/// published next to a stale ask side under a fresh timestamp. A lone side is held
+/// for up to `SNAPSHOT_SIDE_WAIT_MS` (default 250; 0 publishes each frame as-is)
+/// waiting for the other one, then published alone.
+pub struct SideBuffer {
+    wait: Duration,
+    pending: Option<SnapshotSides>,
+    deadline: Option<Instant>,
+}
+
+impl SideBuffer {
+    pub fn from_env() -> Self {
+        let ms = config::var("SNAPSHOT_SIDE_WAIT_MS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(250);
+        Self::new(Duration::from_millis(ms))
+    }
+
+    pub fn new(wait: Duration) -> Self {
+        Self { wait, pending: None, deadline: None }
+    }
+
+    /// Offers one frame's sides; returns what should be published now, if anything.
+    /// A frame with both sides supersedes anything held. A repeated lone side replaces
+    /// the held one without extending the wait.
+    pub fn offer(&mut self, bids: Option<Vec<OrderLevel>>, asks: Option<Vec<OrderLevel>>, ts: u64) -> Option<SnapshotSides> {
+        if self.wait.is_zero() || (bids.is_some() && asks.is_some()) {
+            self.take();
+            return Some(SnapshotSides { bids, asks, ts });
+        }
+        let held = self.pending.get_or_insert(SnapshotSides { bids: None, asks: None, ts });
+        held.ts = held.ts.max(ts);
+        if bids.is_some() { held.bids = bids; }
+        if asks.is_some() { held.asks = asks; }
+        if held.bids.is_some() && held.asks.is_some() {
+            return self.take();
+        }
+        self.deadline.get_or_insert_with(|| Instant::now() + self.wait);
+        None
+    }
+
+    /// Whether a lone side is held, i.e. the last snapshot frame isn't published yet.
+    pub fn has_pending(&self) -> bool { self.pending.is_some() }
+
+    /// When the held side must be published alone.
+    pub fn deadline(&self) -> Option<Instant> { self.deadline }
+
+    /// Releases whatever is held, e.g. on timeout or before applying incremental changes.
+    pub fn take(&mut self) -> Option<SnapshotSides> {
+        self.deadline = None;
+        self.pending.take()
+    }
+}
+
+/// Minimum time between snapshots that replace the book (`RESYNC_MIN_DWELL_MS`,
+/// default 0 = no limit). A flapping feed sends a fresh snapshot on every reconnect;
+/// one arriving within the dwell is held back, replacing any snapshot already held,
+/// and applied once the dwell ends. Meanwhile the book keeps following deltas from the
+/// last accepted snapshot, and the held one follows them too so it isn't stale when
+/// applied. An empty book has nothing to apply deltas to, so a snapshot for it is
+/// always accepted. Outlives connections, so it spans reconnects.
+pub struct ResyncDwell {
+    min: Duration,
+    last: Option<Instant>,
+    held: Option<SnapshotSides>,
+    throttled: u64,
+}
+
+impl ResyncDwell {
+    pub fn from_env() -> Self {
+        let ms = config::var("RESYNC_MIN_DWELL_MS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(0);
+        Self::new(Duration::from_millis(ms))
+    }
+
+    pub fn new(min: Duration) -> Self {
+        Self { min, last: None, held: None, throttled: 0 }
+    }
+
+    /// Whether a snapshot arriving at `now` may replace `book`; an accepted one starts a new dwell.
+    pub fn admit(&mut self, book: &OrderBook, now: Instant) -> bool {
+        if self.min.is_zero() || book.status() == BookStatus::Empty || self.last.is_none_or(|t| now.duration_since(t) >= self.min) {
+            self.last = Some(now);
+            return true;
+        }
+        false
+    }
+
+    /// Publishes `sides` if `admit` allows it, otherwise holds them until the dwell ends.
+    pub fn publish(&mut self, sides: SnapshotSides, book: &mut OrderBook, cross_check: &mut CrossCheck) {
+        if self.admit(book, Instant::now()) {
+            self.held = None;
+            sides.publish(book, cross_check);
+            return;
+        }
+        self.held = Some(sides);
+        self.throttled += 1;
+        if self.throttled.is_power_of_two() {
+            info!("🧊 {} snapshot held within {:?} of the last resync, applying deltas until the dwell ends ({} throttled so far)", book.meta().symbol(), self.min, self.throttled);
+        }
+    }
+
+    /// When the held snapshot is due, if one is held.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.held.as_ref().and(self.last).map(|t| t + self.min)
+    }
+
+    /// The held snapshot, so deltas can be applied to it as well.
+    pub fn held_mut(&mut self) -> Option<&mut SnapshotSides> { self.held.as_mut() }
+
+    /// Releases the held snapshot once the dwell has ended at `now`, starting a new dwell.
+    pub fn take_due(&mut self, now: Instant) -> Option<SnapshotSides> {
+        if self.deadline().is_none_or(|due| now < due) {
+            return None;
+        }
+        self.last = Some(now);
+        self.held.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: u64) -> Vec<OrderLevel> {
+        vec![OrderLevel { price, qty: 1 }]
+    }
+
+    fn sides(bid: u64, ask: u64) -> SnapshotSides {
+        SnapshotSides { bids: Some(level(bid)), asks: Some(level(ask)), ts: 1 }
+    }
+
+    fn live_book() -> OrderBook {
+        let mut book = OrderBook::default();
+        book.publish(&level(100), &level(101), 1);
+        book
+    }
+
+    fn prices(side: &Option<Vec<OrderLevel>>) -> Option<u64> {
+        side.as_ref().map(|levels| levels[0].price)
+    }
+
+    #[test]
+    fn side_buffer_pairs_split_frames() {
+        let mut buf = SideBuffer::new(Duration::from_millis(250));
+        assert!(buf.offer(Some(level(100)), None, 5).is_none(), "a lone bid side waits");
+        assert!(buf.has_pending() && buf.deadline().is_some());
+        let paired = buf.offer(None, Some(level(101)), 7).expect("the ask side completes it");
+        assert_eq!((prices(&paired.bids), prices(&paired.asks), paired.ts), (Some(100), Some(101), 7));
+        assert!(!buf.has_pending() && buf.deadline().is_none());
+    }
+
+    #[test]
+    fn side_buffer_replaces_a_repeated_side_and_releases_on_take() {
+        let mut buf = SideBuffer::new(Duration::from_millis(250));
+        assert!(buf.offer(Some(level(100)), None, 5).is_none());
+        let deadline = buf.deadline();
+        assert!(buf.offer(Some(level(102)), None, 6).is_none());
+        assert_eq!(buf.deadline(), deadline, "a repeat doesn't extend the wait");
+        let alone = buf.take().expect("held side");
+        assert_eq!((prices(&alone.bids), prices(&alone.asks), alone.ts), (Some(102), None, 6));
+        assert!(buf.take().is_none());
+    }
+
+    #[test]
+    fn side_buffer_passes_through_full_frames_and_zero_wait() {
+        let mut buf = SideBuffer::new(Duration::from_millis(250));
+        assert!(buf.offer(None, Some(level(101)), 5).is_none());
+        let full = buf.offer(Some(level(100)), Some(level(103)), 6).expect("both sides publish at once");
+        assert_eq!(prices(&full.asks), Some(103), "supersedes the held side");
+        assert!(!buf.has_pending());
+
+        let mut unbuffered = SideBuffer::new(Duration::ZERO);
+        let lone = unbuffered.offer(Some(level(100)), None, 5).expect("published as-is");
+        assert_eq!((prices(&lone.bids), prices(&lone.asks)), (Some(100), None));
+    }
+
+    #[test]
+    fn dwell_admits_empty_book_and_spaced_snapshots() {
+        let (book, t0) = (live_book(), Instant::now());
+        let mut dwell = ResyncDwell::new(Duration::from_millis(100));
+        assert!(dwell.admit(&OrderBook::default(), t0));
+        assert!(dwell.admit(&OrderBook::default(), t0 + Duration::from_millis(1)));
+        assert!(!dwell.admit(&book, t0 + Duration::from_millis(50)));
+        assert!(dwell.admit(&book, t0 + Duration::from_millis(101)));
+        assert!(ResyncDwell::new(Duration::ZERO).admit(&book, t0));
+    }
+
+    #[test]
+    fn dwell_holds_latest_throttled_snapshot_until_due() {
+        let (mut book, mut check) = (live_book(), CrossCheck { reject: false, rejected: 0 });
+        let mut dwell = ResyncDwell::new(Duration::from_secs(3600));
+        dwell.publish(sides(200, 201), &mut book, &mut check);
+        assert_eq!(book.bids[0].load_price(), 200);
+        assert_eq!(dwell.deadline(), None);
+
+        dwell.publish(sides(300, 301), &mut book, &mut check);
+        dwell.publish(sides(400, 401), &mut book, &mut check);
+        assert_eq!(book.bids[0].load_price(), 200, "throttled snapshots must not replace the book");
+        let due = dwell.deadline().expect("a snapshot is held");
+
+        assert!(dwell.take_due(due - Duration::from_millis(1)).is_none());
+        let held = dwell.take_due(due).expect("due at the deadline");
+        assert_eq!(held.bids.map(|b| b[0].price), Some(400), "the latest throttled snapshot wins");
+        assert_eq!(dwell.deadline(), None);
+        assert!(dwell.take_due(due + Duration::from_secs(7200)).is_none());
+    }
+
+    #[test]
+    fn dwell_drops_held_snapshot_when_a_newer_one_is_admitted() {
+        let (mut book, mut check) = (OrderBook::default(), CrossCheck { reject: false, rejected: 0 });
+        let mut dwell = ResyncDwell::new(Duration::from_secs(3600));
+        dwell.publish(sides(200, 201), &mut book, &mut check);
+        dwell.publish(sides(300, 301), &mut book, &mut check);
+        assert!(dwell.held_mut().is_some());
+        // Emptied by a halt, so the next snapshot is admitted despite the dwell
+        book.publish(&[], &[], 2);
+        dwell.publish(sides(500, 501), &mut book, &mut check);
+        assert_eq!(book.bids[0].load_price(), 500);
+        assert!(dwell.held_mut().is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use shared::config;
+use shared::metrics::{self, Feed};
+use std::time::{Duration, Instant};
+
+const BUCKETS: usize = 40;
+
+/// Power-of-two microsecond histogram: bucket `i` counts samples in `[2^(i-1), 2^i)` us.
+#[derive(Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    total: u64,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self { Self { counts: [0; BUCKETS], total: 0, max: Duration::ZERO } }
+}
+
+impl Histogram {
+    pub fn record(&mut self, d: Duration) {
+        let us = d.as_micros().min(u64::MAX as u128) as u64;
+        let idx = ((u64::BITS - us.leading_zeros()) as usize).min(BUCKETS - 1);
+        self.counts[idx] += 1;
+        self.total += 1;
+        self.max = self.max.max(d);
+    }
+
+    pub fn count(&self) -> u64 { self.total }
+    pub fn max(&self) -> Duration { self.max }
+
+    /// Upper bound of the bucket containing quantile `q` (0.0..=1.0).
+    pub fn quantile(&self, q: f64) -> Duration {
+        if self.total == 0 { return Duration::ZERO; }
+        let target = ((self.total as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= target {
+                return Duration::from_micros(1u64 << i).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn reset(&mut self) { *self = Self::default(); }
+}
+
+/// Inter-arrival tracker that rolls its histogram over every `STATS_INTERVAL_SECS`
+/// (default 60) and hands the finished window back for reporting. Each gap also goes to
+/// the cumulative `book_update_gap_seconds` histogram on `/metrics`.
+pub struct InterArrival {
+    last: Option<Instant>,
+    window_start: Instant,
+    window: Duration,
+    hist: Histogram,
+}
+
+impl InterArrival {
+    pub fn from_env() -> Self {
+        let secs = config::var("STATS_INTERVAL_SECS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(60).max(1);
+        Self { last: None, window_start: Instant::now(), window: Duration::from_secs(secs), hist: Histogram::default() }
+    }
+
+    /// Records an arrival at `now`; returns the completed histogram when the window rolls over.
+    pub fn record(&mut self, now: Instant) -> Option<Histogram> {
+        if let Some(prev) = self.last.replace(now) {
+            let gap = now.saturating_duration_since(prev);
+            metrics::observe_book_update_gap(gap);
+            self.hist.record(gap);
+        }
+        if now.saturating_duration_since(self.window_start) < self.window {
+            return None;
+        }
+        self.window_start = now;
+        let done = self.hist.clone();
+        self.hist.reset();
+        Some(done)
+    }
+}
+
+/// Internal processing time from a frame's receipt to its completed mmap publish,
+/// excluding network latency. Rolls over every `STATS_INTERVAL_SECS` like `InterArrival`
+/// and feeds `feed`'s `publish_latency_seconds` histogram on `/metrics`.
+pub struct PublishLatency {
+    feed: Feed,
+    window_start: Instant,
+    window: Duration,
+    hist: Histogram,
+}
+
+impl PublishLatency {
+    pub fn from_env(feed: Feed) -> Self {
+        let secs = config::var("STATS_INTERVAL_SECS").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(60).max(1);
+        Self { feed, window_start: Instant::now(), window: Duration::from_secs(secs), hist: Histogram::default() }
+    }
+
+    /// Records a publish just completed for a frame received at `received`; returns the
+    /// completed histogram when the window rolls over.
+    pub fn record(&mut self, received: Instant) -> Option<Histogram> {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(received);
+        metrics::observe_publish_latency(self.feed, elapsed);
+        self.hist.record(elapsed);
+        if now.saturating_duration_since(self.window_start) < self.window {
+            return None;
+        }
+        self.window_start = now;
+        let done = self.hist.clone();
+        self.hist.reset();
+        Some(done)
+    }
+}
+
+/// Share of book updates in the current stats window that saw a crossed or locked book.
+#[derive(Default)]
+pub struct CrossedRate {
+    updates: u64,
+    crossed: u64,
+}
+
+impl CrossedRate {
+    pub fn observe(&mut self, crossed: bool) {
+        self.updates += 1;
+        self.crossed += crossed as u64;
+    }
+
+    /// Returns the rate for the window so far and starts a new one.
+    pub fn take(&mut self) -> f64 {
+        let rate = if self.updates == 0 { 0.0 } else { self.crossed as f64 / self.updates as f64 };
+        *self = Self::default();
+        rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::metrics::{render, Format, Instance};
+
+    fn sample(series: &str) -> u64 {
+        let instance = Instance { build_version: "test", symbols: "SOLUSD", symbol: None };
+        render(&instance, Format::Text).lines().find_map(|l| l.strip_prefix(series)?.trim().parse().ok()).unwrap_or(0)
+    }
+
+    fn count(feed: &str) -> u64 {
+        sample(&format!("publish_latency_seconds_count{{feed=\"{}\"}}", feed))
+    }
+
+    fn gap_bucket(le: &str) -> u64 {
+        sample(&format!("book_update_gap_seconds_bucket{{le=\"{}\"}}", le))
+    }
+
+    #[test]
+    fn update_gaps_fill_their_buckets_and_roll_over() {
+        let start = Instant::now();
+        let mut gaps = InterArrival { last: None, window_start: start, window: Duration::from_secs(1), hist: Histogram::default() };
+        let before: Vec<u64> = ["0.001024", "0.002048", "0.004096", "1.048576"].iter().map(|le| gap_bucket(le)).collect();
+        // Gaps of 1 ms, 3 ms, 3 ms, then 1 s, which also closes the window
+        for at_ms in [0, 1, 4, 7] {
+            assert!(gaps.record(start + Duration::from_millis(at_ms)).is_none());
+        }
+        let window = gaps.record(start + Duration::from_millis(1_007)).expect("window rolled over");
+        assert_eq!(window.count(), 4);
+        assert_eq!(window.quantile(0.25), Duration::from_micros(1_024), "1 ms lands in [512, 1024) us");
+        assert_eq!(window.quantile(0.75), Duration::from_micros(4_096), "3 ms lands in [2048, 4096) us");
+        assert_eq!(window.quantile(1.0), Duration::from_secs(1), "capped at the max");
+        assert_eq!(window.max(), Duration::from_secs(1));
+
+        // The exported histogram is cumulative, with inclusive upper bounds
+        let after: Vec<u64> = ["0.001024", "0.002048", "0.004096", "1.048576"].iter().map(|le| gap_bucket(le)).collect();
+        let moved: Vec<u64> = after.iter().zip(&before).map(|(a, b)| a - b).collect();
+        assert_eq!(moved, [1, 1, 3, 4]);
+
+        // The next window starts empty
+        let next = gaps.record(start + Duration::from_millis(2_100)).expect("second window");
+        assert_eq!(next.count(), 1);
+    }
+
+    #[test]
+    fn publish_latency_reaches_the_feed_histogram() {
+        let before = count("v2");
+        let mut latency = PublishLatency::from_env(Feed::V2);
+        latency.record(Instant::now());
+        assert_eq!(count("v2"), before + 1);
+    }
+}
@@ -0,0 +1,149 @@
+use shared::config;
+use shared::errors::{self, ErrorCategory};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Fixed-point multipliers used to turn venue decimals into integer units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scales {
+    pub price: u64,
+    pub qty: u64,
+    /// Venue price increment in scaled price units, 0 when unknown.
+    pub price_tick: u64,
+}
+
+impl Default for Scales {
+    fn default() -> Self { Self { price: 1_000_000, qty: 1_000_000, price_tick: 0 } }
+}
+
+impl Scales {
+    /// Derives scales from a `/v1/symbols/details/:symbol` body. Scales follow the
+    /// venue's precision but never go below micro units. They may go above it (e.g. x1e8
+    /// for a 1e-8 quantity increment): the mmap files record their scales in `BookMeta`,
+    /// while payloads leaving ingest are converted back with `micro`.
+    pub fn from_details(v: &serde_json::Value) -> Option<Self> {
+        let field = |k: &str| v.get(k).and_then(|x| x.as_f64().or_else(|| x.as_str().and_then(|s| s.parse().ok())));
+        let quote_increment = field("quote_increment")?;
+        let tick_size = field("tick_size")?;
+        let price = scale_for(quote_increment);
+        Some(Self { price, qty: scale_for(tick_size), price_tick: (quote_increment * price as f64).round() as u64 })
+    }
+}
+
+/// `value` at `scale` in micro units, the unit of every `_u` field ingest publishes
+/// (bus trades, Redis quotes) and the consumer stores. Precision finer than 1e-6 is
+/// rounded to the nearest micro unit.
+pub fn micro(value: u64, scale: u64) -> u64 {
+    if scale == 1_000_000 || scale == 0 { return value; }
+    ((value as u128 * 1_000_000 + scale as u128 / 2) / scale as u128).min(u64::MAX as u128) as u64
+}
+
+fn scale_for(increment: f64) -> u64 {
+    let decimals = (0..=12u32)
+        .find(|&d| {
+            let x = increment * 10f64.powi(d as i32);
+            (x - x.round()).abs() < 1e-9
+        })
+        .unwrap_or(12);
+    10u64.pow(decimals.max(6))
+}
+
+/// Fetches the symbol's tick sizes from Gemini REST (`GEMINI_REST_URL`, default
+/// `https://api.gemini.com`), caching the response under `data_dir`. Falls back to the
+/// cached copy and then to micro-unit defaults if the venue can't be reached.
+pub async fn load_scales(symbol: &str, data_dir: &str) -> Scales {
+    let base = config::var("GEMINI_REST_URL").unwrap_or_else(|_| "https://api.gemini.com".to_string());
+    fetch_scales(&base, symbol, data_dir).await
+}
+
+async fn fetch_scales(base: &str, symbol: &str, data_dir: &str) -> Scales {
+    let url = format!("{}/v1/symbols/details/{}", base.trim_end_matches('/'), symbol.to_lowercase());
+    let cache = Path::new(data_dir).join(format!("{}_details.json", symbol.to_lowercase()));
+
+    let fetched = async {
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?;
+        client.get(&url).send().await?.error_for_status()?.json::<serde_json::Value>().await
+    }.await;
+    let details = match fetched {
+        Ok(v) => {
+            let _ = std::fs::write(&cache, v.to_string());
+            Some(v)
+        }
+        Err(e) => {
+            let total = errors::record(ErrorCategory::Connect);
+            warn!(category = %ErrorCategory::Connect, errors_total = total, "⚠️  Failed to fetch {} symbol details ({}), trying cache", symbol, e);
+            std::fs::read_to_string(&cache).ok().and_then(|s| serde_json::from_str(&s).ok())
+        }
+    };
+    match details.as_ref().and_then(Scales::from_details) {
+        Some(scales) => {
+            info!("📐 {} scales: price x{} qty x{}", symbol, scales.price, scales.qty);
+            scales
+        }
+        None => {
+            warn!("⚠️  No usable {} symbol details, using default micro-unit scales", symbol);
+            Scales::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `body` as JSON to the first request and returns the base URL and the path
+    /// it was asked for.
+    async fn details_endpoint(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let served = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let path = String::from_utf8_lossy(&buf[..n]).split_whitespace().nth(1).unwrap_or("").to_string();
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            path
+        });
+        (base, served)
+    }
+
+    fn data_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("details-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn scales_follow_the_details_endpoint_then_its_cache() {
+        let dir = data_dir("fetch");
+        let (base, served) = details_endpoint(r#"{"symbol":"BTCUSD","tick_size":1e-8,"quote_increment":0.01}"#).await;
+        let scales = fetch_scales(&base, "BTCUSD", dir.to_str().unwrap()).await;
+        assert_eq!(served.await.unwrap(), "/v1/symbols/details/btcusd");
+        assert_eq!(scales, Scales { price: 1_000_000, qty: 100_000_000, price_tick: 10_000 });
+
+        // Venue unreachable: the cached response gives the same scales
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        assert_eq!(fetch_scales(&format!("http://{}", closed), "BTCUSD", dir.to_str().unwrap()).await, scales);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn unusable_details_fall_back_to_micro_units() {
+        let dir = data_dir("fallback");
+        let (base, _) = details_endpoint(r#"{"symbol":"SOLUSD"}"#).await;
+        assert_eq!(fetch_scales(&base, "SOLUSD", dir.to_str().unwrap()).await, Scales::default());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn micro_converts_finer_scales_back() {
+        assert_eq!(micro(123_456_789, 100_000_000), 1_234_568);
+        assert_eq!(micro(1_500_000, 1_000_000), 1_500_000);
+        assert_eq!(micro(5, 100_000_000), 0);
+    }
+}
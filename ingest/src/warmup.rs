@@ -0,0 +1,92 @@
+use crate::parse::{parse_scaled, PriceConvention};
+use crate::sides::SnapshotSides;
+use shared::{config, OrderLevel};
+use std::time::Duration;
+
+/// Fetches `depth` levels per side from Gemini REST `/v1/book/:symbol`
+/// (`GEMINI_REST_URL`, default `https://api.gemini.com`) to seed the book before the
+/// v2 feed's own snapshot arrives. Prices go through the same convention as the feed.
+pub async fn fetch_book(symbol: &str, depth: usize, prices: PriceConvention, qty_scale: u64) -> anyhow::Result<SnapshotSides> {
+    let base = config::var("GEMINI_REST_URL").unwrap_or_else(|_| "https://api.gemini.com".to_string());
+    fetch_book_from(&base, symbol, depth, prices, qty_scale).await
+}
+
+async fn fetch_book_from(base: &str, symbol: &str, depth: usize, prices: PriceConvention, qty_scale: u64) -> anyhow::Result<SnapshotSides> {
+    let url = format!("{}/v1/book/{}?limit_bids={}&limit_asks={}", base.trim_end_matches('/'), symbol.to_lowercase(), depth, depth);
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?;
+    let v = client.get(&url).send().await?.error_for_status()?.json::<serde_json::Value>().await?;
+    let side = |k: &str| -> anyhow::Result<Vec<OrderLevel>> {
+        let lvls = v.get(k).and_then(|x| x.as_array()).ok_or_else(|| anyhow::anyhow!("REST book has no '{}'", k))?;
+        Ok(lvls.iter().take(depth).map(|lvl| OrderLevel {
+            price: lvl.get("price").and_then(|x| prices.price(x)).unwrap_or(0),
+            qty: lvl.get("amount").and_then(|x| parse_scaled(x, qty_scale)).unwrap_or(0),
+        }).collect())
+    };
+    let (mut bids, mut asks) = (side("bids")?, side("asks")?);
+    if prices.invert {
+        std::mem::swap(&mut bids, &mut asks);
+    }
+    let ts = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    Ok(SnapshotSides { bids: Some(bids), asks: Some(asks), ts })
+}
+
+/// Levels (by index, up to the longer side) where the live book differs from the
+/// seed; an empty level and a missing one count as the same.
+pub fn differing_levels(seed: &[OrderLevel], live: &[OrderLevel]) -> usize {
+    let at = |side: &[OrderLevel], i: usize| side.get(i).filter(|l| l.price > 0).map(|l| (l.price, l.qty));
+    (0..seed.len().max(live.len())).filter(|&i| at(seed, i) != at(live, i)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply_changes;
+    use crate::sides::CrossCheck;
+    use shared::OrderBook;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `body` as the REST book to the first request and returns the base URL and
+    /// the path it was asked for.
+    async fn book_endpoint(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let served = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let path = String::from_utf8_lossy(&buf[..n]).split_whitespace().nth(1).unwrap_or("").to_string();
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            path
+        });
+        (base, served)
+    }
+
+    #[tokio::test]
+    async fn rest_seed_is_in_the_book_before_the_first_delta() {
+        let (base, served) = book_endpoint(r#"{
+            "bids": [{"price": "145.85", "amount": "2.5", "timestamp": "1700000000"}, {"price": "145.84", "amount": "1"}],
+            "asks": [{"price": "145.90", "amount": "1.8"}, {"price": "145.91", "amount": "4"}]
+        }"#).await;
+        let prices = PriceConvention { scale: 100, invert: false };
+        let seed = fetch_book_from(&base, "SOLUSD", 10, prices, 100).await.unwrap();
+        assert_eq!(served.await.unwrap(), "/v1/book/solusd?limit_bids=10&limit_asks=10");
+
+        let mut book = OrderBook::default();
+        let mut cross_check = CrossCheck::from_env();
+        seed.publish(&mut book, &mut cross_check);
+        let levels = |book: &OrderBook| (book.bids_iter().map(|(_, p, q)| (p, q)).collect::<Vec<_>>(), book.asks_iter().map(|(_, p, q)| (p, q)).collect::<Vec<_>>());
+        assert_eq!(levels(&book), (vec![(14_585, 250), (14_584, 100)], vec![(14_590, 180), (14_591, 400)]));
+
+        // The first v2 delta resizes one seeded level and deletes another, which only
+        // lands as intended on top of the seed
+        let changes: Vec<serde_json::Value> = serde_json::from_str(r#"[["buy","145.85","3"],["sell","145.90","0"]]"#).unwrap();
+        let mut bids: Vec<OrderLevel> = book.bids.iter().map(OrderLevel::load).collect();
+        let mut asks: Vec<OrderLevel> = book.asks.iter().map(OrderLevel::load).collect();
+        apply_changes(&changes, &mut bids, &mut asks, 10, prices, 100);
+        cross_check.publish(&mut book, &bids, &asks, 2);
+        assert_eq!(levels(&book), (vec![(14_585, 300), (14_584, 100)], vec![(14_591, 400)]));
+        assert_eq!(book.best_ask().map(|l| l.price), Some(14_591));
+    }
+}
@@ -0,0 +1,54 @@
+//! Reader cost while a writer thread hammers a nearby level of the same mapped book.
+//! Run once per layout and compare:
+//!
+//!     cargo bench -p shared --bench levels
+//!     cargo bench -p shared --bench levels --features padded-levels
+//!     cargo bench -p shared --bench levels --features split-sides
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use shared::{OrderBook, BOOK_DEPTH};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Maps a fresh book file, runs `write` in a loop on a second thread and benches `read`
+/// on the reader's mapping meanwhile.
+fn contended(c: &mut Criterion, name: &str, write: fn(&mut OrderBook, u64), read: fn(&OrderBook) -> u64) {
+    let path: PathBuf = std::env::temp_dir().join(format!("levels-bench-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    let (map, writer) = OrderBook::mmap(&path).unwrap();
+    let (_reader_map, reader) = OrderBook::mmap_readonly(&path).unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let _map = map;
+            let mut n = 0;
+            while !stop.load(Ordering::Relaxed) {
+                n += 1;
+                write(writer, n);
+            }
+        })
+    };
+    c.bench_function(name, |b| b.iter(|| black_box(read(reader))));
+    stop.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+fn levels(c: &mut Criterion) {
+    // Adjacent levels share a cache line in the compact layout
+    contended(c, "best_bid_while_second_bid_written", |w, n| w.update_bid(1, n, n), |r| r.bids[0].load_price());
+    // The deepest bid and the best ask share a line unless the sides are split
+    contended(c, "best_ask_while_deepest_bid_written", |w, n| w.update_bid(BOOK_DEPTH - 1, n, n), |r| r.asks[0].load_price());
+    // Seqlock snapshots retry on any write, whatever the layout
+    contended(c, "snapshot_while_level_published", |w, n| {
+        w.begin_write();
+        w.update_bid(1, n, n);
+        w.end_write();
+    }, |r| r.snapshot().timestamp_ms);
+}
+
+criterion_group!(benches, levels);
+criterion_main!(benches);
@@ -0,0 +1,424 @@
+use crate::{OrderLevel, Side, TradeEvent};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Infers the taker side of a trade from the prevailing quote: a print above mid was
+/// a buy, below mid a sell. Returns `None` at mid or without a two-sided quote.
+pub fn infer_taker_side(price_u: u64, bid_u: Option<u64>, ask_u: Option<u64>) -> Option<Side> {
+    let mid2 = bid_u? as u128 + ask_u? as u128;
+    let price2 = price_u as u128 * 2;
+    match price2.cmp(&mid2) {
+        std::cmp::Ordering::Greater => Some(Side::Buy),
+        std::cmp::Ordering::Less => Some(Side::Sell),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Trailing time-windowed VWAP over trades, in the same micro units as the inputs.
+///
+/// The window is "cold" until trades spanning at least `window_ms` have been seen,
+/// so a freshly started process reports `None` instead of a VWAP over a partial window.
+pub struct VwapWindow {
+    window_ms: u64,
+    trades: VecDeque<(u64, u64, u64)>, // (ts_ms, price_u, qty_u)
+    notional: u128,
+    volume: u128,
+    first_ts: Option<u64>,
+}
+
+impl VwapWindow {
+    pub fn new(window_ms: u64) -> Self {
+        Self { window_ms, trades: VecDeque::new(), notional: 0, volume: 0, first_ts: None }
+    }
+
+    /// Adds a trade and evicts trades older than the window relative to `ts_ms`.
+    pub fn push(&mut self, ts_ms: u64, price_u: u64, qty_u: u64) {
+        self.first_ts.get_or_insert(ts_ms);
+        self.trades.push_back((ts_ms, price_u, qty_u));
+        self.notional += price_u as u128 * qty_u as u128;
+        self.volume += qty_u as u128;
+        let cutoff = ts_ms.saturating_sub(self.window_ms);
+        while let Some(&(t, p, q)) = self.trades.front() {
+            if t > cutoff { break; }
+            self.notional -= p as u128 * q as u128;
+            self.volume -= q as u128;
+            self.trades.pop_front();
+        }
+    }
+
+    /// VWAP in micro-dollars, or `None` while the window is cold or empty.
+    pub fn vwap_u(&self) -> Option<u64> {
+        let last = self.trades.back()?.0;
+        if last.saturating_sub(self.first_ts?) < self.window_ms || self.volume == 0 {
+            return None;
+        }
+        Some((self.notional / self.volume) as u64)
+    }
+}
+
+/// Upper bound applied by `QtyFilter`.
+#[derive(Debug, Clone, Copy)]
+pub enum QtyCap {
+    /// Reject quantities above this many scaled units.
+    Absolute(u64),
+    /// Reject quantities above `multiple` x the median of the last `window` accepted trades.
+    MedianMultiple { multiple: f64, window: usize },
+}
+
+/// Flags implausibly large trade sizes (unit or parse errors). In median mode every
+/// trade is accepted until `QTY_FILTER_WARMUP` sizes have been seen, and rejected
+/// trades never enter the history, so one bad print can't raise the bar for the next.
+pub struct QtyFilter {
+    cap: QtyCap,
+    recent: VecDeque<u64>,
+}
+
+pub const QTY_FILTER_WARMUP: usize = 20;
+
+impl QtyFilter {
+    pub fn new(cap: QtyCap) -> Self {
+        Self { cap, recent: VecDeque::new() }
+    }
+
+    /// Returns `true` if `qty_u` is plausible and records it.
+    pub fn accept(&mut self, qty_u: u64) -> bool {
+        match self.cap {
+            QtyCap::Absolute(max) => qty_u <= max,
+            QtyCap::MedianMultiple { multiple, window } => {
+                if self.recent.len() >= QTY_FILTER_WARMUP.min(window) {
+                    let mut sorted: Vec<u64> = self.recent.iter().copied().collect();
+                    sorted.sort_unstable();
+                    let median = sorted[sorted.len() / 2];
+                    if qty_u as f64 > median as f64 * multiple {
+                        return false;
+                    }
+                }
+                self.recent.push_back(qty_u);
+                if self.recent.len() > window {
+                    self.recent.pop_front();
+                }
+                true
+            }
+        }
+    }
+}
+
+/// What to do with a trade stamped earlier than the latest one already seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfOrderPolicy {
+    /// Keep the trade's own timestamp.
+    Accept,
+    /// Move the timestamp forward to the latest one seen.
+    Clamp,
+    /// Discard the trade.
+    Drop,
+}
+
+/// Keeps one trade stream's timestamps monotonic according to an `OutOfOrderPolicy`,
+/// counting the out-of-order trades it sees (under any policy).
+pub struct TsOrderGuard {
+    policy: OutOfOrderPolicy,
+    last_ts: Option<u64>,
+    pub out_of_order: u64,
+}
+
+impl TsOrderGuard {
+    pub fn new(policy: OutOfOrderPolicy) -> Self {
+        Self { policy, last_ts: None, out_of_order: 0 }
+    }
+
+    /// The timestamp to use for a trade stamped `ts_ms`, or `None` to drop it. Equal
+    /// timestamps are in order.
+    pub fn check(&mut self, ts_ms: u64) -> Option<u64> {
+        let last = *self.last_ts.get_or_insert(ts_ms);
+        if ts_ms >= last {
+            self.last_ts = Some(ts_ms);
+            return Some(ts_ms);
+        }
+        self.out_of_order += 1;
+        match self.policy {
+            OutOfOrderPolicy::Accept => Some(ts_ms),
+            OutOfOrderPolicy::Clamp => Some(last),
+            OutOfOrderPolicy::Drop => None,
+        }
+    }
+}
+
+/// Book state sampled over a stats window, scored by `book_quality_score`.
+#[derive(Debug, Clone, Copy)]
+pub struct BookQuality {
+    /// Age of the book's last update when sampled.
+    pub age_ms: u64,
+    pub bid_levels: usize,
+    pub ask_levels: usize,
+    /// Current spread; `None` when one side is empty.
+    pub spread_bps: Option<f64>,
+    /// Fraction of updates in the window that left the book crossed or locked.
+    pub crossed_rate: f64,
+}
+
+/// Composite 0-100 score for SLA reporting, the sum of:
+/// - freshness (30): full when ≤1s old, falling linearly to 0 at 30s
+/// - depth (25): the thinner side's levels out of 10
+/// - spread (25): full at ≤10 bps, falling linearly to 0 at 100 bps; 0 if one-sided or crossed
+/// - crossed rate (20): scaled by the fraction of updates that were not crossed
+pub fn book_quality_score(q: &BookQuality) -> u8 {
+    let ramp = |x: f64, good: f64, bad: f64| ((bad - x) / (bad - good)).clamp(0.0, 1.0);
+    let freshness = ramp(q.age_ms as f64, 1_000.0, 30_000.0);
+    let depth = (q.bid_levels.min(q.ask_levels) as f64 / 10.0).min(1.0);
+    let spread = q.spread_bps.filter(|s| *s > 0.0).map_or(0.0, |s| ramp(s, 10.0, 100.0));
+    let crossed = 1.0 - q.crossed_rate.clamp(0.0, 1.0);
+    (30.0 * freshness + 25.0 * depth + 25.0 * spread + 20.0 * crossed).round() as u8
+}
+
+/// Running quantity from the top of one book side down to each level. Empty (zero)
+/// levels add nothing, so the sum carries through gaps unchanged.
+pub fn cumulative_qty(levels: &[OrderLevel]) -> Vec<u64> {
+    levels.iter()
+        .scan(0u64, |sum, lvl| {
+            *sum = sum.saturating_add(lvl.load_qty());
+            Some(*sum)
+        })
+        .collect()
+}
+
+/// Traded quantity at one price bucket of a `VolumeProfile`, split by taker direction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeBucket {
+    /// Lower edge of the bucket in scaled price units.
+    pub price_u: u64,
+    pub buy_qty: u64,
+    pub sell_qty: u64,
+    /// Trades with no known side.
+    pub unknown_qty: u64,
+}
+
+impl VolumeBucket {
+    pub fn total(&self) -> u64 { self.buy_qty + self.sell_qty + self.unknown_qty }
+}
+
+/// Volume-at-price over a trailing time window: trade quantity bucketed into price
+/// bands `granularity_u` wide (floored), split into taker buys and sells.
+pub struct VolumeProfile {
+    granularity_u: u64,
+    window_ms: u64,
+    trades: VecDeque<(u64, u64, Option<bool>, u64)>, // (ts_ms, bucket price_u, taker buy, qty_u)
+    buckets: BTreeMap<u64, VolumeBucket>,
+}
+
+impl VolumeProfile {
+    /// A zero granularity buckets by exact price.
+    pub fn new(granularity_u: u64, window_ms: u64) -> Self {
+        Self { granularity_u: granularity_u.max(1), window_ms, trades: VecDeque::new(), buckets: BTreeMap::new() }
+    }
+
+    /// Adds a trade and evicts trades older than the window relative to its `ts_ms`.
+    pub fn push(&mut self, tr: &TradeEvent) {
+        let price = tr.price_u / self.granularity_u * self.granularity_u;
+        let buy = tr.side.map(|s| s == Side::Buy);
+        self.trades.push_back((tr.ts_ms, price, buy, tr.qty_u));
+        Self::apply(&mut self.buckets, price, buy, tr.qty_u, true);
+        let cutoff = tr.ts_ms.saturating_sub(self.window_ms);
+        while let Some(&(t, p, b, q)) = self.trades.front() {
+            if t > cutoff { break; }
+            Self::apply(&mut self.buckets, p, b, q, false);
+            self.trades.pop_front();
+        }
+    }
+
+    fn apply(buckets: &mut BTreeMap<u64, VolumeBucket>, price_u: u64, buy: Option<bool>, qty_u: u64, add: bool) {
+        let bucket = buckets.entry(price_u).or_insert(VolumeBucket { price_u, ..Default::default() });
+        let slot = match buy {
+            Some(true) => &mut bucket.buy_qty,
+            Some(false) => &mut bucket.sell_qty,
+            None => &mut bucket.unknown_qty,
+        };
+        *slot = if add { slot.saturating_add(qty_u) } else { slot.saturating_sub(qty_u) };
+        if bucket.total() == 0 {
+            buckets.remove(&price_u);
+        }
+    }
+
+    /// Non-empty buckets, lowest price first.
+    pub fn buckets(&self) -> impl Iterator<Item = &VolumeBucket> {
+        self.buckets.values()
+    }
+
+    /// Point of control: the bucket with the most volume (the lowest price on ties),
+    /// or `None` while the window is empty.
+    pub fn poc(&self) -> Option<&VolumeBucket> {
+        self.buckets.values().rev().max_by_key(|b| b.total())
+    }
+}
+
+/// Size-weighted mid: `(bid * ask_qty + ask * bid_qty) / (bid_qty + ask_qty)` in scaled
+/// price units, leaning towards the side with less size. `None` if either side is empty.
+pub fn microprice(bid_u: u64, bid_qty: u64, ask_u: u64, ask_qty: u64) -> Option<f64> {
+    if bid_u == 0 || ask_u == 0 || bid_qty + ask_qty == 0 { return None; }
+    let (bq, aq) = (bid_qty as f64, ask_qty as f64);
+    Some((bid_u as f64 * aq + ask_u as f64 * bq) / (bq + aq))
+}
+
+/// `(bid_qty - ask_qty) / (bid_qty + ask_qty)`, from -1 (all ask) to +1 (all bid);
+/// `None` when both are empty.
+pub fn depth_imbalance(bid_qty: u64, ask_qty: u64) -> Option<f64> {
+    let total = bid_qty as f64 + ask_qty as f64;
+    (total > 0.0).then(|| (bid_qty as f64 - ask_qty as f64) / total)
+}
+
+/// Direction of a short series of samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Rising,
+    Falling,
+    Flat,
+}
+
+impl Trend {
+    pub fn arrow(self) -> &'static str {
+        match self {
+            Trend::Rising => "↑",
+            Trend::Falling => "↓",
+            Trend::Flat => "→",
+        }
+    }
+}
+
+/// Compares the mean of the newer half of `samples` (oldest first) with the older
+/// half; a move of at most `flat_within` is `Flat`. `None` with fewer than 2 samples.
+pub fn trend(samples: &[f64], flat_within: f64) -> Option<Trend> {
+    if samples.len() < 2 { return None; }
+    let (older, newer) = samples.split_at(samples.len() / 2);
+    let mean = |s: &[f64]| s.iter().sum::<f64>() / s.len() as f64;
+    let delta = mean(newer) - mean(older);
+    Some(if delta > flat_within {
+        Trend::Rising
+    } else if delta < -flat_within {
+        Trend::Falling
+    } else {
+        Trend::Flat
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vwap_window_is_cold_until_full_then_trails() {
+        let mut w = VwapWindow::new(1_000);
+        assert_eq!(w.vwap_u(), None);
+        w.push(10_000, 100, 1);
+        w.push(10_500, 200, 1);
+        assert_eq!(w.vwap_u(), None, "cold until the trades span a window");
+        // Spans the window; the trade at exactly the cutoff is evicted
+        w.push(11_000, 300, 2);
+        assert_eq!(w.vwap_u(), Some((200 + 300 * 2) / 3));
+        w.push(12_600, 100, 1);
+        assert_eq!(w.vwap_u(), Some(100));
+    }
+
+    #[test]
+    fn qty_filter_absolute_cap() {
+        let mut f = QtyFilter::new(QtyCap::Absolute(1_000));
+        assert!(f.accept(1_000), "at the cap is kept");
+        assert!(!f.accept(1_001));
+    }
+
+    #[test]
+    fn qty_filter_median_multiple() {
+        let mut f = QtyFilter::new(QtyCap::MedianMultiple { multiple: 10.0, window: 50 });
+        assert!(f.accept(1_000_000), "everything passes during warm-up");
+        for _ in 1..QTY_FILTER_WARMUP {
+            assert!(f.accept(100));
+        }
+        // Median is 100 now
+        assert!(f.accept(1_000));
+        assert!(!f.accept(1_001));
+        // Rejected sizes stay out of the history, so repeats are still rejected
+        for _ in 0..100 {
+            assert!(!f.accept(5_000));
+        }
+    }
+
+    #[test]
+    fn book_quality_score_components() {
+        let q = |age_ms, levels, spread_bps, crossed_rate| BookQuality { age_ms, bid_levels: levels, ask_levels: 20, spread_bps, crossed_rate };
+        assert_eq!(book_quality_score(&q(500, 12, Some(5.0), 0.0)), 100);
+        assert_eq!(book_quality_score(&q(30_000, 0, None, 1.0)), 0);
+        // Half of each: 15 + 12.5 + 12.5 + 10
+        assert_eq!(book_quality_score(&q(15_500, 5, Some(55.0), 0.5)), 50);
+        // One-sided or crossed books get no spread score
+        assert_eq!(book_quality_score(&q(500, 12, None, 0.0)), 75);
+        assert_eq!(book_quality_score(&q(500, 12, Some(-3.0), 0.0)), 75);
+    }
+
+    fn trade(ts_ms: u64, price_u: u64, qty_u: u64, side: Option<Side>) -> TradeEvent {
+        TradeEvent::builder("SOLUSD").ts_ms(ts_ms).price_u(price_u).qty_u(qty_u).side(side, false).build().unwrap()
+    }
+
+    #[test]
+    fn volume_profile_buckets_by_direction() {
+        let mut p = VolumeProfile::new(100, 60_000);
+        assert!(p.poc().is_none());
+        p.push(&trade(1_000, 1_050, 3, Some(Side::Buy)));
+        p.push(&trade(2_000, 1_099, 2, Some(Side::Sell)));
+        p.push(&trade(3_000, 1_100, 4, None));
+        p.push(&trade(4_000, 1_000, 1, Some(Side::Buy)));
+        let buckets: Vec<VolumeBucket> = p.buckets().copied().collect();
+        assert_eq!(buckets, [
+            VolumeBucket { price_u: 1_000, buy_qty: 4, sell_qty: 2, unknown_qty: 0 },
+            VolumeBucket { price_u: 1_100, buy_qty: 0, sell_qty: 0, unknown_qty: 4 },
+        ]);
+        assert_eq!(p.poc().map(|b| b.price_u), Some(1_000));
+    }
+
+    #[test]
+    fn volume_profile_evicts_old_trades() {
+        let mut p = VolumeProfile::new(0, 1_000);
+        p.push(&trade(1_000, 500, 9, Some(Side::Sell)));
+        p.push(&trade(1_500, 700, 1, Some(Side::Buy)));
+        p.push(&trade(1_600, 700, 1, Some(Side::Buy)));
+        assert_eq!(p.poc().map(|b| b.price_u), Some(500));
+        p.push(&trade(2_000, 700, 1, Some(Side::Buy)));
+        assert_eq!(p.buckets().map(|b| (b.price_u, b.buy_qty)).collect::<Vec<_>>(), [(700, 3)], "emptied buckets are removed");
+    }
+
+    #[test]
+    fn ts_order_guard_policies() {
+        let run = |policy| {
+            let mut g = TsOrderGuard::new(policy);
+            let out: Vec<Option<u64>> = [100, 100, 90, 120, 110].into_iter().map(|ts| g.check(ts)).collect();
+            (out, g.out_of_order)
+        };
+        assert_eq!(run(OutOfOrderPolicy::Accept), (vec![Some(100), Some(100), Some(90), Some(120), Some(110)], 2));
+        assert_eq!(run(OutOfOrderPolicy::Clamp), (vec![Some(100), Some(100), Some(100), Some(120), Some(120)], 2));
+        assert_eq!(run(OutOfOrderPolicy::Drop), (vec![Some(100), Some(100), None, Some(120), None], 2));
+    }
+
+    #[test]
+    fn trend_follows_a_rise_then_a_fall() {
+        // A 4-sample window sliding over a price that peaks and comes back down
+        let series = [100.0, 101.0, 102.0, 103.0, 104.0, 103.0, 102.0, 101.0, 100.0];
+        let trends: Vec<_> = series.windows(4).map(|w| trend(w, 1.0).unwrap()).collect();
+        // Around the peak the halves differ by exactly the threshold, which reads as flat
+        assert_eq!(trends, vec![Trend::Rising, Trend::Rising, Trend::Flat, Trend::Flat, Trend::Falling, Trend::Falling]);
+
+        // The threshold itself is still flat
+        assert_eq!(trend(&[1.0, 1.5], 0.5), Some(Trend::Flat));
+        assert_eq!(trend(&[1.5, 1.0], 0.5), Some(Trend::Flat));
+        assert_eq!(trend(&[1.0, 1.75], 0.5), Some(Trend::Rising));
+        assert_eq!(trend(&[1.75, 1.0], 0.5), Some(Trend::Falling));
+        assert_eq!(trend(&[2.0, 2.0], 0.0), Some(Trend::Flat));
+        assert_eq!(trend(&[1.0], 0.0), None);
+        assert_eq!(Trend::Falling.arrow(), "↓");
+    }
+
+    #[test]
+    fn cumulative_qty_carries_through_empty_levels() {
+        let levels: Vec<OrderLevel> = [(100, 5), (99, 0), (98, 3), (0, 0), (0, 0)]
+            .iter().map(|&(price, qty)| OrderLevel { price, qty }).collect();
+        assert_eq!(cumulative_qty(&levels), vec![5, 5, 8, 8, 8]);
+        assert_eq!(cumulative_qty(&[OrderLevel { price: 1, qty: u64::MAX }, OrderLevel { price: 1, qty: 1 }]), vec![u64::MAX, u64::MAX], "saturates");
+        assert!(cumulative_qty(&[]).is_empty());
+    }
+}
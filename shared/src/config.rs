@@ -0,0 +1,51 @@
+//! Environment lookup shared by all binaries. When `APP_ENV_PREFIX` is set (e.g.
+//! `SOL`), `var("KAFKA_TOPIC")` reads `SOL_KAFKA_TOPIC` first and falls back to the
+//! bare `KAFKA_TOPIC`, so several services can share one environment.
+
+use std::env::{self, VarError};
+
+/// Drop-in replacement for `std::env::var` that honours `APP_ENV_PREFIX`.
+pub fn var(name: &str) -> Result<String, VarError> {
+    lookup(name, |k| env::var(k))
+}
+
+/// `var` over any source of variables.
+fn lookup(name: &str, get: impl Fn(&str) -> Result<String, VarError>) -> Result<String, VarError> {
+    match get("APP_ENV_PREFIX") {
+        Ok(prefix) if !prefix.is_empty() => {
+            get(&format!("{}_{}", prefix.trim_end_matches('_'), name)).or_else(|_| get(name))
+        }
+        _ => get(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Result<String, VarError> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |k| vars.get(k).cloned().ok_or(VarError::NotPresent)
+    }
+
+    #[test]
+    fn prefixed_variable_beats_the_bare_one() {
+        let env = from(&[("APP_ENV_PREFIX", "SOL"), ("SOL_KAFKA_TOPIC", "sol-trades"), ("KAFKA_TOPIC", "trades"), ("KAFKA_BROKERS", "broker:9092")]);
+        assert_eq!(lookup("KAFKA_TOPIC", &env), Ok("sol-trades".to_string()));
+        assert_eq!(lookup("KAFKA_BROKERS", &env), Ok("broker:9092".to_string()), "falls back to the bare name");
+        assert_eq!(lookup("PULSAR_URL", &env), Err(VarError::NotPresent));
+
+        let trailing = from(&[("APP_ENV_PREFIX", "SOL_"), ("SOL_KAFKA_TOPIC", "sol-trades")]);
+        assert_eq!(lookup("KAFKA_TOPIC", &trailing), Ok("sol-trades".to_string()), "no double underscore");
+    }
+
+    #[test]
+    fn without_a_prefix_only_the_bare_name_is_read() {
+        for prefix in [None, Some("")] {
+            let mut vars = vec![("SOL_KAFKA_TOPIC", "sol-trades"), ("KAFKA_TOPIC", "trades")];
+            vars.extend(prefix.map(|p| ("APP_ENV_PREFIX", p)));
+            assert_eq!(lookup("KAFKA_TOPIC", from(&vars)), Ok("trades".to_string()));
+        }
+    }
+}
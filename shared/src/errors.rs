@@ -0,0 +1,62 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Failure classes for alerting. Error logs carry the class as a `category` field
+/// alongside its running `errors_total`, the process-wide count kept by `record`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Venue or broker connection could not be established.
+    Connect,
+    /// TLS handshake or certificate failure while connecting.
+    Tls,
+    /// A frame, payload or field that could not be parsed or validated.
+    Parse,
+    /// The book became crossed or locked (once per episode), or a crossed update was rejected.
+    Crossed,
+    /// A live connection stopped delivering data or pongs.
+    Stale,
+    /// Sequence gap or other loss that needs the stream re-anchored.
+    Resync,
+    /// Publishing to the bus, an output topic or an alert sink failed.
+    Produce,
+    /// Writing to Postgres or flushing the mmap files failed.
+    Persist,
+}
+
+impl ErrorCategory {
+    pub const ALL: [ErrorCategory; 8] = [
+        ErrorCategory::Connect, ErrorCategory::Tls, ErrorCategory::Parse, ErrorCategory::Crossed,
+        ErrorCategory::Stale, ErrorCategory::Resync, ErrorCategory::Produce, ErrorCategory::Persist,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::Connect => "connect",
+            ErrorCategory::Tls => "tls",
+            ErrorCategory::Parse => "parse",
+            ErrorCategory::Crossed => "crossed",
+            ErrorCategory::Stale => "stale",
+            ErrorCategory::Resync => "resync",
+            ErrorCategory::Produce => "produce",
+            ErrorCategory::Persist => "persist",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+static TOTALS: [AtomicU64; 8] = [const { AtomicU64::new(0) }; 8];
+
+/// Counts one error of `category` and returns its new total.
+pub fn record(category: ErrorCategory) -> u64 {
+    TOTALS[category as usize].fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Current `errors_total` per category, in `ErrorCategory::ALL` order.
+pub fn totals() -> [(ErrorCategory, u64); 8] {
+    ErrorCategory::ALL.map(|c| (c, TOTALS[c as usize].load(Ordering::Relaxed)))
+}
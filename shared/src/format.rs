@@ -0,0 +1,37 @@
+//! Decimal rendering of fixed-point book values, shared by the `Display`/`Debug`
+//! impls and the reader.
+
+/// Renders `v` scaled units as a decimal with as many places as `scale` has zeros.
+pub fn scaled(v: u64, scale: u64) -> String {
+    let decimals = scale.ilog10() as usize;
+    format!("{:.*}", decimals, v as f64 / scale as f64)
+}
+
+/// Formats basis points with `decimals` places, widening to two significant digits
+/// when a non-zero value would otherwise round to zero (very tight spreads).
+pub fn bps(bps: f64, decimals: usize) -> String {
+    let floor = 0.5 * 10f64.powi(-(decimals as i32));
+    let decimals = if bps != 0.0 && bps.abs() < floor {
+        (-bps.abs().log10()).ceil() as usize + 1
+    } else {
+        decimals
+    };
+    format!("{:.*}", decimals, bps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_spreads_keep_two_significant_digits() {
+        // Default reader precision is two decimals
+        assert_eq!(bps(3.4281, 2), "3.43");
+        assert_eq!(bps(0.004, 2), "0.0040");
+        assert_eq!(bps(-0.004, 2), "-0.0040", "crossed");
+        // One micro-dollar on a 145.85 price
+        assert_eq!(bps(1.0 / 145_850_000.0 * 10_000.0, 2), "0.000069");
+        assert_eq!(bps(0.0, 2), "0.00", "a locked book stays at the configured precision");
+        assert_eq!(bps(0.04, 0), "0.040");
+    }
+}
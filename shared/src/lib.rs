@@ -1,16 +1,219 @@
-use std::fs::OpenOptions;
+use std::fmt;
+use std::fs::{File, OpenOptions};
 use std::mem::size_of;
 use std::path::Path;
 use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
 use memmap2::MmapOptions;
 
+pub mod analytics;
+pub mod config;
+pub mod errors;
+pub mod format;
+pub mod metrics;
+pub mod poll;
+pub mod ring;
+
 pub const BOOK_DEPTH: usize = 50;
+pub const SYMBOL_LEN: usize = 16;
+/// `timestamp_ms` of a book whose writer stopped publishing it on purpose (ingest's kill
+/// switch). Zero, by contrast, means never written.
+pub const TS_DISABLED: u64 = u64::MAX;
+
+/// Which sides of an `OrderBook` are quoted. Gemini can send one-sided or empty
+/// snapshots, e.g. during a halt, leaving no mid or spread to analyse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookStatus {
+    TwoSided,
+    OneSided,
+    Empty,
+}
+
+impl BookStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BookStatus::TwoSided => "TWO_SIDED",
+            BookStatus::OneSided => "ONE_SIDED",
+            BookStatus::Empty => "EMPTY",
+        }
+    }
+}
+
+impl fmt::Display for BookStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookError {
+    /// Level index at or beyond `BOOK_DEPTH`.
+    IndexOutOfRange { index: usize, depth: usize },
+    /// Configured depth is zero or larger than the mapped file holds.
+    DepthExceedsCapacity { depth: usize, capacity: usize },
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::IndexOutOfRange { index, depth } => write!(f, "level index {} out of range (depth {})", index, depth),
+            BookError::DepthExceedsCapacity { depth, capacity } => write!(f, "configured depth {} not within 1..={} levels the book file holds", depth, capacity),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TradeError {
+    EmptySymbol,
+    /// Price is zero or too large to store as a signed 64-bit column.
+    BadPrice(u64),
+    /// Quantity is zero or too large to store as a signed 64-bit column.
+    BadQty(u64),
+    /// Side isn't a recognized spelling (see `Side`).
+    BadSide(String),
+}
+
+impl fmt::Display for TradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeError::EmptySymbol => write!(f, "empty symbol"),
+            TradeError::BadPrice(p) => write!(f, "invalid price {}", p),
+            TradeError::BadQty(q) => write!(f, "invalid quantity {}", q),
+            TradeError::BadSide(s) => write!(f, "invalid side '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for TradeError {}
+
+// Seqlock helpers over a `u64` sequence word living in the mmap. Writers make the
+// sequence odd while mutating and even again once done; readers retry until they see
+// the same even value before and after copying.
+#[inline] fn seq_atomic(seq: &mut u64) -> &AtomicU64 { unsafe { AtomicU64::from_ptr(seq) } }
+#[inline] fn seq_load(seq: &u64) -> u64 { unsafe { AtomicU64::from_ptr(seq as *const u64 as *mut u64) }.load(Ordering::Acquire) }
+#[inline] fn seq_begin(seq: &mut u64) { let a = seq_atomic(seq); a.store(a.load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed); fence(Ordering::Release); }
+#[inline] fn seq_end(seq: &mut u64) { let a = seq_atomic(seq); a.store(a.load(Ordering::Relaxed).wrapping_add(1), Ordering::Release); }
+
+/// Runs `read` until it observes a stable, even sequence, returning the value and sequence.
+#[inline] fn seq_read<T>(seq: &u64, read: impl Fn() -> T) -> (T, u64) {
+    loop {
+        let s1 = seq_load(seq);
+        if s1 & 1 == 1 { std::hint::spin_loop(); continue; }
+        let v = read();
+        fence(Ordering::Acquire);
+        if seq_load(seq) == s1 { return (v, s1); }
+    }
+}
+
+/// Bounded `seq_read`: `None` if every one of `attempts` tries overlapped a write.
+#[inline] fn seq_try_read<T>(seq: &u64, attempts: u32, read: impl Fn() -> T) -> Option<(T, u64)> {
+    for _ in 0..attempts {
+        let s1 = seq_load(seq);
+        if s1 & 1 == 1 { std::hint::spin_loop(); continue; }
+        let v = read();
+        fence(Ordering::Acquire);
+        if seq_load(seq) == s1 { return Some((v, s1)); }
+    }
+    None
+}
+
+/// Self-describing metadata stored in each mmap file, so readers know what a file
+/// holds without separate config. All-zero means the writer hasn't populated it.
 #[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BookMeta {
+    /// Symbol as ASCII, NUL-padded (longer names are truncated).
+    pub symbol: [u8; SYMBOL_LEN],
+    /// Integer units per 1.0 of price / quantity.
+    pub price_scale: u64,
+    pub qty_scale: u64,
+    /// Minimum price increment in scaled price units (0 if unknown).
+    pub tick_size: u64,
+    /// Number of levels per side the writer maintains.
+    pub depth: u64,
+}
+
+impl BookMeta {
+    pub fn new(symbol: &str, price_scale: u64, qty_scale: u64, tick_size: u64, depth: usize) -> Self {
+        let mut buf = [0u8; SYMBOL_LEN];
+        let n = symbol.len().min(SYMBOL_LEN);
+        buf[..n].copy_from_slice(&symbol.as_bytes()[..n]);
+        Self { symbol: buf, price_scale, qty_scale, tick_size, depth: depth as u64 }
+    }
+
+    pub fn symbol(&self) -> &str {
+        let end = self.symbol.iter().position(|&b| b == 0).unwrap_or(SYMBOL_LEN);
+        std::str::from_utf8(&self.symbol[..end]).unwrap_or("")
+    }
+
+    /// `(price_scale, qty_scale)`, falling back to micro units when the writer
+    /// predates the metadata region.
+    pub fn scales(&self) -> (u64, u64) {
+        let or_micro = |s: u64| if s == 0 { 1_000_000 } else { s };
+        (or_micro(self.price_scale), or_micro(self.qty_scale))
+    }
+}
+
+/// Opens `path` (creating it if missing) and maps exactly `len` bytes. A new, empty
+/// file is sized to `len`; an existing file of any other length is refused instead of
+/// resized, since another process may still have it mapped at the old size and a
+/// shorter file would let us read past its end. The mapping never follows later
+/// external resizes, so files must not be truncated or extended while in use.
+fn open_mapping(path: &Path, len: usize) -> std::io::Result<memmap2::MmapMut> {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+    let actual = file.metadata()?.len();
+    if actual == 0 {
+        file.set_len(len as u64)?;
+    } else if actual != len as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is {} bytes, expected {} (written by a different layout? remove it to recreate)", path.display(), actual, len),
+        ));
+    }
+    let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+    if mmap.len() < len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("{} shrank to {} bytes while mapping, expected {}", path.display(), mmap.len(), len),
+        ));
+    }
+    Ok(mmap)
+}
+
+/// Maps an existing file at `path` read-only, for processes that must never create or
+/// modify the writer's files. A missing file or one of any length other than `len` is
+/// an error, as in `open_mapping`.
+fn open_mapping_readonly(path: &Path, len: usize) -> std::io::Result<memmap2::Mmap> {
+    let file = File::open(path)?;
+    let actual = file.metadata()?.len();
+    if actual != len as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is {} bytes, expected {} (not written yet, or by a different layout?)", path.display(), actual, len),
+        ));
+    }
+    let mmap = unsafe { MmapOptions::new().map(&file)? };
+    if mmap.len() < len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("{} shrank to {} bytes while mapping, expected {}", path.display(), mmap.len(), len),
+        ));
+    }
+    Ok(mmap)
+}
+
+/// One price level. With the `padded-levels` feature each level is aligned to its own
+/// 64-byte cache line, so a writer updating one level doesn't invalidate the line a
+/// reader is polling on its neighbour (compare with `cargo bench -p shared --bench
+/// levels`). That grows `OrderBook` from ~1.6 KB to ~6.4 KB
+/// and shifts every offset after `bids`, so writer and readers must agree on the feature
+/// (a mismatched file is refused by `open_mapping`).
+#[cfg_attr(not(feature = "padded-levels"), repr(C))]
+#[cfg_attr(feature = "padded-levels", repr(C, align(64)))]
 #[derive(Default, Clone, Copy)]
 pub struct OrderLevel {
-    pub price: u64, // micro dollars
-    pub qty: u64,   // base units (1e-6)
+    pub price: u64, // quote units x `BookMeta::price_scale` (micro dollars without metadata)
+    pub qty: u64,   // base units x `BookMeta::qty_scale` (1e-6 without metadata)
 }
 
 impl OrderLevel {
@@ -19,38 +222,305 @@ impl OrderLevel {
     #[inline] pub fn store_price(&mut self, v: u64) { unsafe { ptr::write_volatile(&mut self.price, v) } }
     #[inline] pub fn load_qty(&self) -> u64 { unsafe { ptr::read_volatile(&self.qty) } }
     #[inline] pub fn store_qty(&mut self, v: u64) { unsafe { ptr::write_volatile(&mut self.qty, v) } }
+    /// Volatile copy of this level.
+    #[inline] pub fn load(&self) -> Self { Self { price: self.load_price(), qty: self.load_qty() } }
 }
 
+/// Bytes between `bids` and `asks`. With the `split-sides` feature they start `asks` on
+/// a fresh cache line (mappings are page-aligned), so the writer updating the deepest
+/// bid doesn't invalidate the best ask a reader is polling. Zero without the feature,
+/// and with `padded-levels`, whose levels already end on a line boundary.
+const SIDE_PAD: usize = if cfg!(feature = "split-sides") {
+    (64 - size_of::<[OrderLevel; BOOK_DEPTH]>() % 64) % 64
+} else {
+    0
+};
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct OrderBook {
     pub bids: [OrderLevel; BOOK_DEPTH],
+    _side_pad: [u8; SIDE_PAD],
     pub asks: [OrderLevel; BOOK_DEPTH],
     pub timestamp_ms: u64,
+    /// Seqlock sequence (offset 1608 in the compact layout): odd while a `begin_write`/`end_write` section is open.
+    pub seq: u64,
+    /// File metadata (offset 1616 in the compact layout), appended so existing field offsets stay put.
+    pub meta: BookMeta,
+    /// Writer liveness counter (offset 1664 in the compact layout), see `heartbeat`.
+    pub heartbeat: u64,
 }
 
 impl Default for OrderBook {
     fn default() -> Self {
         Self {
             bids: [OrderLevel::default(); BOOK_DEPTH],
+            _side_pad: [0; SIDE_PAD],
             asks: [OrderLevel::default(); BOOK_DEPTH],
             timestamp_ms: 0,
+            seq: 0,
+            meta: BookMeta::default(),
+            heartbeat: 0,
         }
     }
 }
 
 impl OrderBook {
+    /// Maps `path` as an `OrderBook`, creating it if missing. Existing contents are
+    /// never truncated, so a restarted writer resumes from the last published book;
+    /// a file whose length doesn't match this layout is rejected (see `open_mapping`).
     pub fn mmap(path: &Path) -> std::io::Result<(memmap2::MmapMut, &'static mut Self)> {
-        let file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
-        file.set_len(size_of::<Self>() as u64)?;
-        let mut mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+        let mut mmap = open_mapping(path, size_of::<Self>())?;
         let ptr = mmap.as_mut_ptr() as *mut Self;
         let ob_ref = unsafe { &mut *ptr };
         Ok((mmap, ob_ref))
     }
-    #[inline] pub fn update_bid(&mut self, i: usize, price: u64, qty: u64) { if i<BOOK_DEPTH { self.bids[i].store_price(price); self.bids[i].store_qty(qty); }}
-    #[inline] pub fn update_ask(&mut self, i: usize, price: u64, qty: u64) { if i<BOOK_DEPTH { self.asks[i].store_price(price); self.asks[i].store_qty(qty); }}
+    /// Maps an existing `OrderBook` file read-only: never creates, resizes or writes it,
+    /// and needs only read permission. Missing files and files of the wrong size are
+    /// errors. The reference is only valid while the returned `Mmap` is alive.
+    pub fn mmap_readonly(path: &Path) -> std::io::Result<(memmap2::Mmap, &'static Self)> {
+        let mmap = open_mapping_readonly(path, size_of::<Self>())?;
+        let ob_ref = unsafe { &*(mmap.as_ptr() as *const Self) };
+        Ok((mmap, ob_ref))
+    }
+    /// Out-of-range indices are ignored in release builds and panic in debug builds;
+    /// use `try_update_bid`/`try_update_ask` to handle them explicitly.
+    #[inline] pub fn update_bid(&mut self, i: usize, price: u64, qty: u64) { debug_assert!(i<BOOK_DEPTH, "bid index {} >= BOOK_DEPTH", i); if i<BOOK_DEPTH { self.bids[i].store_price(price); self.bids[i].store_qty(qty); }}
+    #[inline] pub fn update_ask(&mut self, i: usize, price: u64, qty: u64) { debug_assert!(i<BOOK_DEPTH, "ask index {} >= BOOK_DEPTH", i); if i<BOOK_DEPTH { self.asks[i].store_price(price); self.asks[i].store_qty(qty); }}
+    #[inline] pub fn try_update_bid(&mut self, i: usize, price: u64, qty: u64) -> Result<(), BookError> { Self::check_index(i)?; self.update_bid(i, price, qty); Ok(()) }
+    #[inline] pub fn try_update_ask(&mut self, i: usize, price: u64, qty: u64) -> Result<(), BookError> { Self::check_index(i)?; self.update_ask(i, price, qty); Ok(()) }
+    #[inline] fn check_index(i: usize) -> Result<(), BookError> { if i<BOOK_DEPTH { Ok(()) } else { Err(BookError::IndexOutOfRange { index: i, depth: BOOK_DEPTH }) } }
     #[inline] pub fn set_ts(&mut self, ts: u64) { unsafe { ptr::write_volatile(&mut self.timestamp_ms, ts) } }
+    #[inline] pub fn set_meta(&mut self, meta: BookMeta) { unsafe { ptr::write_volatile(&mut self.meta, meta) } }
+    #[inline] pub fn meta(&self) -> BookMeta { unsafe { ptr::read_volatile(&self.meta) } }
+
+    /// Stamps the book `TS_DISABLED` in its own write section, leaving the levels as they
+    /// were. The next `publish` replaces the stamp.
+    pub fn mark_disabled(&mut self) {
+        self.begin_write();
+        self.set_ts(TS_DISABLED);
+        self.end_write();
+    }
+
+    /// Liveness counter the writer bumps on a fixed cadence whether or not the book
+    /// changes, outside the seqlock. A counter that stops advancing means the writer is
+    /// gone, where a still timestamp may only mean a quiet market.
+    #[inline] pub fn heartbeat(&self) -> u64 { seq_load(&self.heartbeat) }
+    #[inline] pub fn bump_heartbeat(&mut self) { seq_atomic(&mut self.heartbeat).fetch_add(1, Ordering::Release); }
+
+    /// Opens a seqlock write section. Level/timestamp setters don't touch the sequence
+    /// themselves, so writers group related updates between `begin_write` and `end_write`.
+    #[inline] pub fn begin_write(&mut self) { seq_begin(&mut self.seq); }
+    #[inline] pub fn end_write(&mut self) { seq_end(&mut self.seq); }
+
+    /// Current seqlock sequence (acquire load). Every `publish` moves it by 2, so two
+    /// reads returning different values mean the book changed in between, and a
+    /// difference above 2 means intermediate states were missed. Odd means a write is
+    /// in progress; `snapshot`/`try_snapshot` already retry around those.
+    #[inline] pub fn load_seq(&self) -> u64 { seq_load(&self.seq) }
+
+    /// Replaces the whole book under a single seqlock bump. Levels beyond the given
+    /// slices are cleared, so readers always see one complete snapshot.
+    pub fn publish(&mut self, bids: &[OrderLevel], asks: &[OrderLevel], ts: u64) {
+        self.begin_write();
+        for i in 0..BOOK_DEPTH {
+            let b = bids.get(i).copied().unwrap_or_default();
+            let a = asks.get(i).copied().unwrap_or_default();
+            self.update_bid(i, b.price, b.qty);
+            self.update_ask(i, a.price, a.qty);
+        }
+        self.set_ts(ts);
+        self.end_write();
+    }
+
+    /// Owned point-in-time copy of the whole book, retried until no write overlaps it
+    /// (the returned `seq` is the one it was read at). This is the safe way to read a
+    /// mapped book that a writer may be updating; like `TopOfBook::snapshot` it spins
+    /// while a write is open, so use `try_snapshot` if the writer may die mid-write.
+    pub fn snapshot(&self) -> OrderBook {
+        let (mut book, seq) = seq_read(&self.seq, || self.copy_levels());
+        book.seq = seq;
+        book
+    }
+
+    /// Copies the whole book under the seqlock, trying up to `attempts` times. `None`
+    /// means a `begin_write`/`end_write` section overlapped every attempt. Level
+    /// updates made outside such a section are not detected.
+    pub fn try_snapshot(&self, attempts: u32) -> Option<OrderBook> {
+        seq_try_read(&self.seq, attempts, || self.copy_levels())
+            .map(|(mut book, seq)| { book.seq = seq; book })
+    }
+
+    /// Unsynchronized volatile copy of levels, timestamp and metadata; callers wrap it
+    /// in a seqlock read.
+    fn copy_levels(&self) -> OrderBook {
+        let mut book = OrderBook::default();
+        for i in 0..BOOK_DEPTH {
+            book.bids[i] = self.bids[i].load();
+            book.asks[i] = self.asks[i].load();
+        }
+        book.timestamp_ms = unsafe { ptr::read_volatile(&self.timestamp_ms) };
+        book.meta = self.meta();
+        book
+    }
+
+    /// Flat `(side, level, price, qty)` rows, one per non-empty level with levels
+    /// numbered from 1, converted to decimals with the file's scales (micro units if
+    /// the metadata isn't populated). Bids come first, best level first.
+    pub fn to_rows(&self) -> Vec<(&'static str, usize, f64, f64)> {
+        let (price_scale, qty_scale) = self.meta().scales();
+        let (price_scale, qty_scale) = (price_scale as f64, qty_scale as f64);
+        let row = |side| move |(i, price, qty): (usize, u64, u64)| (side, i + 1, price as f64 / price_scale, qty as f64 / qty_scale);
+        self.bids_iter().map(row("bid")).chain(self.asks_iter().map(row("ask"))).collect()
+    }
+
+    /// Non-empty bid levels as `(index, price, qty)`, best first. Empty levels are
+    /// skipped rather than ending the walk, since incremental updates can clear a level
+    /// mid-ladder; `index` is the level's position in `bids`. Levels are loaded one at a
+    /// time, so iterate a `snapshot()` when they must be mutually consistent.
+    pub fn bids_iter(&self) -> impl Iterator<Item = (usize, u64, u64)> + '_ { active(&self.bids) }
+
+    /// Non-empty ask levels, as `bids_iter`.
+    pub fn asks_iter(&self) -> impl Iterator<Item = (usize, u64, u64)> + '_ { active(&self.asks) }
+
+    /// Levels per side this file can hold.
+    pub fn capacity(&self) -> usize { self.bids.len() }
+
+    /// Validates a configured per-side depth against `capacity`.
+    pub fn check_depth(&self, depth: usize) -> Result<usize, BookError> {
+        if depth == 0 || depth > self.capacity() {
+            return Err(BookError::DepthExceedsCapacity { depth, capacity: self.capacity() });
+        }
+        Ok(depth)
+    }
+
+    /// Best bid: the first non-empty level, since incremental updates can clear level 0
+    /// while deeper levels remain. `None` when the bid side is empty.
+    pub fn best_bid(&self) -> Option<OrderLevel> {
+        self.bids_iter().next().map(|(_, price, qty)| OrderLevel { price, qty })
+    }
+
+    /// Best ask, as `best_bid`.
+    pub fn best_ask(&self) -> Option<OrderLevel> {
+        self.asks_iter().next().map(|(_, price, qty)| OrderLevel { price, qty })
+    }
+
+    /// Integer midpoint of the best bid and ask in scaled price units (micro-dollars at
+    /// the default scale), rounded down; `None` unless both sides are quoted.
+    pub fn mid_price_u(&self) -> Option<u64> {
+        let (bid, ask) = (self.best_bid()?, self.best_ask()?);
+        Some(((bid.price as u128 + ask.price as u128) / 2) as u64)
+    }
+
+    /// Best ask minus best bid in scaled price units, zero for a locked or crossed book;
+    /// `None` unless both sides are quoted.
+    pub fn spread_u(&self) -> Option<u64> {
+        Some(self.best_ask()?.price.saturating_sub(self.best_bid()?.price))
+    }
+
+    /// `spread_u` over `mid_price_u` in basis points; `None` unless the book is
+    /// `TwoSided`, since a one-sided (e.g. halted) book has no meaningful spread.
+    pub fn spread_bps(&self) -> Option<f64> {
+        let mid = self.mid_price_u().filter(|m| *m > 0)?;
+        Some(self.spread_u()? as f64 / mid as f64 * 10_000.0)
+    }
+
+    /// Average price to buy `qty_u` base units by walking the asks from the best level,
+    /// taking only part of the last level touched; quantity-weighted, in scaled price
+    /// units, rounded down. `None` for a zero quantity or if the asks can't fill it.
+    /// Call it on a `snapshot()` so the levels walked are consistent.
+    pub fn vwap_buy(&self, qty_u: u64) -> Option<u64> { fill_vwap(self.asks_iter(), qty_u) }
+
+    /// Average price to sell `qty_u` base units into the bids, as `vwap_buy`.
+    pub fn vwap_sell(&self, qty_u: u64) -> Option<u64> { fill_vwap(self.bids_iter(), qty_u) }
+
+    /// Bid quantity summed over the top `levels` positions (clamped to `BOOK_DEPTH`).
+    pub fn total_bid_qty(&self, levels: usize) -> u64 { depth_qty(&self.bids, levels) }
+
+    /// Ask quantity summed over the top `levels` positions, as `total_bid_qty`.
+    pub fn total_ask_qty(&self, levels: usize) -> u64 { depth_qty(&self.asks, levels) }
+
+    /// `(bid_qty - ask_qty) / (bid_qty + ask_qty)` over the top `levels` of each side,
+    /// from -1 (all ask) to +1 (all bid); `None` when both sides are empty there.
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        analytics::depth_imbalance(self.total_bid_qty(levels), self.total_ask_qty(levels))
+    }
+
+    /// Best bid strictly above best ask. Empty sides never cross.
+    pub fn is_crossed(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some(bid), Some(ask)) if bid.price > ask.price)
+    }
+
+    /// Best bid equal to best ask. Empty sides never lock.
+    pub fn is_locked(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some(bid), Some(ask)) if bid.price == ask.price)
+    }
+
+    /// Which sides are quoted, from the best levels. Read it on a `snapshot()` when it
+    /// must agree with the levels used alongside it.
+    pub fn status(&self) -> BookStatus {
+        match (self.best_bid().is_some(), self.best_ask().is_some()) {
+            (true, true) => BookStatus::TwoSided,
+            (false, false) => BookStatus::Empty,
+            _ => BookStatus::OneSided,
+        }
+    }
+
+    /// Number of non-empty (bid, ask) levels currently in the book.
+    pub fn active_levels(&self) -> (usize, usize) {
+        (self.bids_iter().count(), self.asks_iter().count())
+    }
+}
+
+fn active(levels: &[OrderLevel]) -> impl Iterator<Item = (usize, u64, u64)> + '_ {
+    levels.iter().enumerate().filter_map(|(i, lvl)| {
+        let lvl = lvl.load();
+        (lvl.price > 0).then_some((i, lvl.price, lvl.qty))
+    })
+}
+
+fn depth_qty(levels: &[OrderLevel], n: usize) -> u64 {
+    active(&levels[..n.min(levels.len())]).fold(0u64, |sum, (_, _, qty)| sum.saturating_add(qty))
+}
+
+fn fill_vwap(levels: impl Iterator<Item = (usize, u64, u64)>, qty_u: u64) -> Option<u64> {
+    if qty_u == 0 { return None; }
+    let (mut left, mut notional) = (qty_u as u128, 0u128);
+    for (_, price, qty) in levels {
+        let take = left.min(qty as u128);
+        notional += take * price as u128;
+        left -= take;
+        if left == 0 {
+            return Some((notional / qty_u as u128) as u64);
+        }
+    }
+    None
+}
+
+/// Levels per side shown by `OrderBook`'s `Debug` output.
+const DEBUG_LEVELS: usize = 5;
+
+/// Compact view: timestamp, seq and the top `DEBUG_LEVELS` non-empty levels per side
+/// as `price x qty` in the file's scales. The full arrays are 100 mostly-empty levels.
+impl fmt::Debug for OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let meta = self.meta();
+        let (price_scale, qty_scale) = meta.scales();
+        let side = |levels: &[OrderLevel]| -> Vec<String> {
+            active(levels)
+                .take(DEBUG_LEVELS)
+                .map(|(_, price, qty)| format!("{} x {}", format::scaled(price, price_scale), format::scaled(qty, qty_scale)))
+                .collect()
+        };
+        f.debug_struct("OrderBook")
+            .field("symbol", &meta.symbol())
+            .field("timestamp_ms", &unsafe { ptr::read_volatile(&self.timestamp_ms) })
+            .field("seq", &seq_load(&self.seq))
+            .field("heartbeat", &self.heartbeat())
+            .field("bids", &side(&self.bids))
+            .field("asks", &side(&self.asks))
+            .finish()
+    }
 }
 
 #[repr(C)]
@@ -61,20 +531,165 @@ pub struct TopOfBook {
     pub ask_price: u64,
     pub ask_qty: u64,
     pub timestamp_ms: u64,
+    /// Seqlock sequence (offset 40), bumped around every setter. Odd while a write is in progress.
+    pub seq: u64,
+    /// File metadata (offset 48).
+    pub meta: BookMeta,
+    /// Writer liveness counter (offset 96), see `heartbeat`.
+    pub heartbeat: u64,
+}
+
+/// Consistent point-in-time copy of a `TopOfBook`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TopOfBookSnapshot {
+    pub bid_price: u64,
+    pub bid_qty: u64,
+    pub ask_price: u64,
+    pub ask_qty: u64,
+    pub timestamp_ms: u64,
+    pub seq: u64,
 }
 
 impl TopOfBook {
+    /// Maps `path` as a `TopOfBook`, with the same creation and size rules as `OrderBook::mmap`.
     pub fn mmap(path: &Path) -> std::io::Result<(memmap2::MmapMut, &'static mut Self)> {
-        let file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
-        file.set_len(size_of::<Self>() as u64)?;
-        let mut mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+        let mut mmap = open_mapping(path, size_of::<Self>())?;
         let ptr = mmap.as_mut_ptr() as *mut Self;
         let ob_ref = unsafe { &mut *ptr };
         Ok((mmap, ob_ref))
     }
-    #[inline] pub fn set_bid(&mut self, p: u64, q: u64) { unsafe { ptr::write_volatile(&mut self.bid_price, p); ptr::write_volatile(&mut self.bid_qty, q);} }
-    #[inline] pub fn set_ask(&mut self, p: u64, q: u64) { unsafe { ptr::write_volatile(&mut self.ask_price, p); ptr::write_volatile(&mut self.ask_qty, q);} }
-    #[inline] pub fn set_ts(&mut self, ts: u64) { unsafe { ptr::write_volatile(&mut self.timestamp_ms, ts) } }
+
+    /// Maps an existing `TopOfBook` file read-only, as `OrderBook::mmap_readonly`.
+    pub fn mmap_readonly(path: &Path) -> std::io::Result<(memmap2::Mmap, &'static Self)> {
+        let mmap = open_mapping_readonly(path, size_of::<Self>())?;
+        let tob_ref = unsafe { &*(mmap.as_ptr() as *const Self) };
+        Ok((mmap, tob_ref))
+    }
+    #[inline] pub fn set_bid(&mut self, p: u64, q: u64) { seq_begin(&mut self.seq); unsafe { ptr::write_volatile(&mut self.bid_price, p); ptr::write_volatile(&mut self.bid_qty, q);} seq_end(&mut self.seq); }
+    #[inline] pub fn set_ask(&mut self, p: u64, q: u64) { seq_begin(&mut self.seq); unsafe { ptr::write_volatile(&mut self.ask_price, p); ptr::write_volatile(&mut self.ask_qty, q);} seq_end(&mut self.seq); }
+    #[inline] pub fn set_ts(&mut self, ts: u64) { seq_begin(&mut self.seq); unsafe { ptr::write_volatile(&mut self.timestamp_ms, ts) } seq_end(&mut self.seq); }
+    /// Stamps the quote `TS_DISABLED`, as `OrderBook::mark_disabled`.
+    #[inline] pub fn mark_disabled(&mut self) { self.set_ts(TS_DISABLED); }
+    /// True while the quote carries the `mark_disabled` stamp.
+    #[inline] pub fn is_disabled(&self) -> bool { unsafe { ptr::read_volatile(&self.timestamp_ms) == TS_DISABLED } }
+    #[inline] pub fn set_meta(&mut self, meta: BookMeta) { unsafe { ptr::write_volatile(&mut self.meta, meta) } }
+    #[inline] pub fn meta(&self) -> BookMeta { unsafe { ptr::read_volatile(&self.meta) } }
+    /// Liveness counter, as `OrderBook::heartbeat`.
+    #[inline] pub fn heartbeat(&self) -> u64 { seq_load(&self.heartbeat) }
+    #[inline] pub fn bump_heartbeat(&mut self) { seq_atomic(&mut self.heartbeat).fetch_add(1, Ordering::Release); }
+
+    /// Like `set_bid`/`set_ask`, but skips the write (and the seq bump) when the side
+    /// already holds these values. Returns whether anything was written.
+    #[inline] pub fn set_bid_if_changed(&mut self, p: u64, q: u64) -> bool {
+        let unchanged = unsafe { ptr::read_volatile(&self.bid_price) == p && ptr::read_volatile(&self.bid_qty) == q };
+        if !unchanged { self.set_bid(p, q); }
+        !unchanged
+    }
+    #[inline] pub fn set_ask_if_changed(&mut self, p: u64, q: u64) -> bool {
+        let unchanged = unsafe { ptr::read_volatile(&self.ask_price) == p && ptr::read_volatile(&self.ask_qty) == q };
+        if !unchanged { self.set_ask(p, q); }
+        !unchanged
+    }
+
+    /// Copies all fields under the seqlock, so bid and ask are never from different writes.
+    pub fn snapshot(&self) -> TopOfBookSnapshot {
+        let ((bid_price, bid_qty, ask_price, ask_qty, timestamp_ms), seq) = seq_read(&self.seq, || unsafe {
+            (ptr::read_volatile(&self.bid_price), ptr::read_volatile(&self.bid_qty),
+             ptr::read_volatile(&self.ask_price), ptr::read_volatile(&self.ask_qty),
+             ptr::read_volatile(&self.timestamp_ms))
+        });
+        TopOfBookSnapshot { bid_price, bid_qty, ask_price, ask_qty, timestamp_ms, seq }
+    }
+
+    /// True if any setter has run since `prev` was taken. Costs a single load, so pollers
+    /// can call this in a loop and only `snapshot()` when it returns true.
+    #[inline] pub fn changed_since(&self, prev: &TopOfBookSnapshot) -> bool { seq_load(&self.seq) != prev.seq }
+}
+
+impl TopOfBookSnapshot {
+    /// This quote as the `TopOfBook` `Display` line, using `meta` for the symbol and
+    /// scales. Lets a caller print exactly the snapshot it holds, where `Display` would
+    /// take a fresh one.
+    pub fn line(&self, meta: &BookMeta) -> String {
+        QuoteLine { meta, quote: self }.to_string()
+    }
+}
+
+/// One line from a consistent snapshot, e.g.
+/// `SOLUSD bid 145.850000 x 2.500000 / ask 145.900000 x 1.800000, spread 0.050000 (3.43 bps)`.
+/// An empty side renders as `-` and the spread is omitted unless both sides are quoted.
+impl fmt::Display for TopOfBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        QuoteLine { meta: &self.meta(), quote: &self.snapshot() }.fmt(f)
+    }
+}
+
+struct QuoteLine<'a> {
+    meta: &'a BookMeta,
+    quote: &'a TopOfBookSnapshot,
+}
+
+impl fmt::Display for QuoteLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (meta, s) = (self.meta, self.quote);
+        let (price_scale, qty_scale) = meta.scales();
+        let quote = |p: u64, q: u64| if p == 0 { "-".to_string() } else { format!("{} x {}", format::scaled(p, price_scale), format::scaled(q, qty_scale)) };
+        if !meta.symbol().is_empty() {
+            write!(f, "{} ", meta.symbol())?;
+        }
+        write!(f, "bid {} / ask {}", quote(s.bid_price, s.bid_qty), quote(s.ask_price, s.ask_qty))?;
+        if s.bid_price > 0 && s.ask_price > 0 {
+            let spread = s.ask_price as i128 - s.bid_price as i128;
+            let mid = (s.ask_price as f64 + s.bid_price as f64) / 2.0;
+            let sign = if spread < 0 { "-" } else { "" };
+            write!(f, ", spread {}{} ({} bps)", sign, format::scaled(spread.unsigned_abs() as u64, price_scale), format::bps(spread as f64 / mid * 10_000.0, 2))?;
+        }
+        Ok(())
+    }
+}
+
+/// Side of an order or trade. Venues spell it `buy`/`sell` or, for book sides and
+/// Gemini's maker side, `bid`/`ask`; all parse case-insensitively, with `bid` as
+/// `Buy`. The string form stored and published is `buy`/`sell`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+
+    /// The other side, e.g. a trade's taker side from its maker side.
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Side {
+    type Err = TradeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "buy" | "bid" => Ok(Side::Buy),
+            "sell" | "ask" => Ok(Side::Sell),
+            _ => Err(TradeError::BadSide(s.to_string())),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -83,5 +698,512 @@ pub struct TradeEvent {
     pub symbol: String,
     pub price_u: u64,
     pub qty_u: u64,
-    pub side: String, // buy/sell from taker perspective
+    /// Taker side; `None` when the feed didn't say and it couldn't be inferred.
+    pub side: Option<Side>,
+    /// `side` was inferred from the prevailing mid because the feed didn't provide one.
+    pub side_inferred: bool,
+    /// Venue trade id (Gemini `tid`), if the feed provided one.
+    pub trade_id: Option<u64>,
+    /// Best bid/ask prevailing when the trade arrived; `None` if that side had no quote yet.
+    pub bid_at: Option<u64>,
+    pub ask_at: Option<u64>,
+    /// Exact text of the feed frame the trade arrived in, when raw capture is enabled.
+    pub raw: Option<String>,
+    /// Feed data may be missing right before this trade (a sequence gap or reconnect
+    /// since the previous published trade), so windows spanning it are incomplete.
+    pub gap_before: bool,
+}
+
+impl TradeEvent {
+    pub fn builder(symbol: impl Into<String>) -> TradeEventBuilder {
+        TradeEventBuilder {
+            ev: TradeEvent {
+                ts_ms: 0, symbol: symbol.into(), price_u: 0, qty_u: 0, side: None,
+                side_inferred: false, trade_id: None, bid_at: None, ask_at: None, raw: None, gap_before: false,
+            },
+        }
+    }
+}
+
+/// Builds a `TradeEvent`, validating it in `build` so invalid trades never reach the bus.
+pub struct TradeEventBuilder {
+    ev: TradeEvent,
+}
+
+impl TradeEventBuilder {
+    pub fn ts_ms(mut self, ts_ms: u64) -> Self { self.ev.ts_ms = ts_ms; self }
+    pub fn price_u(mut self, price_u: u64) -> Self { self.ev.price_u = price_u; self }
+    pub fn qty_u(mut self, qty_u: u64) -> Self { self.ev.qty_u = qty_u; self }
+    pub fn side(mut self, side: Option<Side>, inferred: bool) -> Self { self.ev.side = side; self.ev.side_inferred = inferred; self }
+    pub fn trade_id(mut self, trade_id: Option<u64>) -> Self { self.ev.trade_id = trade_id; self }
+    pub fn quote(mut self, bid_at: Option<u64>, ask_at: Option<u64>) -> Self { self.ev.bid_at = bid_at; self.ev.ask_at = ask_at; self }
+    pub fn raw(mut self, raw: Option<String>) -> Self { self.ev.raw = raw; self }
+    pub fn gap_before(mut self, gap_before: bool) -> Self { self.ev.gap_before = gap_before; self }
+
+    pub fn build(self) -> Result<TradeEvent, TradeError> {
+        let ev = self.ev;
+        let storable = |v: u64| v > 0 && v <= i64::MAX as u64;
+        if ev.symbol.trim().is_empty() { return Err(TradeError::EmptySymbol); }
+        if !storable(ev.price_u) { return Err(TradeError::BadPrice(ev.price_u)); }
+        if !storable(ev.qty_u) { return Err(TradeError::BadQty(ev.qty_u)); }
+        Ok(ev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Book with the given `(price, qty)` levels per side; `(0, 0)` leaves a level empty.
+    fn book(bids: &[(u64, u64)], asks: &[(u64, u64)]) -> OrderBook {
+        let levels = |side: &[(u64, u64)]| side.iter().map(|&(price, qty)| OrderLevel { price, qty }).collect::<Vec<_>>();
+        let mut book = OrderBook::default();
+        book.publish(&levels(bids), &levels(asks), 1);
+        book
+    }
+
+    #[test]
+    fn side_parses_venue_spellings() {
+        for (s, side) in [("buy", Side::Buy), ("bid", Side::Buy), ("BUY", Side::Buy), (" Bid ", Side::Buy), ("sell", Side::Sell), ("ask", Side::Sell), ("Ask", Side::Sell)] {
+            assert_eq!(s.parse::<Side>(), Ok(side), "{:?}", s);
+        }
+        for s in ["", "b", "offer", "buyer"] {
+            assert_eq!(s.parse::<Side>(), Err(TradeError::BadSide(s.to_string())));
+        }
+    }
+
+    #[test]
+    fn side_round_trips_and_flips() {
+        for side in [Side::Buy, Side::Sell] {
+            assert_eq!(side.to_string().parse::<Side>(), Ok(side));
+            assert_eq!(side.opposite().opposite(), side);
+        }
+        assert_eq!(Side::Buy.opposite(), Side::Sell);
+        assert_eq!(Side::Sell.as_str(), "sell");
+    }
+
+    #[test]
+    fn best_levels_mid_and_spread() {
+        let empty = book(&[], &[]);
+        assert!(empty.best_bid().is_none() && empty.best_ask().is_none());
+        assert_eq!((empty.mid_price_u(), empty.spread_u(), empty.spread_bps()), (None, None, None));
+
+        let bids_only = book(&[(100, 5)], &[]);
+        assert_eq!(bids_only.best_bid().map(|l| (l.price, l.qty)), Some((100, 5)));
+        assert!(bids_only.best_ask().is_none());
+        assert_eq!((bids_only.mid_price_u(), bids_only.spread_u(), bids_only.spread_bps()), (None, None, None));
+
+        // Level 0 cleared by an update: the best is the first non-empty level
+        let normal = book(&[(0, 0), (100, 5), (99, 1)], &[(103, 2), (104, 9)]);
+        assert_eq!(normal.best_bid().map(|l| l.price), Some(100));
+        assert_eq!(normal.best_ask().map(|l| l.price), Some(103));
+        assert_eq!(normal.mid_price_u(), Some(101), "rounded down");
+        assert_eq!(normal.spread_u(), Some(3));
+        assert_eq!(normal.spread_bps(), Some(3.0 / 101.0 * 10_000.0));
+
+        assert_eq!(book(&[(u64::MAX, 1)], &[(u64::MAX, 1)]).mid_price_u(), Some(u64::MAX));
+        assert_eq!(book(&[(105, 1)], &[(103, 1)]).spread_u(), Some(0), "crossed saturates to zero");
+    }
+
+    #[test]
+    fn one_sided_snapshot_has_no_spread() {
+        // Halted: the bids are pulled and the asks stay, with the best ask below level 0
+        let snap = book(&[], &[(0, 0), (103, 2)]).snapshot();
+        assert_eq!(snap.status(), BookStatus::OneSided);
+        assert_eq!(snap.best_ask().map(|l| l.price), Some(103));
+        assert_eq!((snap.mid_price_u(), snap.spread_u(), snap.spread_bps()), (None, None, None));
+
+        let snap = book(&[(0, 0), (99, 5)], &[(0, 0), (101, 2)]).snapshot();
+        assert_eq!(snap.status(), BookStatus::TwoSided);
+        assert_eq!(snap.spread_bps(), Some(200.0), "measured from the best levels, not level 0");
+    }
+
+    #[test]
+    fn fill_vwap_walks_levels() {
+        let b = book(&[(99, 4), (98, 6)], &[(101, 2), (102, 3), (104, 5)]);
+        assert_eq!(b.vwap_buy(2), Some(101), "one level");
+        assert_eq!(b.vwap_buy(1), Some(101), "part of one level");
+        // 2@101 + 3@102 + 1@104 = 612 over 6
+        assert_eq!(b.vwap_buy(6), Some(102));
+        // 2@101 + 3@102 + 5@104 = 1028 over 10, rounded down
+        assert_eq!(b.vwap_buy(10), Some(102));
+        assert_eq!(b.vwap_buy(11), None, "more than the asks hold");
+        assert_eq!(b.vwap_buy(0), None);
+        // 4@99 + 1@98 = 494 over 5
+        assert_eq!(b.vwap_sell(5), Some(98));
+        assert_eq!(book(&[], &[]).vwap_sell(1), None);
+    }
+
+    #[test]
+    fn imbalance_over_top_levels() {
+        assert_eq!(book(&[(100, 5), (99, 5)], &[(101, 5), (102, 5)]).imbalance(2), Some(0.0), "balanced");
+        let bid_heavy = book(&[(100, 30), (99, 10)], &[(101, 10), (102, 10)]);
+        assert_eq!(bid_heavy.imbalance(1), Some(0.5));
+        assert_eq!(bid_heavy.imbalance(2), Some(1.0 / 3.0));
+        assert_eq!((bid_heavy.total_bid_qty(BOOK_DEPTH + 10), bid_heavy.total_ask_qty(1)), (40, 10));
+        assert_eq!(book(&[], &[(101, 3)]).imbalance(5), Some(-1.0), "all ask");
+        assert_eq!(book(&[], &[]).imbalance(5), None, "empty");
+        assert_eq!(bid_heavy.imbalance(0), None);
+    }
+
+    #[test]
+    fn crossed_and_locked() {
+        let normal = book(&[(100, 1)], &[(101, 1)]);
+        assert!(!normal.is_crossed() && !normal.is_locked());
+        let locked = book(&[(100, 1)], &[(100, 1)]);
+        assert!(!locked.is_crossed() && locked.is_locked());
+        let crossed = book(&[(0, 0), (102, 1)], &[(101, 1)]);
+        assert!(crossed.is_crossed() && !crossed.is_locked(), "judged from the first non-empty levels");
+        for one_sided in [book(&[(100, 1)], &[]), book(&[], &[(100, 1)]), book(&[], &[])] {
+            assert!(!one_sided.is_crossed() && !one_sided.is_locked());
+        }
+    }
+
+    /// Fresh file path for a mapping shared between threads, removed when dropped.
+    struct TempPath(std::path::PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("seqlock-test-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) { let _ = std::fs::remove_file(&self.0); }
+    }
+
+    #[test]
+    fn snapshot_line_prints_the_held_quote() {
+        let mut tob = TopOfBook::default();
+        tob.set_meta(BookMeta::new("SOLUSD", 1_000_000, 1_000_000, 0, 1));
+        tob.set_bid(145_850_000, 2_500_000);
+        tob.set_ask(145_900_000, 1_800_000);
+        let held = tob.snapshot();
+        let line = "SOLUSD bid 145.850000 x 2.500000 / ask 145.900000 x 1.800000, spread 0.050000 (3.43 bps)";
+        assert_eq!(held.line(&tob.meta()), line);
+        assert_eq!(tob.to_string(), line);
+        // A later write changes `Display`, not the held snapshot's line
+        tob.set_ask(146_000_000, 1_000_000);
+        assert_eq!(held.line(&tob.meta()), line);
+        assert_ne!(tob.to_string(), line);
+    }
+
+    #[test]
+    fn level_layout_follows_features() {
+        use std::mem::{align_of, offset_of};
+        let (level, asks, book) = match (cfg!(feature = "padded-levels"), cfg!(feature = "split-sides")) {
+            (true, _) => (64, 3200, 6528),
+            (false, true) => (16, 832, 1704),
+            (false, false) => (16, 800, 1672),
+        };
+        assert_eq!((size_of::<OrderLevel>(), align_of::<OrderLevel>()), (level, if level == 64 { 64 } else { 8 }));
+        assert_eq!(offset_of!(OrderBook, asks), asks);
+        assert_eq!(size_of::<OrderBook>(), book);
+
+        let tmp = TempPath::new("layout");
+        let (_map, writer) = OrderBook::mmap(&tmp.0).unwrap();
+        writer.publish(&[OrderLevel { price: 99, qty: 1 }], &[OrderLevel { price: 101, qty: 2 }], 7);
+        writer.update_bid(BOOK_DEPTH - 1, 50, 3);
+        let (_reader_map, reader) = OrderBook::mmap_readonly(&tmp.0).unwrap();
+        let book = reader.snapshot();
+        assert_eq!((book.bids[0].price, book.asks[0].price, book.asks[0].qty), (99, 101, 2));
+        assert_eq!((book.bids[BOOK_DEPTH - 1].price, book.timestamp_ms), (50, 7));
+    }
+
+    #[test]
+    fn order_book_snapshots_are_never_torn() {
+        const WRITES: u64 = 20_000;
+        let tmp = TempPath::new("book");
+        let (map, writer) = OrderBook::mmap(&tmp.0).unwrap();
+        let (_reader_map, reader) = OrderBook::mmap_readonly(&tmp.0).unwrap();
+        let handle = std::thread::spawn(move || {
+            let _map = map;
+            for n in 1..=WRITES {
+                writer.publish(&[OrderLevel { price: n, qty: n }; BOOK_DEPTH], &[OrderLevel { price: n + 1, qty: n }; BOOK_DEPTH], n);
+            }
+        });
+        let mut last = 0;
+        while last < WRITES {
+            let book = reader.snapshot();
+            let n = book.timestamp_ms;
+            assert!(n >= last, "snapshot went backwards: {} after {}", n, last);
+            if n > 0 {
+                assert!(book.bids.iter().all(|l| l.price == n && l.qty == n), "torn bids at write {}", n);
+                assert!(book.asks.iter().all(|l| l.price == n + 1 && l.qty == n), "torn asks at write {}", n);
+            }
+            last = n;
+        }
+        handle.join().unwrap();
+        assert_eq!(reader.load_seq() % 2, 0, "no write left open");
+    }
+
+    #[test]
+    fn top_of_book_snapshots_are_never_torn() {
+        const WRITES: u64 = 20_000;
+        let tmp = TempPath::new("tob");
+        let (map, writer) = TopOfBook::mmap(&tmp.0).unwrap();
+        let (_reader_map, reader) = TopOfBook::mmap_readonly(&tmp.0).unwrap();
+        let handle = std::thread::spawn(move || {
+            let _map = map;
+            for n in 1..=WRITES {
+                writer.set_bid(n, n);
+                writer.set_ask(n + 1, n);
+            }
+        });
+        let mut prev = reader.snapshot();
+        while prev.ask_price < WRITES + 1 {
+            if !reader.changed_since(&prev) {
+                std::hint::spin_loop();
+                continue;
+            }
+            let snap = reader.snapshot();
+            assert!(snap.seq > prev.seq);
+            assert_eq!(snap.bid_price, snap.bid_qty, "torn bid");
+            assert!(snap.ask_price == 0 || snap.ask_price == snap.ask_qty + 1, "torn ask");
+            assert!(snap.bid_price >= prev.bid_price && snap.ask_price >= prev.ask_price, "prices went backwards");
+            prev = snap;
+        }
+        handle.join().unwrap();
+        assert!(!reader.changed_since(&reader.snapshot()));
+    }
+
+    #[test]
+    fn checked_updates_reject_out_of_range_levels() {
+        let mut book = OrderBook::default();
+        assert_eq!(book.try_update_bid(0, 100, 5), Ok(()));
+        assert_eq!(book.try_update_ask(BOOK_DEPTH - 1, 110, 7), Ok(()));
+        assert_eq!((book.bids[0].price, book.bids[0].qty), (100, 5));
+        assert_eq!((book.asks[BOOK_DEPTH - 1].price, book.asks[BOOK_DEPTH - 1].qty), (110, 7));
+
+        let out_of_range = Err(BookError::IndexOutOfRange { index: BOOK_DEPTH, depth: BOOK_DEPTH });
+        assert_eq!(book.try_update_bid(BOOK_DEPTH, 1, 1), out_of_range);
+        assert_eq!(book.try_update_ask(BOOK_DEPTH, 1, 1), out_of_range);
+        assert_eq!(book.active_levels(), (1, 1), "nothing else written");
+    }
+
+    #[test]
+    fn restarted_writer_resumes_the_saved_book() {
+        let tmp = TempPath::new("resume");
+        {
+            let (_map, writer) = OrderBook::mmap(&tmp.0).unwrap();
+            writer.set_meta(BookMeta::new("SOLUSD", 1_000_000, 1_000_000, 10_000, 3));
+            writer.publish(&[OrderLevel { price: 145_850_000, qty: 2 }, OrderLevel { price: 145_840_000, qty: 5 }],
+                           &[OrderLevel { price: 145_900_000, qty: 1 }], 42);
+        } // mapping dropped, as when the process exits
+
+        let (_map, resumed) = OrderBook::mmap(&tmp.0).unwrap();
+        assert_eq!(resumed.active_levels(), (2, 1));
+        let book = resumed.snapshot();
+        assert_eq!(book.bids_iter().collect::<Vec<_>>(), vec![(0, 145_850_000, 2), (1, 145_840_000, 5)]);
+        assert_eq!(book.asks_iter().collect::<Vec<_>>(), vec![(0, 145_900_000, 1)]);
+        assert_eq!((book.timestamp_ms, book.meta().symbol()), (42, "SOLUSD"));
+    }
+
+    #[test]
+    fn flushed_book_is_on_disk_before_unmapping() {
+        use std::mem::offset_of;
+        let tmp = TempPath::new("flush");
+        let (map, writer) = OrderBook::mmap(&tmp.0).unwrap();
+        writer.publish(&[OrderLevel { price: 145_850_000, qty: 2 }], &[OrderLevel { price: 145_900_000, qty: 1 }], 42);
+        map.flush().unwrap();
+
+        // Read the file itself while the mapping is still open
+        let bytes = std::fs::read(&tmp.0).unwrap();
+        let word = |offset: usize| u64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        assert_eq!(word(offset_of!(OrderBook, bids)), 145_850_000);
+        assert_eq!(word(offset_of!(OrderBook, asks)), 145_900_000);
+        assert_eq!(word(offset_of!(OrderBook, timestamp_ms)), 42);
+        drop(map);
+
+        let (_map, reopened) = OrderBook::mmap_readonly(&tmp.0).unwrap();
+        let book = reopened.snapshot();
+        assert_eq!((book.best_bid().map(|l| l.price), book.best_ask().map(|l| l.price), book.timestamp_ms), (Some(145_850_000), Some(145_900_000), 42));
+    }
+
+    #[test]
+    fn book_meta_round_trips_a_short_symbol() {
+        let meta = BookMeta::new("SOL", 100_000_000, 1_000, 5, 20);
+        assert_eq!(&meta.symbol[..4], b"SOL\0");
+        assert_eq!(meta.symbol(), "SOL", "padding trimmed");
+
+        let tmp = TempPath::new("meta");
+        let (_map, writer) = TopOfBook::mmap(&tmp.0).unwrap();
+        writer.set_meta(meta);
+        let (_reader_map, reader) = TopOfBook::mmap_readonly(&tmp.0).unwrap();
+        let read = reader.meta();
+        assert_eq!(read, meta);
+        assert_eq!((read.symbol(), read.scales(), read.tick_size, read.depth), ("SOL", (100_000_000, 1_000), 5, 20));
+
+        assert_eq!(BookMeta::new("ABCDEFGHIJKLMNOPQR", 1, 1, 0, 1).symbol(), "ABCDEFGHIJKLMNOP", "truncated to SYMBOL_LEN");
+        assert_eq!(BookMeta::default().scales(), (1_000_000, 1_000_000), "unpopulated falls back to micro units");
+    }
+
+    #[test]
+    fn mismatched_file_sizes_are_refused() {
+        let tmp = TempPath::new("size");
+        let book_len = size_of::<OrderBook>();
+        let tob_len = size_of::<TopOfBook>();
+
+        // Read-only openers never create a missing file
+        assert_eq!(OrderBook::mmap_readonly(&tmp.0).err().map(|e| e.kind()), Some(std::io::ErrorKind::NotFound));
+        assert_eq!(TopOfBook::mmap_readonly(&tmp.0).err().map(|e| e.kind()), Some(std::io::ErrorKind::NotFound));
+        assert!(!tmp.0.exists());
+
+        for len in [0, tob_len, book_len - 8, book_len + 8] {
+            std::fs::write(&tmp.0, vec![0u8; len]).unwrap();
+            let err = OrderBook::mmap_readonly(&tmp.0).err().unwrap_or_else(|| panic!("{}-byte book file mapped", len));
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{} bytes", len);
+        }
+        for len in [0, tob_len - 8, book_len] {
+            std::fs::write(&tmp.0, vec![0u8; len]).unwrap();
+            let err = TopOfBook::mmap_readonly(&tmp.0).err().unwrap_or_else(|| panic!("{}-byte quote file mapped", len));
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{} bytes", len);
+        }
+
+        // The writer refuses a wrong-sized file rather than resizing it, but sizes an empty one
+        std::fs::write(&tmp.0, vec![0u8; tob_len]).unwrap();
+        assert_eq!(OrderBook::mmap(&tmp.0).err().map(|e| e.kind()), Some(std::io::ErrorKind::InvalidData));
+        assert_eq!(std::fs::metadata(&tmp.0).unwrap().len(), tob_len as u64, "left as it was");
+        std::fs::write(&tmp.0, b"").unwrap();
+        assert!(OrderBook::mmap(&tmp.0).is_ok());
+        assert_eq!(std::fs::metadata(&tmp.0).unwrap().len(), book_len as u64);
+    }
+
+    #[test]
+    fn repeated_quote_is_not_rewritten() {
+        let mut tob = TopOfBook::default();
+        tob.set_ts(10);
+        assert!(tob.set_bid_if_changed(145_850_000, 2_500_000));
+        assert!(tob.set_ask_if_changed(145_900_000, 1_800_000));
+        let before = tob.snapshot();
+
+        assert!(!tob.set_bid_if_changed(145_850_000, 2_500_000), "duplicate bid");
+        assert!(!tob.set_ask_if_changed(145_900_000, 1_800_000), "duplicate ask");
+        assert!(!tob.changed_since(&before), "no seq bump");
+        assert_eq!(tob.snapshot(), before, "timestamp and quote untouched");
+
+        assert!(tob.set_bid_if_changed(145_850_000, 3_000_000), "same price, new size");
+        assert!(tob.changed_since(&before));
+    }
+
+    #[test]
+    fn a_batch_touching_both_sides_lands_at_once() {
+        const BATCHES: u64 = 20_000;
+        let tmp = TempPath::new("batch");
+        let (map, writer) = OrderBook::mmap(&tmp.0).unwrap();
+        let (_reader_map, reader) = OrderBook::mmap_readonly(&tmp.0).unwrap();
+        let handle = std::thread::spawn(move || {
+            let _map = map;
+            let mut bids = vec![OrderLevel { price: 100, qty: 1 }; BOOK_DEPTH];
+            let mut asks = vec![OrderLevel { price: 200, qty: 1 }; BOOK_DEPTH];
+            for n in 1..=BATCHES {
+                // One frame: the best bid's size and the deepest ask's size change together
+                bids[0].qty = n;
+                asks[BOOK_DEPTH - 1].qty = n;
+                writer.publish(&bids, &asks, n);
+            }
+        });
+        let mut seen = 0;
+        while seen < BATCHES {
+            let book = reader.snapshot();
+            if book.timestamp_ms == 0 { continue; }
+            let (bid, ask) = (book.bids[0].qty, book.asks[BOOK_DEPTH - 1].qty);
+            assert_eq!(bid, ask, "one side of batch {} without the other", book.timestamp_ms);
+            assert_eq!(bid, book.timestamp_ms);
+            seen = book.timestamp_ms;
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn configured_depth_must_fit_the_file() {
+        let book = OrderBook::default();
+        assert_eq!(book.capacity(), BOOK_DEPTH);
+        for depth in [1, 10, BOOK_DEPTH] {
+            assert_eq!(book.check_depth(depth), Ok(depth));
+        }
+        for depth in [0, BOOK_DEPTH + 1, 1_000] {
+            assert_eq!(book.check_depth(depth), Err(BookError::DepthExceedsCapacity { depth, capacity: BOOK_DEPTH }));
+        }
+    }
+
+    #[test]
+    fn level_iterators_skip_empty_levels() {
+        let mut b = book(&[(100, 5), (99, 1), (0, 0), (97, 4)], &[(101, 2)]);
+        assert_eq!(b.bids_iter().collect::<Vec<_>>(), vec![(0, 100, 5), (1, 99, 1), (3, 97, 4)]);
+        assert_eq!(b.asks_iter().collect::<Vec<_>>(), vec![(0, 101, 2)]);
+
+        // Level 0 deleted by an incremental update
+        b.update_bid(0, 0, 0);
+        b.update_ask(0, 0, 0);
+        assert_eq!(b.bids_iter().collect::<Vec<_>>(), vec![(1, 99, 1), (3, 97, 4)]);
+        assert_eq!(b.asks_iter().count(), 0);
+        assert_eq!(b.active_levels(), (2, 0));
+    }
+
+    #[test]
+    fn rows_use_the_file_scales() {
+        let mut b = book(&[(14_585, 250), (0, 0), (14_580, 1_000)], &[(14_590, 75)]);
+        b.set_meta(BookMeta::new("SOLUSD", 100, 100, 1, 3));
+        assert_eq!(b.to_rows(), vec![
+            ("bid", 1, 145.85, 2.5),
+            ("bid", 3, 145.80, 10.0),
+            ("ask", 1, 145.90, 0.75),
+        ]);
+        assert_eq!(book(&[(2_500_000, 1_000_000)], &[]).to_rows(), vec![("bid", 1, 2.5, 1.0)], "micro units without metadata");
+        assert!(book(&[], &[]).to_rows().is_empty());
+    }
+
+    #[test]
+    fn debug_shows_the_top_levels_only() {
+        let bids: Vec<(u64, u64)> = (0..7).map(|i| (14_585 - i, 100)).collect();
+        let mut b = book(&bids, &[(0, 0), (14_590, 75)]);
+        b.set_meta(BookMeta::new("SOLUSD", 100, 100, 1, 10));
+        b.bump_heartbeat();
+        let seq = b.load_seq();
+        assert_eq!(format!("{:?}", b), format!(
+            "OrderBook {{ symbol: \"SOLUSD\", timestamp_ms: 1, seq: {}, heartbeat: 1, \
+             bids: [\"145.85 x 1.00\", \"145.84 x 1.00\", \"145.83 x 1.00\", \"145.82 x 1.00\", \"145.81 x 1.00\"], \
+             asks: [\"145.90 x 0.75\"] }}", seq));
+    }
+
+    fn valid_trade() -> TradeEventBuilder {
+        TradeEvent::builder("SOLUSD").ts_ms(1).price_u(145_850_000).qty_u(2_500_000)
+    }
+
+    #[test]
+    fn builder_accepts_a_valid_trade() {
+        let tr = valid_trade().side(Some(Side::Buy), false).trade_id(Some(7)).build().unwrap();
+        assert_eq!((tr.symbol.as_str(), tr.price_u, tr.qty_u, tr.side, tr.trade_id), ("SOLUSD", 145_850_000, 2_500_000, Some(Side::Buy), Some(7)));
+        assert!(valid_trade().price_u(i64::MAX as u64).qty_u(i64::MAX as u64).build().is_ok(), "largest storable values");
+    }
+
+    #[test]
+    fn builder_rejects_an_empty_symbol() {
+        for symbol in ["", "   "] {
+            assert_eq!(TradeEvent::builder(symbol).price_u(1).qty_u(1).build().unwrap_err(), TradeError::EmptySymbol);
+        }
+    }
+
+    #[test]
+    fn builder_rejects_a_bad_price() {
+        assert_eq!(valid_trade().price_u(0).build().unwrap_err(), TradeError::BadPrice(0));
+        let too_big = i64::MAX as u64 + 1;
+        assert_eq!(valid_trade().price_u(too_big).build().unwrap_err(), TradeError::BadPrice(too_big));
+    }
+
+    #[test]
+    fn builder_rejects_a_bad_qty() {
+        assert_eq!(valid_trade().qty_u(0).build().unwrap_err(), TradeError::BadQty(0));
+        assert_eq!(valid_trade().qty_u(u64::MAX).build().unwrap_err(), TradeError::BadQty(u64::MAX));
+    }
+
+    #[test]
+    fn bad_side_is_reported_by_parse() {
+        // `BadSide` comes from parsing a venue side, before the builder sees it
+        assert_eq!("short".parse::<Side>(), Err(TradeError::BadSide("short".to_string())));
+        assert_eq!(TradeError::BadSide("short".to_string()).to_string(), "invalid side 'short'");
+    }
 }
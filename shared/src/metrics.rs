@@ -0,0 +1,428 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::errors;
+
+/// What a scrape says about the process: `build_info` carries `build_version` and the
+/// configured `symbols`, and `symbol`, set for a single-symbol process, labels every
+/// per-symbol series.
+#[derive(Debug, Clone, Copy)]
+pub struct Instance {
+    pub build_version: &'static str,
+    pub symbols: &'static str,
+    pub symbol: Option<&'static str>,
+}
+
+/// Exposition format, picked per scrape from its `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Prometheus text format 0.0.4, the default.
+    Text,
+    /// OpenMetrics 1.0, which also carries exemplars on the trade counters.
+    OpenMetrics,
+}
+
+impl Format {
+    /// OpenMetrics when the request's `Accept` header lists it, else text.
+    fn negotiate(request: &str) -> Self {
+        let accepts = request.lines().filter_map(|l| l.split_once(':')).any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("accept") && value.contains("application/openmetrics-text")
+        });
+        if accepts { Format::OpenMetrics } else { Format::Text }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Text => "text/plain; version=0.0.4",
+            Format::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        }
+    }
+}
+
+/// The latest trade counted by `record_trade`, carried as the trade counters' exemplar.
+#[derive(Debug, Clone, Copy)]
+struct LastTrade {
+    trade_id: Option<u64>,
+    ts_ms: u64,
+    qty_micro: u64,
+}
+
+static TRADES: AtomicU64 = AtomicU64::new(0);
+static VOLUME_MICRO: AtomicU64 = AtomicU64::new(0);
+static LAST_TRADE: Mutex<Option<LastTrade>> = Mutex::new(None);
+
+/// Counts one published trade of `qty_micro` base micro-units. The trade counters only
+/// appear in scrapes once a trade was counted.
+pub fn record_trade(trade_id: Option<u64>, ts_ms: u64, qty_micro: u64) {
+    TRADES.fetch_add(1, Ordering::Relaxed);
+    VOLUME_MICRO.fetch_add(qty_micro, Ordering::Relaxed);
+    *LAST_TRADE.lock().unwrap_or_else(|e| e.into_inner()) = Some(LastTrade { trade_id, ts_ms, qty_micro });
+}
+
+/// Finite bucket bounds: `le` = 2^i microseconds for i in `0..BOUNDS`, so 1 us up to ~33.5 s.
+const BOUNDS: usize = 26;
+
+/// Cumulative-since-start latency histogram exported as `<name>_bucket`/`_sum`/`_count`.
+pub struct Histogram {
+    /// Per-bucket counts, the last one past every finite bound (`le="+Inf"`).
+    buckets: [AtomicU64; BOUNDS + 1],
+    sum_us: AtomicU64,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self { buckets: [const { AtomicU64::new(0) }; BOUNDS + 1], sum_us: AtomicU64::new(0) }
+    }
+
+    /// Counts `d` in the first bucket whose bound is at least `d`, in whole microseconds.
+    pub fn observe(&self, d: Duration) {
+        let us = d.as_micros().min(u64::MAX as u128) as u64;
+        let idx = if us <= 1 { 0 } else { ((u64::BITS - (us - 1).leading_zeros()) as usize).min(BOUNDS) };
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+    }
+
+    fn count(&self) -> u64 {
+        self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).sum()
+    }
+
+    /// The bucket, sum and count samples, each labelled with `labels` (`a="b",c="d"`).
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let (bucket_labels, labels) = if labels.is_empty() { (String::new(), String::new()) } else { (format!("{},", labels), format!("{{{}}}", labels)) };
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = if i < BOUNDS { ((1u64 << i) as f64 / 1e6).to_string() } else { "+Inf".to_string() };
+            out.push_str(&format!("{}_bucket{{{}le=\"{}\"}} {}\n", name, bucket_labels, le, cumulative));
+        }
+        out.push_str(&format!("{}_sum{} {}\n", name, labels, self.sum_us.load(Ordering::Relaxed) as f64 / 1e6));
+        out.push_str(&format!("{}_count{} {}\n", name, labels, cumulative));
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self { Self::new() }
+}
+
+/// Which Gemini feed a sample came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feed {
+    V1,
+    V2,
+}
+
+impl Feed {
+    pub const ALL: [Feed; 2] = [Feed::V1, Feed::V2];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Feed::V1 => "v1",
+            Feed::V2 => "v2",
+        }
+    }
+}
+
+static PUBLISH_LATENCY: [Histogram; 2] = [const { Histogram::new() }; 2];
+
+/// Records the time from receiving a `feed` frame to its completed mmap publish.
+pub fn observe_publish_latency(feed: Feed, d: Duration) {
+    PUBLISH_LATENCY[feed as usize].observe(d);
+}
+
+static BOOK_UPDATE_GAP: Histogram = Histogram::new();
+
+/// Records the gap between two consecutive book updates, from their receive times.
+pub fn observe_book_update_gap(d: Duration) {
+    BOOK_UPDATE_GAP.observe(d);
+}
+
+/// Latest `analytics::book_quality_score`; `u64::MAX` until the first one is set.
+static BOOK_QUALITY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Publishes the latest 0-100 book quality score as the `book_quality_score` gauge.
+pub fn set_book_quality(score: u8) {
+    BOOK_QUALITY.store(score as u64, Ordering::Relaxed);
+}
+
+/// Symbols subscribed to and whether each has delivered data since, for `/ready`.
+static AWAITED: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+
+/// Marks `symbol` as subscribed and still without data, so `/ready` reports not ready
+/// until `data_arrived(symbol)`. Call it on each (re)subscribe.
+pub fn expect_data(symbol: &str) {
+    let mut awaited = AWAITED.lock().unwrap_or_else(|e| e.into_inner());
+    match awaited.iter_mut().find(|(s, _)| s == symbol) {
+        Some(entry) => entry.1 = false,
+        None => awaited.push((symbol.to_string(), false)),
+    }
+}
+
+/// Records the first data for `symbol` since it was last subscribed.
+pub fn data_arrived(symbol: &str) {
+    let mut awaited = AWAITED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(entry) = awaited.iter_mut().find(|(s, _)| s == symbol) {
+        entry.1 = true;
+    }
+}
+
+/// Subscribed symbols that have not delivered data yet; empty means ready.
+pub fn missing_data() -> Vec<String> {
+    AWAITED.lock().unwrap_or_else(|e| e.into_inner()).iter().filter(|(_, seen)| !seen).map(|(s, _)| s.clone()).collect()
+}
+
+/// Writes the `# HELP`/`# TYPE` lines for `name`. OpenMetrics names a counter family
+/// without its `_total` suffix.
+fn family(out: &mut String, format: Format, name: &str, kind: &str, help: &str) {
+    let name = match (format, kind) {
+        (Format::OpenMetrics, "counter") => name.strip_suffix("_total").unwrap_or(name),
+        _ => name,
+    };
+    out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+}
+
+/// ` # {trade_id="..."} value timestamp` for OpenMetrics, nothing for text.
+fn exemplar(format: Format, trade: &LastTrade, value: &str) -> String {
+    if format != Format::OpenMetrics { return String::new(); }
+    let labels = trade.trade_id.map(|id| format!("trade_id=\"{}\"", id)).unwrap_or_default();
+    format!(" # {{{}}} {} {}.{:03}", labels, value, trade.ts_ms / 1000, trade.ts_ms % 1000)
+}
+
+/// `build_info`, `errors_total` per category and, once they have samples, the trade
+/// counters, book quality gauge and latency histograms in `format`.
+pub fn render(instance: &Instance, format: Format) -> String {
+    let symbol_label = instance.symbol.map(|s| format!(",symbol=\"{}\"", escape(s))).unwrap_or_default();
+    let only_symbol = instance.symbol.map(|s| format!("{{symbol=\"{}\"}}", escape(s))).unwrap_or_default();
+    let mut out = String::new();
+    family(&mut out, format, "build_info", "gauge", "Build and configuration of this process; always 1.");
+    out.push_str(&format!("build_info{{build_version=\"{}\",symbols=\"{}\"}} 1\n",
+        escape(instance.build_version), escape(instance.symbols)));
+    family(&mut out, format, "errors_total", "counter", "Errors counted per failure category.");
+    for (category, total) in errors::totals() {
+        out.push_str(&format!("errors_total{{category=\"{}\"{}}} {}\n", category, symbol_label, total));
+    }
+    if let Some(trade) = *LAST_TRADE.lock().unwrap_or_else(|e| e.into_inner()) {
+        family(&mut out, format, "trades_total", "counter", "Trades published; the exemplar is the latest one.");
+        out.push_str(&format!("trades_total{} {}{}\n", only_symbol, TRADES.load(Ordering::Relaxed), exemplar(format, &trade, "1")));
+        family(&mut out, format, "trade_volume_total", "counter", "Base units traded across published trades; the exemplar is the latest trade.");
+        out.push_str(&format!("trade_volume_total{} {}{}\n", only_symbol,
+            micro_units(VOLUME_MICRO.load(Ordering::Relaxed)), exemplar(format, &trade, &micro_units(trade.qty_micro))));
+    }
+    let quality = BOOK_QUALITY.load(Ordering::Relaxed);
+    if quality != u64::MAX {
+        family(&mut out, format, "book_quality_score", "gauge", "Composite 0-100 book quality over the last stats window.");
+        out.push_str(&format!("book_quality_score{} {}\n", only_symbol, quality));
+    }
+    if BOOK_UPDATE_GAP.count() > 0 {
+        family(&mut out, format, "book_update_gap_seconds", "histogram", "Time between consecutive book updates, by receive time.");
+        BOOK_UPDATE_GAP.render(&mut out, "book_update_gap_seconds", symbol_label.trim_start_matches(','));
+    }
+    if PUBLISH_LATENCY.iter().any(|h| h.count() > 0) {
+        family(&mut out, format, "publish_latency_seconds", "histogram", "Time from receiving a frame to its completed mmap publish, per feed.");
+        for feed in Feed::ALL {
+            PUBLISH_LATENCY[feed as usize].render(&mut out, "publish_latency_seconds", &format!("feed=\"{}\"{}", feed.as_str(), symbol_label));
+        }
+    }
+    if format == Format::OpenMetrics {
+        out.push_str("# EOF\n");
+    }
+    out
+}
+
+/// A micro-unit amount as a decimal number of units.
+fn micro_units(micro: u64) -> String {
+    format!("{}.{:06}", micro / 1_000_000, micro % 1_000_000)
+}
+
+/// Serves `GET /metrics` with `render(&instance, ..)` and `GET /ready` on `addr` from a
+/// background thread. `/ready` is 200 once every symbol passed to `expect_data` has
+/// delivered data, else 503 naming the missing ones. Returns the bound address, so
+/// `addr` may use port 0.
+pub fn serve(addr: &str, instance: Instance) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = respond(stream, &instance);
+        }
+    });
+    Ok(local)
+}
+
+fn respond(mut stream: TcpStream, instance: &Instance) -> io::Result<()> {
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let format = Format::negotiate(&request);
+    let (status, content_type, body) = match (request.starts_with("GET "), path) {
+        (true, "/metrics") => ("200 OK", format.content_type(), render(instance, format)),
+        (true, "/ready") => match missing_data() {
+            missing if missing.is_empty() => ("200 OK", Format::Text.content_type(), "ready\n".to_string()),
+            missing => ("503 Service Unavailable", Format::Text.content_type(), format!("not ready: no data yet for {}\n", missing.join(","))),
+        },
+        _ => ("404 Not Found", Format::Text.content_type(), String::new()),
+    };
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, content_type, body.len(), body)
+}
+
+/// Escapes a label value per the text exposition format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorCategory;
+
+    const INGEST: Instance = Instance { build_version: "1.2.3", symbols: "SOLUSD", symbol: Some("SOLUSD") };
+
+    fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn scrape(addr: SocketAddr) -> String {
+        get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+    }
+
+    fn sample(body: &str, series: &str) -> u64 {
+        body.lines().find_map(|l| l.strip_prefix(series)?.trim().parse().ok()).unwrap()
+    }
+
+    #[test]
+    fn parse_and_connect_failures_increment_distinct_counters() {
+        let addr = serve("127.0.0.1:0", INGEST).unwrap();
+        let parse = "errors_total{category=\"parse\",symbol=\"SOLUSD\"}";
+        let connect = "errors_total{category=\"connect\",symbol=\"SOLUSD\"}";
+        let before = scrape(addr);
+        assert!(before.starts_with("HTTP/1.1 200 OK"));
+
+        errors::record(ErrorCategory::Parse);
+        errors::record(ErrorCategory::Connect);
+        errors::record(ErrorCategory::Connect);
+        let after = scrape(addr);
+        assert_eq!(sample(&after, parse), sample(&before, parse) + 1);
+        assert_eq!(sample(&after, connect), sample(&before, connect) + 2);
+    }
+
+    #[test]
+    fn scrape_has_build_info_and_symbol_labels() {
+        let body = scrape(serve("127.0.0.1:0", INGEST).unwrap());
+        assert!(body.contains("\r\n\r\n# HELP build_info"));
+        assert!(body.lines().any(|l| l == "build_info{build_version=\"1.2.3\",symbols=\"SOLUSD\"} 1"));
+        let errors: Vec<&str> = body.lines().filter(|l| l.starts_with("errors_total{")).collect();
+        assert_eq!(errors.len(), ErrorCategory::ALL.len());
+        assert!(errors.iter().all(|l| l.contains(",symbol=\"SOLUSD\"}")));
+    }
+
+    #[test]
+    fn symbol_label_is_optional_and_other_paths_404() {
+        let consumer = Instance { build_version: "1.2.3", symbols: "*", symbol: None };
+        assert!(render(&consumer, Format::Text).contains("errors_total{category=\"tls\"} "));
+        let addr = serve("127.0.0.1:0", consumer).unwrap();
+        assert!(get(addr, "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+    }
+
+    fn bucket(body: &str, series: &str, le: &str) -> u64 {
+        sample(body, &format!("{}_bucket{{{},le=\"{}\"}}", "publish_latency_seconds", series, le))
+    }
+
+    #[test]
+    fn a_latency_sample_moves_its_bucket_and_those_above() {
+        let series = "feed=\"v1\",symbol=\"SOLUSD\"";
+        observe_publish_latency(Feed::V1, Duration::from_micros(1));
+        let before = render(&INGEST, Format::Text);
+        observe_publish_latency(Feed::V1, Duration::from_micros(3_000));
+        let after = render(&INGEST, Format::Text);
+        assert!(after.contains("# TYPE publish_latency_seconds histogram\n"));
+        // 3 ms lands in (2.048 ms, 4.096 ms]
+        assert_eq!(bucket(&after, series, "0.002048"), bucket(&before, series, "0.002048"));
+        assert_eq!(bucket(&after, series, "0.004096"), bucket(&before, series, "0.004096") + 1);
+        assert_eq!(bucket(&after, series, "+Inf"), bucket(&before, series, "+Inf") + 1);
+        assert_eq!(sample(&after, &format!("publish_latency_seconds_count{{{}}}", series)), bucket(&after, series, "+Inf"));
+        assert!(after.contains("publish_latency_seconds_bucket{feed=\"v2\",symbol=\"SOLUSD\",le=\"+Inf\"}"));
+    }
+
+    #[test]
+    fn ready_waits_for_every_subscribed_symbol() {
+        let addr = serve("127.0.0.1:0", INGEST).unwrap();
+        let ready = || get(addr, "GET /ready HTTP/1.1\r\n\r\n");
+        expect_data("AAAUSD");
+        expect_data("BBBUSD");
+        data_arrived("AAAUSD");
+        // BBBUSD was subscribed but never sent anything
+        let response = ready();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        assert!(response.ends_with("not ready: no data yet for BBBUSD\n"), "{}", response);
+        assert_eq!(missing_data(), ["BBBUSD"]);
+
+        data_arrived("BBBUSD");
+        assert!(ready().starts_with("HTTP/1.1 200 OK"));
+        // A resubscribe waits for data again
+        expect_data("AAAUSD");
+        assert!(ready().ends_with("no data yet for AAAUSD\n"));
+        data_arrived("AAAUSD");
+        assert!(ready().ends_with("\r\n\r\nready\n"));
+    }
+
+    #[test]
+    fn book_quality_is_a_gauge_once_scored() {
+        set_book_quality(87);
+        let body = render(&INGEST, Format::Text);
+        assert!(body.contains("# TYPE book_quality_score gauge\n"));
+        assert_eq!(sample(&body, "book_quality_score{symbol=\"SOLUSD\"}"), 87);
+        set_book_quality(12);
+        assert_eq!(sample(&render(&INGEST, Format::OpenMetrics), "book_quality_score{symbol=\"SOLUSD\"}"), 12);
+    }
+
+    #[test]
+    fn histogram_bounds_are_inclusive() {
+        let h = Histogram::new();
+        for us in [0, 1, 2, 3, 4, 5, 40_000_000] {
+            h.observe(Duration::from_micros(us));
+        }
+        let mut out = String::new();
+        h.render(&mut out, "h", "x=\"y\"");
+        let cumulative = |le: &str| sample(&out, &format!("h_bucket{{x=\"y\",le=\"{}\"}}", le));
+        assert_eq!(cumulative("0.000001"), 2, "0 and 1 us");
+        assert_eq!(cumulative("0.000002"), 3);
+        assert_eq!(cumulative("0.000004"), 5, "3 and 4 us");
+        assert_eq!(cumulative("0.000008"), 6);
+        assert_eq!(cumulative("33.554432"), 6, "40 s is past the last bound");
+        assert_eq!(cumulative("+Inf"), 7);
+        assert_eq!(sample(&out, "h_count{x=\"y\"}"), 7);
+        assert!(out.contains("h_sum{x=\"y\"} 40.000015\n"));
+
+        let mut unlabelled = String::new();
+        h.render(&mut unlabelled, "h", "");
+        assert!(unlabelled.contains("h_bucket{le=\"+Inf\"} 7\n") && unlabelled.contains("h_count 7\n"));
+    }
+
+    #[test]
+    fn openmetrics_scrapes_carry_the_latest_trade_as_exemplar() {
+        record_trade(Some(987654), 1_700_000_000_123, 2_500_000);
+        let addr = serve("127.0.0.1:0", INGEST).unwrap();
+        let open = get(addr, "GET /metrics HTTP/1.1\r\nAccept: application/openmetrics-text; version=1.0.0\r\n\r\n");
+        assert!(open.contains("Content-Type: application/openmetrics-text; version=1.0.0"));
+        assert!(open.contains("# TYPE trades counter\n"));
+        assert!(open.ends_with("# EOF\n"));
+        let trades = open.lines().find(|l| l.starts_with("trades_total{symbol=\"SOLUSD\"} ")).unwrap();
+        assert!(trades.ends_with(" # {trade_id=\"987654\"} 1 1700000000.123"), "{}", trades);
+        let volume = open.lines().find(|l| l.starts_with("trade_volume_total{symbol=\"SOLUSD\"} ")).unwrap();
+        assert!(volume.ends_with(" # {trade_id=\"987654\"} 2.500000 1700000000.123"), "{}", volume);
+
+        // Plain text has no exemplar syntax
+        let text = scrape(addr);
+        assert!(text.contains("# TYPE trades_total counter\n"));
+        assert!(text.lines().filter(|l| l.starts_with("trade")).all(|l| !l.contains(" # ")));
+        assert!(!text.contains("# EOF"));
+    }
+}
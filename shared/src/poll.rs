@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+/// Idle strategy for loops polling a mapped file for changes. Each `idle()` call
+/// without an intervening `reset()` escalates: spin, then yield the thread, then
+/// sleep with a doubling delay capped at `max_sleep`. A zero `max_sleep` never leaves
+/// the spin phase (lowest latency, one core pegged).
+pub struct PollBackoff {
+    max_sleep: Duration,
+    idle_rounds: u32,
+    sleep: Duration,
+}
+
+const SPIN_ROUNDS: u32 = 64;
+const YIELD_ROUNDS: u32 = 64;
+const MIN_SLEEP: Duration = Duration::from_micros(50);
+
+impl PollBackoff {
+    pub fn new(max_sleep: Duration) -> Self {
+        Self { max_sleep, idle_rounds: 0, sleep: MIN_SLEEP }
+    }
+
+    /// Waits one step after a poll that saw no change.
+    pub fn idle(&mut self) {
+        if self.max_sleep.is_zero() || self.idle_rounds < SPIN_ROUNDS {
+            std::hint::spin_loop();
+        } else if self.idle_rounds < SPIN_ROUNDS + YIELD_ROUNDS {
+            std::thread::yield_now();
+        } else {
+            std::thread::sleep(self.sleep.min(self.max_sleep));
+            self.sleep = (self.sleep * 2).min(self.max_sleep);
+        }
+        self.idle_rounds = self.idle_rounds.saturating_add(1);
+    }
+
+    /// Call after a poll that saw a change, so the next wait starts back at spinning.
+    pub fn reset(&mut self) {
+        self.idle_rounds = 0;
+        self.sleep = MIN_SLEEP;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn idle_backoff_sleeps_up_to_the_max_and_reset_tightens_it() {
+        let max = Duration::from_millis(2);
+        let mut backoff = PollBackoff::new(max);
+        for _ in 0..SPIN_ROUNDS + YIELD_ROUNDS {
+            backoff.idle();
+        }
+        assert_eq!(backoff.sleep, MIN_SLEEP, "no sleeping while spinning and yielding");
+
+        // 50us doubling: 100, 200, 400, 800, 1600us, then held at the 2ms cap
+        let mut sleeps = Vec::new();
+        for _ in 0..7 {
+            backoff.idle();
+            sleeps.push(backoff.sleep.as_micros());
+        }
+        assert_eq!(sleeps, vec![100, 200, 400, 800, 1_600, 2_000, 2_000]);
+        let start = Instant::now();
+        backoff.idle();
+        assert!(start.elapsed() >= max, "a fully backed-off poll sleeps the max");
+
+        backoff.reset();
+        assert_eq!((backoff.idle_rounds, backoff.sleep), (0, MIN_SLEEP));
+        backoff.idle();
+        assert_eq!((backoff.idle_rounds, backoff.sleep), (1, MIN_SLEEP), "back to spinning");
+    }
+
+    #[test]
+    fn zero_max_never_sleeps() {
+        let mut backoff = PollBackoff::new(Duration::ZERO);
+        for _ in 0..2 * (SPIN_ROUNDS + YIELD_ROUNDS) {
+            backoff.idle();
+        }
+        assert_eq!(backoff.sleep, MIN_SLEEP);
+    }
+}
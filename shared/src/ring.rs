@@ -0,0 +1,195 @@
+use crate::OrderBook;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::mem::size_of;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// Identifies a snapshot ring file ("OBRING02").
+const MAGIC: u64 = u64::from_le_bytes(*b"OBRING02");
+/// Header: magic, slot size, capacity, snapshots written so far.
+const HEADER_LEN: u64 = 4 * 8;
+/// Slot: snapshot number, checksum of the book bytes, then the book.
+const SLOT_HEADER_LEN: u64 = 2 * 8;
+const BOOK_LEN: usize = size_of::<OrderBook>();
+const SLOT_LEN: u64 = SLOT_HEADER_LEN + BOOK_LEN as u64;
+
+/// Fixed-capacity ring of full order book copies in an ordinary file, for crash
+/// forensics and lookback independent of the mmap and the database. Snapshot `n`
+/// goes to slot `n % capacity`, overwriting the oldest once full. The write count in
+/// the header is updated after the slot. Each slot also records its snapshot number
+/// and a checksum, so a slot torn by a crash mid-write (which can only be the oldest
+/// retained one) is skipped by `load` rather than returned. Slots hold raw `OrderBook`
+/// bytes, so the file only loads in builds with the same layout (`padded-levels` included).
+pub struct SnapshotRing {
+    file: File,
+    capacity: u64,
+    written: u64,
+}
+
+impl SnapshotRing {
+    /// Opens `path`, creating it with `capacity` slots if missing or empty. An existing
+    /// ring keeps its contents and capacity; one with a different capacity or layout
+    /// is refused rather than overwritten.
+    pub fn open(path: &Path, capacity: usize) -> Result<Self> {
+        if capacity == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "snapshot ring capacity must be at least 1"));
+        }
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let capacity = capacity as u64;
+        if file.metadata()?.len() == 0 {
+            file.set_len(HEADER_LEN + capacity * SLOT_LEN)?;
+            write_header(&file, capacity, 0)?;
+            return Ok(Self { file, capacity, written: 0 });
+        }
+        let (existing, written) = read_header(&file, path)?;
+        if existing != capacity {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} holds {} snapshots, not {} (remove it to recreate)", path.display(), existing, capacity),
+            ));
+        }
+        Ok(Self { file, capacity, written })
+    }
+
+    /// Appends a copy of `book`, overwriting the oldest snapshot once the ring is full.
+    /// Pass an owned `snapshot()` so the copy is consistent.
+    pub fn push(&mut self, book: &OrderBook) -> Result<()> {
+        let slot = self.written % self.capacity;
+        let bytes = unsafe { std::slice::from_raw_parts(book as *const OrderBook as *const u8, BOOK_LEN) };
+        let mut buf = Vec::with_capacity(SLOT_LEN as usize);
+        buf.extend_from_slice(&self.written.to_le_bytes());
+        buf.extend_from_slice(&checksum(bytes).to_le_bytes());
+        buf.extend_from_slice(bytes);
+        self.file.write_all_at(&buf, HEADER_LEN + slot * SLOT_LEN)?;
+        self.written += 1;
+        write_header(&self.file, self.capacity, self.written)
+    }
+
+    /// Snapshots written over the ring's lifetime, including overwritten ones.
+    pub fn written(&self) -> u64 { self.written }
+}
+
+/// Reads the retained snapshots of the ring at `path`, oldest first (so the newest is
+/// last). At most the ring's capacity are returned; a slot whose number or checksum
+/// doesn't match (torn by a crash mid-write) is left out.
+pub fn load(path: &Path) -> Result<Vec<OrderBook>> {
+    let file = File::open(path)?;
+    let (capacity, written) = read_header(&file, path)?;
+    let first = written.saturating_sub(capacity);
+    let mut books = Vec::new();
+    let mut slot = vec![0u8; SLOT_LEN as usize];
+    for n in first..written {
+        file.read_exact_at(&mut slot, HEADER_LEN + (n % capacity) * SLOT_LEN)?;
+        let (header, bytes) = slot.split_at(SLOT_HEADER_LEN as usize);
+        let word = |i: usize| u64::from_le_bytes(header[i * 8..i * 8 + 8].try_into().expect("8-byte chunk"));
+        if word(0) != n || word(1) != checksum(bytes) {
+            continue;
+        }
+        let mut book = OrderBook::default();
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut book as *mut OrderBook as *mut u8, BOOK_LEN) };
+        books.push(book);
+    }
+    Ok(books)
+}
+
+/// FNV-1a, enough to tell a torn slot from a complete one.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn write_header(file: &File, capacity: u64, written: u64) -> Result<()> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    for (chunk, v) in header.chunks_exact_mut(8).zip([MAGIC, SLOT_LEN, capacity, written]) {
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+    file.write_all_at(&header, 0)
+}
+
+/// `(capacity, written)` from a ring's header, checking it was written with this layout.
+fn read_header(file: &File, path: &Path) -> Result<(u64, u64)> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    file.read_exact_at(&mut header, 0)?;
+    let word = |i: usize| u64::from_le_bytes(header[i * 8..i * 8 + 8].try_into().expect("8-byte chunk"));
+    if word(0) != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is not a snapshot ring", path.display())));
+    }
+    if word(1) != SLOT_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} holds {}-byte snapshots, expected {} (written by a different layout?)", path.display(), word(1), SLOT_LEN),
+        ));
+    }
+    let capacity = word(2);
+    if capacity == 0 || file.metadata()?.len() < HEADER_LEN + capacity * SLOT_LEN {
+        return Err(Error::new(ErrorKind::UnexpectedEof, format!("{} is truncated", path.display())));
+    }
+    Ok((capacity, word(3)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrderLevel;
+    use std::path::PathBuf;
+
+    /// Fresh ring file path, removed when dropped.
+    struct TempRing(PathBuf);
+
+    impl TempRing {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("ring-test-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempRing {
+        fn drop(&mut self) { let _ = std::fs::remove_file(&self.0); }
+    }
+
+    fn book(ts: u64) -> OrderBook {
+        let mut book = OrderBook::default();
+        book.publish(&[OrderLevel { price: 100 + ts, qty: 1 }], &[OrderLevel { price: 200 + ts, qty: 1 }], ts);
+        book
+    }
+
+    fn timestamps(path: &Path) -> Vec<u64> {
+        load(path).unwrap().iter().map(|b| b.timestamp_ms).collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_capacity_snapshots_oldest_first() {
+        let tmp = TempRing::new("wrap");
+        let mut ring = SnapshotRing::open(&tmp.0, 3).unwrap();
+        assert!(timestamps(&tmp.0).is_empty());
+        for ts in 1..=5 {
+            ring.push(&book(ts)).unwrap();
+        }
+        assert_eq!(ring.written(), 5);
+        assert_eq!(timestamps(&tmp.0), [3, 4, 5]);
+        assert_eq!(load(&tmp.0).unwrap()[2].bids[0].load_price(), 105);
+
+        // Reopening resumes after the last snapshot
+        drop(ring);
+        let mut ring = SnapshotRing::open(&tmp.0, 3).unwrap();
+        ring.push(&book(6)).unwrap();
+        assert_eq!(timestamps(&tmp.0), [4, 5, 6]);
+        assert!(SnapshotRing::open(&tmp.0, 4).is_err(), "a different capacity is refused");
+    }
+
+    #[test]
+    fn skips_a_torn_slot() {
+        let tmp = TempRing::new("torn");
+        let mut ring = SnapshotRing::open(&tmp.0, 3).unwrap();
+        for ts in 1..=4 {
+            ring.push(&book(ts)).unwrap();
+        }
+        // A crash while writing the fifth snapshot over the oldest retained one (slot 1)
+        ring.file.write_all_at(&[0xff; 8], HEADER_LEN + SLOT_LEN + SLOT_HEADER_LEN + 8).unwrap();
+        assert_eq!(timestamps(&tmp.0), [3, 4]);
+        // Only the new snapshot number made it to disk before the crash
+        ring.file.write_all_at(&4u64.to_le_bytes(), HEADER_LEN + SLOT_LEN).unwrap();
+        assert_eq!(timestamps(&tmp.0), [3, 4]);
+    }
+}